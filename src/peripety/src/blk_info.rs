@@ -1,17 +1,33 @@
+use super::devfs::Devfs;
 use super::dm;
 use super::error::PeripetyError;
+use super::loop_dev;
+use super::md::{self, MdMember};
+use super::mount::{self, MountInfo};
+use super::nvme;
+use super::partition;
 use super::scsi;
+use super::sys_root::SystemRoot;
 use super::sysfs::Sysfs;
+use super::virtio;
+use super::xen;
+use super::zram;
 
-use libc;
 use regex::Regex;
 use serde_json;
-use std::ffi::CStr;
-use std::ffi::CString;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fmt;
 use std::fs;
+use std::os::unix::fs::FileTypeExt;
 use std::path::Path;
 
+// Convert st_dev or st_rdev to 'major:minor' string.
+pub(crate) fn major_minor_of_dev_t(dev: u64) -> String {
+    let major = ((dev >> 8) & 0xfff) | ((dev >> 32) & !0xfff);
+    let minor = (dev & 0xff) | ((dev >> 12) & !0xff);
+    format!("{}:{}", major, minor)
+}
+
 #[derive(Clone, PartialEq, Debug, Serialize)]
 pub enum BlkType {
     Scsi,
@@ -19,6 +35,12 @@ pub enum BlkType {
     DmMultipath,
     DmLvm,
     Partition,
+    Nvme,
+    MdRaid,
+    Virtio,
+    Xen,
+    Loop,
+    Zram,
 }
 
 impl fmt::Display for BlkType {
@@ -29,6 +51,43 @@ impl fmt::Display for BlkType {
             BlkType::DmMultipath => write!(fmt, "Device Mapper Multipath"),
             BlkType::DmLvm => write!(fmt, "Device Mapper LVM"),
             BlkType::Partition => write!(fmt, "Partition"),
+            BlkType::Nvme => write!(fmt, "NVMe"),
+            BlkType::MdRaid => write!(fmt, "MD RAID"),
+            BlkType::Virtio => write!(fmt, "VirtIO"),
+            BlkType::Xen => write!(fmt, "Xen"),
+            BlkType::Loop => write!(fmt, "Loop"),
+            BlkType::Zram => write!(fmt, "zram"),
+        }
+    }
+}
+
+// Node of block topology tree. The `owners` are the blocks current block is
+// depending on, each of them holds its own `owners` down to the disks.
+#[derive(Debug, Serialize, Clone)]
+pub struct BlkNode {
+    pub wwid: String,
+    pub blk_type: BlkType,
+    pub blk_path: String,
+    pub owners: Vec<BlkNode>,
+}
+
+impl From<BlkInfo> for BlkNode {
+    fn from(blk_info: BlkInfo) -> BlkNode {
+        BlkNode {
+            wwid: blk_info.wwid,
+            blk_type: blk_info.blk_type,
+            blk_path: blk_info.blk_path,
+            owners: blk_info.owners,
+        }
+    }
+}
+
+impl BlkNode {
+    // Depth-first walk of owners tree, current node is not included.
+    fn walk_owners<'a>(&'a self, nodes: &mut Vec<&'a BlkNode>) {
+        for owner in &self.owners {
+            nodes.push(owner);
+            owner.walk_owners(nodes);
         }
     }
 }
@@ -38,39 +97,128 @@ pub struct BlkInfo {
     pub wwid: String,
     pub blk_type: BlkType,
     pub blk_path: String,
-    pub owners_wwids: Vec<String>,
-    pub owners_types: Vec<BlkType>,
-    pub owners_paths: Vec<String>,
+    pub owners: Vec<BlkNode>,
     pub uuid: Option<String>,
+    // The first entry of `mounts`, kept for compatibility.
     pub mount_point: Option<String>,
+    pub mounts: Vec<MountInfo>,
+    // Other paths of this block: udev symbolic links and LVM '/dev/vg/lv'.
+    pub aliases: Vec<String>,
+    pub extension: HashMap<String, String>,
+    pub md_members: Vec<MdMember>,
 }
 
 impl BlkInfo {
-    pub fn new(blk: &str) -> Result<BlkInfo, PeripetyError> {
-        let mut bi = BlkInfo::_new(blk, false)?;
-        if let Ok(uuid) = BlkInfo::uuid(&bi.blk_path) {
+    pub fn new(
+        blk: &str,
+        sys_root: &SystemRoot,
+    ) -> Result<BlkInfo, PeripetyError> {
+        let mut bi = BlkInfo::_new(blk, sys_root, false)?;
+        bi.fill_extra(sys_root);
+        Ok(bi)
+    }
+
+    fn all_owners(&self) -> Vec<&BlkNode> {
+        let mut nodes = Vec::new();
+        for owner in &self.owners {
+            nodes.push(owner);
+            owner.walk_owners(&mut nodes);
+        }
+        nodes
+    }
+
+    // Flattened wwids of `owners` tree without duplication, upper layer
+    // first.
+    pub fn owners_wwids(&self) -> Vec<String> {
+        let mut ret: Vec<String> = Vec::new();
+        for node in self.all_owners() {
+            if !ret.contains(&node.wwid) {
+                ret.push(node.wwid.clone());
+            }
+        }
+        ret
+    }
+
+    // Block types of `owners_wwids()` in the same order.
+    pub fn owners_types(&self) -> Vec<BlkType> {
+        let mut wwids: Vec<&str> = Vec::new();
+        let mut ret = Vec::new();
+        for node in self.all_owners() {
+            if !wwids.contains(&node.wwid.as_str()) {
+                wwids.push(&node.wwid);
+                ret.push(node.blk_type.clone());
+            }
+        }
+        ret
+    }
+
+    // Flattened paths of `owners` tree without duplication, upper layer
+    // first.
+    pub fn owners_paths(&self) -> Vec<String> {
+        let mut ret: Vec<String> = Vec::new();
+        for node in self.all_owners() {
+            if !ret.contains(&node.blk_path) {
+                ret.push(node.blk_path.clone());
+            }
+        }
+        ret
+    }
+
+    fn fill_extra(&mut self, sys_root: &SystemRoot) {
+        self.aliases = Devfs::all_links(&self.blk_path, sys_root);
+        if self.blk_type == BlkType::DmLvm {
+            let dm_name = self.blk_path.trim_start_matches("/dev/mapper/");
+            if let Some((vg, lv)) = dm::lvm_names(dm_name) {
+                let path = format!("/dev/{}/{}", vg, lv);
+                if Path::new(&sys_root.real_path(&path)).exists() {
+                    self.aliases.push(path);
+                }
+            }
+        }
+        if let Ok(uuid) = BlkInfo::uuid(&self.blk_path, sys_root) {
             // Only search mount table when block has uuid.
-            bi.uuid = Some(uuid);
-            bi.mount_point = BlkInfo::get_mount_point(&bi.blk_path);
+            self.uuid = Some(uuid);
+            self.mounts =
+                BlkInfo::get_mounts(&self.blk_path, sys_root, false);
+            self.mount_point =
+                self.mounts.first().map(|m| m.mount_point.clone());
         }
-        Ok(bi)
     }
 
-    pub fn new_skip_extra(blk: &str) -> Result<BlkInfo, PeripetyError> {
-        return BlkInfo::_new(blk, true);
+    pub fn new_skip_extra(
+        blk: &str,
+        sys_root: &SystemRoot,
+    ) -> Result<BlkInfo, PeripetyError> {
+        BlkInfo::_new(blk, sys_root, true)
     }
 
     fn _new(
         blk: &str,
+        sys_root: &SystemRoot,
         skip_holder_check: bool,
     ) -> Result<BlkInfo, PeripetyError> {
-        // device symbolic link or full path.
-        if blk.starts_with("/") {
-            if Path::new(blk).exists() {
-                if let Ok(p) = Path::new(blk).canonicalize() {
+        // device symbolic link, full path, or any file or folder path.
+        if blk.starts_with('/') {
+            let real_path = sys_root.real_path(blk);
+            if Path::new(&real_path).exists() {
+                if let Ok(p) = Path::new(&real_path).canonicalize() {
+                    if !BlkInfo::is_blk_path(&p, sys_root) {
+                        let major_minor = mount::major_minor_of_path(
+                            &real_path, sys_root,
+                        )?;
+                        return BlkInfo::_new(
+                            &major_minor,
+                            sys_root,
+                            skip_holder_check,
+                        );
+                    }
                     if let Some(s) = p.file_name() {
                         if let Some(s) = s.to_str() {
-                            return BlkInfo::_new(s, skip_holder_check);
+                            return BlkInfo::_new(
+                                s,
+                                sys_root,
+                                skip_holder_check,
+                            );
                         }
                     }
                 }
@@ -86,38 +234,108 @@ impl BlkInfo {
             }
         }
 
+        // sda1, nvme0n1p1, md0p1, etc
+        if partition::is_partition(blk, sys_root) {
+            if !skip_holder_check {
+                if let Some(h) = BlkInfo::get_holder_blk_info(blk, sys_root) {
+                    return h;
+                }
+            }
+            return partition::blk_info_get_partition(blk, sys_root);
+        }
+
         // sda
         if blk.starts_with("sd") {
-            // If certain disk is used device-mapper (like multipath or LVM),
-            // return block information for that mpath instead
+            // If certain disk is used device-mapper (like multipath or LVM)
+            // or MD RAID, return block information for that holder instead
+            if !skip_holder_check {
+                if let Some(h) = BlkInfo::get_holder_blk_info(blk, sys_root) {
+                    return h;
+                }
+            }
+            return scsi::blk_info_get_scsi(blk, sys_root);
+        }
+
+        // nvme0n1 or nvme0c1n1
+        if nvme::is_nvme_blk(blk) {
             if !skip_holder_check {
-                if let Some(d) = dm::get_holder_dm_name(blk) {
-                    return dm::blk_info_get_dm(&d);
+                if let Some(h) = BlkInfo::get_holder_blk_info(blk, sys_root) {
+                    return h;
                 }
             }
-            return scsi::blk_info_get_scsi(blk);
+            return nvme::blk_info_get_nvme(blk, sys_root);
+        }
+
+        // vda, xvda or loop0
+        if virtio::is_virtio_blk(blk)
+            || xen::is_xen_blk(blk)
+            || loop_dev::is_loop_blk(blk)
+        {
+            if !skip_holder_check {
+                if let Some(h) = BlkInfo::get_holder_blk_info(blk, sys_root) {
+                    return h;
+                }
+            }
+            if virtio::is_virtio_blk(blk) {
+                return virtio::blk_info_get_virtio(blk, sys_root);
+            }
+            if xen::is_xen_blk(blk) {
+                return xen::blk_info_get_xen(blk, sys_root);
+            }
+            return loop_dev::blk_info_get_loop(blk, sys_root);
+        }
+
+        // zram0
+        if zram::is_zram_blk(blk) {
+            return zram::blk_info_get_zram(blk, sys_root);
+        }
+
+        // md0
+        if md::is_md_blk(blk) {
+            return md::blk_info_get_md(blk, sys_root);
+        }
+
+        // nvme controller name and namespace id: nvme0:1
+        if let Ok(reg) = Regex::new(r"^(nvme[0-9]+):([0-9]+)$") {
+            if let Some(cap) = reg.captures(blk) {
+                return BlkInfo::_new(
+                    &nvme::ctrl_ns_id_to_blk_name(
+                        &cap[1],
+                        &cap[2],
+                        sys_root,
+                    )?,
+                    sys_root,
+                    skip_holder_check,
+                );
+            }
         }
 
         // scsi_id: 4:0:1:1
         if let Ok(reg) = Regex::new(r"^(?:[0-9]+:){3}[0-9]+$") {
             if reg.is_match(blk) {
                 return BlkInfo::_new(
-                    &scsi::scsi_id_to_blk_name(blk)?,
+                    &scsi::scsi_id_to_blk_name(blk, sys_root)?,
+                    sys_root,
                     skip_holder_check,
                 );
             }
         }
 
         // dm-0
-        if blk.starts_with("dm-") {
-            return dm::blk_info_get_dm(blk);
+        if Regex::new(r"^dm-[0-9]+$")
+            .expect("BUG: BlkInfo::_new()")
+            // ^ We never panic as above regex is valid.
+            .is_match(blk)
+        {
+            return dm::blk_info_get_dm(blk, sys_root);
         }
 
         // major: minor
         if let Ok(reg) = Regex::new(r"^[0-9]+:[0-9]+$") {
             if reg.is_match(blk) {
                 return BlkInfo::_new(
-                    &BlkInfo::major_minor_to_blk_name(blk)?,
+                    &BlkInfo::major_minor_to_blk_name(blk, sys_root)?,
+                    sys_root,
                     skip_holder_check,
                 );
             }
@@ -125,12 +343,42 @@ impl BlkInfo {
 
         // uuid
         let uuid_dev_path = format!("/dev/disk/by-uuid/{}", blk);
-        if Path::new(&uuid_dev_path).exists() {
-            return BlkInfo::_new(&uuid_dev_path, skip_holder_check);
+        if Path::new(&sys_root.real_path(&uuid_dev_path)).exists() {
+            return BlkInfo::_new(&uuid_dev_path, sys_root, skip_holder_check);
+        }
+
+        // 'LABEL=data', 'UUID=<uuid>', 'PARTUUID=<uuid>' and
+        // 'PARTLABEL=<label>' like in fstab.
+        for (tag, dir) in &[
+            ("LABEL=", "disk/by-label"),
+            ("UUID=", "disk/by-uuid"),
+            ("PARTUUID=", "disk/by-partuuid"),
+            ("PARTLABEL=", "disk/by-partlabel"),
+        ] {
+            if let Some(value) = blk.strip_prefix(tag) {
+                let link =
+                    format!("/dev/{}/{}", dir, Devfs::udev_encode(value));
+                return BlkInfo::_new(&link, sys_root, skip_holder_check);
+            }
+        }
+
+        // Link name in '/dev/disk/by-id' or '/dev/disk/by-path'
+        for dir in &["disk/by-id", "disk/by-path"] {
+            let link = format!("/dev/{}/{}", dir, blk);
+            if !blk.contains('/')
+                && Path::new(&sys_root.real_path(&link)).exists()
+            {
+                return BlkInfo::_new(&link, sys_root, skip_holder_check);
+            }
+        }
+
+        // LVM 'vg/lv'
+        if let Some(path) = dm::lvm_blk_path(blk, sys_root) {
+            return BlkInfo::_new(&path, sys_root, skip_holder_check);
         }
 
         // scsi wwid
-        let sysfs_folder = "/sys/class/scsi_disk";
+        let sysfs_folder = sys_root.sysfs_path("class/scsi_disk");
         if let Ok(entries) = fs::read_dir(&sysfs_folder) {
             for entry in entries {
                 let e = match entry {
@@ -144,7 +392,11 @@ impl BlkInfo {
                         let c = Sysfs::read(p)?;
                         if blk == scsi::pretty_wwid(&c) {
                             if let Some(s) = e.file_name().to_str() {
-                                return BlkInfo::new(s);
+                                return BlkInfo::_new(
+                                    s,
+                                    sys_root,
+                                    skip_holder_check,
+                                );
                             }
                         }
                     }
@@ -158,8 +410,108 @@ impl BlkInfo {
         )))
     }
 
-    pub fn uuid(blk_path: &str) -> Result<String, PeripetyError> {
-        let path = Path::new(&blk_path);
+    // Find all blocks stacked on top of current block recursively:
+    // partitions, device-mapper and MD RAID holders. The uuid and mount
+    // point of each holder are also included. The lower layer is listed
+    // first.
+    pub fn holders(
+        &self,
+        sys_root: &SystemRoot,
+    ) -> Result<Vec<BlkInfo>, PeripetyError> {
+        let mut ret = Vec::new();
+        let mut checked = HashSet::new();
+        let mut pending = VecDeque::new();
+        pending.push_back(BlkInfo::blk_path_to_name(&self.blk_path, sys_root)?);
+
+        while let Some(cur) = pending.pop_front() {
+            for name in BlkInfo::upper_blk_names(&cur, sys_root) {
+                if checked.contains(&name) {
+                    continue;
+                }
+                checked.insert(name.clone());
+                if let Ok(mut bi) = BlkInfo::new_skip_extra(&name, sys_root) {
+                    bi.fill_extra(sys_root);
+                    ret.push(bi);
+                }
+                pending.push_back(name);
+            }
+        }
+        Ok(ret)
+    }
+
+    // Return kernel names of partitions and holders of specified block.
+    fn upper_blk_names(blk: &str, sys_root: &SystemRoot) -> Vec<String> {
+        let mut ret = Vec::new();
+        let blk_dir = sys_root.sysfs_path(&format!("class/block/{}", blk));
+        if let Ok(entries) = fs::read_dir(&blk_dir) {
+            for entry in entries.flatten() {
+                if !entry.path().join("partition").exists() {
+                    continue;
+                }
+                if let Ok(n) = entry.file_name().into_string() {
+                    ret.push(n);
+                }
+            }
+        }
+        if let Ok(entries) = fs::read_dir(format!("{}/holders", blk_dir)) {
+            for entry in entries.flatten() {
+                if let Ok(n) = entry.file_name().into_string() {
+                    ret.push(n);
+                }
+            }
+        }
+        ret.sort();
+        ret
+    }
+
+    fn blk_path_to_name(
+        blk_path: &str,
+        sys_root: &SystemRoot,
+    ) -> Result<String, PeripetyError> {
+        let real_path = sys_root.real_path(blk_path);
+        match Path::new(&real_path).canonicalize() {
+            Ok(p) => match p.file_name().and_then(|n| n.to_str()) {
+                Some(n) => Ok(n.to_string()),
+                None => Err(PeripetyError::InternalBug(format!(
+                    "blk_path_to_name(): Got non-utf8 path {:?}",
+                    p
+                ))),
+            },
+            Err(e) => Err(PeripetyError::BlockNoExists(format!(
+                "Block {} does not exists: {}",
+                blk_path, e
+            ))),
+        }
+    }
+
+    fn get_holder_blk_info(
+        blk: &str,
+        sys_root: &SystemRoot,
+    ) -> Option<Result<BlkInfo, PeripetyError>> {
+        // Partitions are not shown in /sys/block, hence use /sys/class/block.
+        let holders =
+            sys_root.sysfs_path(&format!("class/block/{}/holders", blk));
+        if let Ok(mut entries) = fs::read_dir(&holders) {
+            if let Some(Ok(holder)) = entries.next() {
+                if let Some(s) = holder.file_name().to_str() {
+                    if s.starts_with("dm-") {
+                        return Some(dm::blk_info_get_dm(s, sys_root));
+                    }
+                    if md::is_md_blk(s) {
+                        return Some(md::blk_info_get_md(s, sys_root));
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    pub fn uuid(
+        blk_path: &str,
+        sys_root: &SystemRoot,
+    ) -> Result<String, PeripetyError> {
+        let real_path = sys_root.real_path(blk_path);
+        let path = Path::new(&real_path);
         if !path.exists() {
             return Err(PeripetyError::BlockNoExists(format!(
                 "Block {} does not exists",
@@ -176,19 +528,20 @@ impl BlkInfo {
                 )));
             }
         };
-        let entries = match fs::read_dir("/dev/disk/by-uuid") {
+        let uuid_dir = sys_root.devfs_path("disk/by-uuid");
+        let entries = match fs::read_dir(&uuid_dir) {
             Ok(es) => es,
             Err(e) => {
                 return Err(PeripetyError::InternalBug(format!(
                     "Failed to read_dir {}: {}",
-                    "/dev/disk/by-uuid", e
+                    uuid_dir, e
                 )));
             }
         };
         for entry in entries {
             if let Ok(entry) = entry {
                 if let Ok(p) = fs::read_link(&entry.path()) {
-                    let link_path = Path::new("/dev/disk/by-uuid/").join(p);
+                    let link_path = Path::new(&uuid_dir).join(p);
                     if let Ok(cur_path) = link_path.canonicalize() {
                         if cur_path == blk_real_path {
                             if let Some(s) = entry.file_name().to_str() {
@@ -225,51 +578,71 @@ impl BlkInfo {
         }
     }
 
-    pub fn get_mount_point(blk_path: &str) -> Option<String> {
-        let mut ret = String::new();
-        let fd = unsafe {
-            libc::setmntent(
-                CStr::from_bytes_with_nul(b"/proc/mounts\0")
-                    .expect("BUG: get_mount_point()")
-                    // ^We never panic as it is null terminated.
-                    .as_ptr(),
-                CStr::from_bytes_with_nul(b"r\0")
-                    .expect("BUG")
-                    .as_ptr(),
-                // ^We never panic as it is null terminated.
-            )
-        };
-        if fd.is_null() {
-            return None;
-        }
-        let mut entry = unsafe { libc::getmntent(fd) };
-        while !entry.is_null() {
-            let table: libc::mntent = unsafe { *entry };
-            if let Ok(mnt_fsname) =
-                unsafe { CStr::from_ptr(table.mnt_fsname).to_str() }
-            {
-                if mnt_fsname == blk_path {
-                    if let Ok(s) = unsafe {
-                        CString::from_raw(table.mnt_dir).into_string()
-                    } {
-                        ret = s;
-                        break;
-                    }
+    pub fn get_mount_point(
+        blk_path: &str,
+        sys_root: &SystemRoot,
+    ) -> Option<String> {
+        BlkInfo::get_mounts(blk_path, sys_root, false)
+            .into_iter()
+            .next()
+            .map(|m| m.mount_point)
+    }
+
+    // All mounts of specified block including bind mounts and btrfs
+    // subvolumes. With `all_ns` set, mounts in other mount namespaces(like
+    // containers) are also included.
+    pub fn get_mounts(
+        blk_path: &str,
+        sys_root: &SystemRoot,
+        all_ns: bool,
+    ) -> Vec<MountInfo> {
+        mount::mounts_of_blk(blk_path, sys_root, all_ns)
+    }
+
+    // Mounts of specified block only in mount namespaces other than the one
+    // of current process, for extending the `mounts` of BlkInfo.
+    pub fn get_mounts_other_ns(
+        blk_path: &str,
+        sys_root: &SystemRoot,
+    ) -> Vec<MountInfo> {
+        mount::mounts_of_blk_other_ns(blk_path, sys_root)
+    }
+
+    // Whether canonicalized path is block device node or sysfs folder.
+    fn is_blk_path(path: &Path, sys_root: &SystemRoot) -> bool {
+        if let Ok(m) = fs::metadata(path) {
+            if m.file_type().is_block_device() {
+                return true;
+            }
+        }
+        // Captured devfs might use regular files for block nodes, trust them
+        // only if sysfs has the block also.
+        if *sys_root != SystemRoot::default() && !path.is_dir() {
+            let devfs = Path::new(&sys_root.devfs).canonicalize().ok();
+            let name = path.file_name().and_then(|n| n.to_str());
+            if let (Some(d), Some(n)) = (devfs, name) {
+                let sysfs_path =
+                    sys_root.sysfs_path(&format!("class/block/{}", n));
+                if path.parent() == Some(&d) && Path::new(&sysfs_path).exists()
+                {
+                    return true;
                 }
-                entry = unsafe { libc::getmntent(fd) };
             }
         }
-        unsafe { libc::endmntent(fd) };
-        if ret.len() == 0 {
-            return None;
+        if let Ok(d) = Path::new(&sys_root.sysfs).canonicalize() {
+            if path.starts_with(&d) {
+                return true;
+            }
         }
-        return Some(ret);
+        false
     }
 
     pub fn major_minor_to_blk_name(
         major_minor: &str,
+        sys_root: &SystemRoot,
     ) -> Result<String, PeripetyError> {
-        let sysfs_path = format!("/sys/dev/block/{}", major_minor);
+        let sysfs_path =
+            sys_root.sysfs_path(&format!("dev/block/{}", major_minor));
         match fs::read_link(&sysfs_path) {
             Ok(p) => {
                 if let Some(p) = p.file_name() {
@@ -293,3 +666,329 @@ impl BlkInfo {
         )))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fixture::Fixture;
+
+    // SCSI disk sda with partition sda1.
+    fn scsi_fixture(name: &str) -> Fixture {
+        let f = Fixture::new(name);
+        f.add_blk("sda", "8:0");
+        f.write("/sys/block/sda/device/wwid", "naa.6001405\n");
+        f.add_part("sda", "sda1", 1, "8:1");
+        f
+    }
+
+    #[test]
+    fn test_is_blk_path() {
+        let f = scsi_fixture("blk");
+        f.write("/dev/null", "");
+        let sys_root = &f.sys_root;
+
+        let sda = Path::new(&f.path("/dev/sda")).canonicalize();
+        assert!(BlkInfo::is_blk_path(&sda.unwrap(), sys_root));
+        let null = Path::new(&f.path("/dev/null")).canonicalize();
+        assert!(!BlkInfo::is_blk_path(&null.unwrap(), sys_root));
+
+        let blk_info = BlkInfo::new_skip_extra("/dev/sda", sys_root).unwrap();
+        assert_eq!(blk_info.wwid, "naa.6001405");
+        assert_eq!(blk_info.blk_type, BlkType::Scsi);
+        assert!(BlkInfo::new_skip_extra("/dev/null", sys_root).is_err());
+    }
+
+    #[test]
+    fn test_partition() {
+        let f = scsi_fixture("blk-part");
+        f.write("/sys/block/sda/sda1/start", "2048\n");
+        f.symlink("../../sda1", "/dev/disk/by-uuid/f00d");
+
+        let blk_info = BlkInfo::new("/dev/sda1", &f.sys_root).unwrap();
+        assert_eq!(blk_info.wwid, "naa.6001405-part1");
+        assert_eq!(blk_info.blk_type, BlkType::Partition);
+        assert_eq!(blk_info.blk_path, "/dev/sda1");
+        assert_eq!(blk_info.owners_wwids(), vec!["naa.6001405"]);
+        assert_eq!(blk_info.owners_paths(), vec!["/dev/sda"]);
+        assert_eq!(blk_info.uuid, Some("f00d".to_string()));
+        assert_eq!(
+            blk_info.extension.get("part_start"),
+            Some(&"2048".to_string())
+        );
+    }
+
+    #[test]
+    fn test_kpartx_partition() {
+        // Partition dm-1 created by kpartx on multipath dm-0.
+        let f = scsi_fixture("blk-kpartx");
+        f.add_blk("dm-0", "253:0");
+        f.write("/sys/block/dm-0/dm/name", "mpatha\n");
+        f.write("/sys/block/dm-0/dm/uuid", "mpath-36001405\n");
+        f.add_slave("dm-0", "sda");
+        f.add_blk("dm-1", "253:1");
+        f.write("/sys/block/dm-1/dm/name", "mpatha1\n");
+        f.write("/sys/block/dm-1/dm/uuid", "part1-mpath-36001405\n");
+        f.write("/sys/block/dm-1/size", "204800\n");
+        f.add_slave("dm-1", "dm-0");
+        f.symlink("../dm-1", "/dev/mapper/mpatha1");
+        f.symlink("../../dm-1", "/dev/disk/by-partuuid/f00d-01");
+
+        let blk_info = BlkInfo::new_skip_extra("dm-1", &f.sys_root).unwrap();
+        assert_eq!(blk_info.wwid, "mpath-36001405-part1");
+        assert_eq!(blk_info.blk_type, BlkType::Partition);
+        assert_eq!(blk_info.blk_path, "/dev/mapper/mpatha1");
+        let ext = &blk_info.extension;
+        assert_eq!(ext.get("part_number"), Some(&"1".to_string()));
+        assert_eq!(ext.get("part_size"), Some(&"204800".to_string()));
+        assert_eq!(ext.get("part_uuid"), Some(&"f00d-01".to_string()));
+        assert_eq!(ext.get("part_start"), None);
+    }
+
+    #[test]
+    fn test_holders() {
+        // LV dm-0 on sda1, mounted on /data.
+        let f = scsi_fixture("blk-holders");
+        f.add_blk("dm-0", "253:0");
+        f.write("/sys/block/dm-0/dm/name", "vg-lv\n");
+        f.write("/sys/block/dm-0/dm/uuid", "LVM-abc\n");
+        f.add_slave("dm-0", "sda1");
+        f.symlink("../dm-0", "/dev/mapper/vg-lv");
+        f.symlink("../../dm-0", "/dev/disk/by-uuid/f00d");
+        f.write(
+            "/proc/self/mountinfo",
+            "22 1 253:0 / /data rw - xfs /dev/mapper/vg-lv rw\n",
+        );
+        let sys_root = &f.sys_root;
+
+        let blk_info = BlkInfo::new_skip_extra("sda", sys_root).unwrap();
+        let holders = blk_info.holders(sys_root).unwrap();
+        let paths: Vec<&str> =
+            holders.iter().map(|h| h.blk_path.as_str()).collect();
+        assert_eq!(paths, vec!["/dev/sda1", "/dev/mapper/vg-lv"]);
+        assert_eq!(holders[1].blk_type, BlkType::DmLvm);
+        assert_eq!(holders[1].uuid, Some("f00d".to_string()));
+        assert_eq!(holders[1].mount_point, Some("/data".to_string()));
+
+        // Top of the stack has no holder.
+        assert!(holders[1].holders(sys_root).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_owners_tree() {
+        // LV dm-1 on multipath dm-0 with paths sda and sdb.
+        let f = Fixture::new("blk-tree");
+        for (blk, major_minor) in &[("sda", "8:0"), ("sdb", "8:16")] {
+            f.add_blk(blk, major_minor);
+            f.write(
+                &format!("/sys/block/{}/device/wwid", blk),
+                "naa.6001405\n",
+            );
+            f.add_slave("dm-0", blk);
+        }
+        f.add_blk("dm-0", "253:0");
+        f.write("/sys/block/dm-0/dm/name", "mpatha\n");
+        f.write("/sys/block/dm-0/dm/uuid", "mpath-36001405\n");
+        f.add_blk("dm-1", "253:1");
+        f.write("/sys/block/dm-1/dm/name", "vg-lv\n");
+        f.write("/sys/block/dm-1/dm/uuid", "LVM-abc\n");
+        f.add_slave("dm-1", "dm-0");
+
+        let blk_info = BlkInfo::new_skip_extra("dm-1", &f.sys_root).unwrap();
+        assert_eq!(blk_info.owners.len(), 1);
+        let mpath = &blk_info.owners[0];
+        assert_eq!(mpath.blk_type, BlkType::DmMultipath);
+        let paths: Vec<&str> =
+            mpath.owners.iter().map(|o| o.blk_path.as_str()).collect();
+        assert_eq!(paths, vec!["/dev/sda", "/dev/sdb"]);
+
+        // Flattened view of the tree, upper layer first.
+        assert_eq!(
+            blk_info.owners_wwids(),
+            vec!["mpath-36001405", "naa.6001405"]
+        );
+        assert_eq!(
+            blk_info.owners_types(),
+            vec![BlkType::DmMultipath, BlkType::Scsi]
+        );
+        assert_eq!(
+            blk_info.owners_paths(),
+            vec!["/dev/mapper/mpatha", "/dev/sda", "/dev/sdb"]
+        );
+
+        let json: serde_json::Value =
+            serde_json::from_str(&blk_info.to_json_string().unwrap())
+                .unwrap();
+        assert_eq!(
+            json["owners"][0]["owners"][1]["blk_path"],
+            serde_json::Value::from("/dev/sdb")
+        );
+    }
+
+    #[test]
+    fn test_nvme() {
+        let f = Fixture::new("blk-nvme");
+        let ctrl_dir = "/sys/devices/pci0000:00/0000:00:1d.0/nvme/nvme0";
+        f.write(&format!("{}/model", ctrl_dir), "Vendor SSD   \n");
+        f.write(&format!("{}/serial", ctrl_dir), "S3EVNX0J\n");
+        f.write(&format!("{}/nvme0n1/nsid", ctrl_dir), "1\n");
+        f.symlink(ctrl_dir, "/sys/class/nvme/nvme0");
+        f.add_blk("nvme0n1", "259:0");
+        f.write("/sys/block/nvme0n1/wwid", "eui.0025388b71b5f2c1\n");
+        f.write("/sys/block/nvme0n1/nsid", "1\n");
+        f.symlink(ctrl_dir, "/sys/block/nvme0n1/device");
+        f.add_part("nvme0n1", "nvme0n1p1", 1, "259:1");
+        // Old kernel without 'wwid' file.
+        f.add_blk("nvme1n1", "259:2");
+        f.write(
+            "/sys/block/nvme1n1/nguid",
+            "00000000-0000-0000-0000-000000000000\n",
+        );
+        f.write("/sys/block/nvme1n1/eui", "00 25 38 8b 71 b5 f2 c2\n");
+        let sys_root = &f.sys_root;
+
+        let blk_info = BlkInfo::new_skip_extra("nvme0n1", sys_root).unwrap();
+        assert_eq!(blk_info.wwid, "eui.0025388b71b5f2c1");
+        assert_eq!(blk_info.blk_type, BlkType::Nvme);
+        assert_eq!(blk_info.blk_path, "/dev/nvme0n1");
+        assert_eq!(
+            blk_info.extension.get("nvme_ctrl"),
+            Some(&"nvme0".to_string())
+        );
+        assert_eq!(
+            blk_info.extension.get("model"),
+            Some(&"Vendor SSD".to_string())
+        );
+        assert_eq!(
+            blk_info.extension.get("nvme_ns_id"),
+            Some(&"1".to_string())
+        );
+
+        // Controller name and namespace ID, and hidden namespace of native
+        // NVMe multipath.
+        for name in &["nvme0:1", "nvme0c1n1", "/dev/nvme0n1"] {
+            let bi = BlkInfo::new_skip_extra(name, sys_root).unwrap();
+            assert_eq!(bi.blk_path, "/dev/nvme0n1");
+        }
+        assert!(BlkInfo::new_skip_extra("nvme0:2", sys_root).is_err());
+
+        let blk_info = BlkInfo::new_skip_extra("nvme0n1p1", sys_root).unwrap();
+        assert_eq!(blk_info.wwid, "eui.0025388b71b5f2c1-part1");
+        assert_eq!(blk_info.blk_type, BlkType::Partition);
+        assert_eq!(blk_info.owners_paths(), vec!["/dev/nvme0n1"]);
+
+        let blk_info = BlkInfo::new_skip_extra("nvme1n1", sys_root).unwrap();
+        assert_eq!(blk_info.wwid, "eui.0025388b71b5f2c2");
+    }
+
+    #[test]
+    fn test_virtual_blks() {
+        let f = scsi_fixture("blk-virtual");
+        let pci_dir = "/sys/devices/pci0000:00";
+        f.add_blk("vda", "252:0");
+        f.write("/sys/block/vda/serial", "disk-01\n");
+        f.mkdir(&format!("{}/0000:00:04.0/virtio1", pci_dir));
+        f.symlink(
+            &format!("{}/0000:00:04.0/virtio1", pci_dir),
+            "/sys/block/vda/device",
+        );
+        // No serial defined by hypervisor.
+        f.add_blk("vdb", "252:16");
+        f.mkdir(&format!("{}/0000:00:05.0/virtio2", pci_dir));
+        f.symlink(
+            &format!("{}/0000:00:05.0/virtio2", pci_dir),
+            "/sys/block/vdb/device",
+        );
+        f.add_blk("xvda", "202:0");
+        f.write("/sys/devices/vbd-51712/nodename", "device/vbd/51712\n");
+        f.symlink("/sys/devices/vbd-51712", "/sys/block/xvda/device");
+        f.add_blk("loop0", "7:0");
+        f.write("/sys/block/loop0/loop/backing_file", "/var/lib/a.img\n");
+        f.write("/sys/block/loop0/loop/offset", "0\n");
+        f.write("/proc/self/mountinfo", "22 1 8:0 / / rw - xfs /dev/sda rw\n");
+        // Not attached.
+        f.add_blk("loop1", "7:1");
+        f.add_blk("zram0", "251:0");
+        f.write("/sys/block/zram0/comp_algorithm", "lzo [lz4] zstd\n");
+        f.write("/sys/block/zram0/disksize", "1073741824\n");
+        let sys_root = &f.sys_root;
+
+        let blk_info = BlkInfo::new_skip_extra("vda", sys_root).unwrap();
+        assert_eq!(blk_info.wwid, "virtio-disk-01");
+        assert_eq!(blk_info.blk_type, BlkType::Virtio);
+        assert_eq!(
+            blk_info.extension.get("virtio_parent"),
+            Some(&"0000:00:04.0".to_string())
+        );
+        let blk_info = BlkInfo::new_skip_extra("vdb", sys_root).unwrap();
+        assert_eq!(blk_info.wwid, "virtio-path-0000:00:05.0");
+
+        let blk_info = BlkInfo::new_skip_extra("xvda", sys_root).unwrap();
+        assert_eq!(blk_info.wwid, "xen-vbd-51712");
+        assert_eq!(blk_info.blk_type, BlkType::Xen);
+        assert_eq!(
+            blk_info.extension.get("xen_nodename"),
+            Some(&"device/vbd/51712".to_string())
+        );
+
+        // Backing file is not in captured tree, but its block is found by
+        // mount table.
+        let blk_info = BlkInfo::new_skip_extra("loop0", sys_root).unwrap();
+        assert_eq!(blk_info.wwid, "loop-file-/var/lib/a.img");
+        assert_eq!(blk_info.blk_type, BlkType::Loop);
+        assert_eq!(blk_info.owners_paths(), vec!["/dev/sda"]);
+        assert!(BlkInfo::new_skip_extra("loop1", sys_root).is_err());
+
+        let blk_info = BlkInfo::new_skip_extra("zram0", sys_root).unwrap();
+        assert_eq!(blk_info.wwid, "zram-zram0");
+        assert_eq!(blk_info.blk_type, BlkType::Zram);
+        assert_eq!(
+            blk_info.extension.get("zram_comp_algorithm"),
+            Some(&"lz4".to_string())
+        );
+    }
+
+    #[test]
+    fn test_aliases() {
+        // LV 'lv' of VG 'vg-a' on sda, with label 'data'.
+        let f = scsi_fixture("blk-aliases");
+        f.add_blk("dm-0", "253:0");
+        f.write("/sys/block/dm-0/dm/name", "vg--a-lv\n");
+        f.write("/sys/block/dm-0/dm/uuid", "LVM-abc\n");
+        f.add_slave("dm-0", "sda");
+        f.symlink("../dm-0", "/dev/mapper/vg--a-lv");
+        f.symlink("../dm-0", "/dev/vg-a/lv");
+        f.symlink("../../dm-0", "/dev/disk/by-id/dm-name-vg--a-lv");
+        f.symlink("../../dm-0", "/dev/disk/by-label/data");
+        let sys_root = &f.sys_root;
+
+        assert_eq!(
+            dm::lvm_names("vg--a-lv"),
+            Some(("vg-a".to_string(), "lv".to_string()))
+        );
+        for name in &["vg-a/lv", "LABEL=data", "dm-name-vg--a-lv", "dm-0"] {
+            let blk_info = BlkInfo::new(name, sys_root).unwrap();
+            assert_eq!(blk_info.blk_path, "/dev/mapper/vg--a-lv");
+            assert_eq!(blk_info.blk_type, BlkType::DmLvm);
+        }
+
+        let blk_info = BlkInfo::new("dm-0", sys_root).unwrap();
+        assert_eq!(
+            blk_info.aliases,
+            vec![
+                "/dev/disk/by-id/dm-name-vg--a-lv",
+                "/dev/disk/by-label/data",
+                "/dev/vg-a/lv",
+            ]
+        );
+        assert!(BlkInfo::new("vg-a/lv2", sys_root).is_err());
+        assert!(BlkInfo::new("LABEL=data2", sys_root).is_err());
+    }
+
+    #[test]
+    fn test_is_blk_path_live() {
+        let sys_root = SystemRoot::default();
+        if let Ok(p) = Path::new("/dev/null").canonicalize() {
+            assert!(!BlkInfo::is_blk_path(&p, &sys_root));
+        }
+    }
+}
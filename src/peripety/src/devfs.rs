@@ -0,0 +1,80 @@
+use super::sys_root::SystemRoot;
+
+use std::fs;
+use std::path::Path;
+
+pub(crate) struct Devfs;
+
+impl Devfs {
+    // Return names of udev symbolic links in specified devfs folder(like
+    // 'disk/by-partuuid') which are pointing to specified block.
+    pub(crate) fn link_names(
+        dir: &str,
+        blk_path: &str,
+        sys_root: &SystemRoot,
+    ) -> Vec<String> {
+        let mut ret = Vec::new();
+        let blk_real_path =
+            match Path::new(&sys_root.real_path(blk_path)).canonicalize() {
+                Ok(p) => p,
+                Err(_) => return ret,
+            };
+        let entries = match fs::read_dir(sys_root.devfs_path(dir)) {
+            Ok(e) => e,
+            Err(_) => return ret,
+        };
+        for entry in entries.flatten() {
+            if let Ok(p) = entry.path().canonicalize() {
+                if p == blk_real_path {
+                    if let Ok(n) = entry.file_name().into_string() {
+                        ret.push(n);
+                    }
+                }
+            }
+        }
+        ret.sort();
+        ret
+    }
+
+    // Udev encodes unsafe characters of label or uuid in link name, for
+    // example space as '\x20'.
+    pub(crate) fn udev_encode(s: &str) -> String {
+        let mut ret = String::new();
+        for c in s.chars() {
+            if c.is_ascii_alphanumeric() || "#+-.:=@_".contains(c) {
+                ret.push(c);
+            } else if c.is_ascii() {
+                ret.push_str(&format!("\\x{:02x}", c as u8));
+            } else {
+                // Valid UTF-8 is kept.
+                ret.push(c);
+            }
+        }
+        ret
+    }
+
+    // Full paths of all udev symbolic links pointing to specified block.
+    pub(crate) fn all_links(
+        blk_path: &str,
+        sys_root: &SystemRoot,
+    ) -> Vec<String> {
+        let mut ret = Vec::new();
+        for dir in &[
+            "disk/by-id",
+            "disk/by-path",
+            "disk/by-uuid",
+            "disk/by-label",
+            "disk/by-partuuid",
+            "disk/by-partlabel",
+            "mapper",
+        ] {
+            for name in Devfs::link_names(dir, blk_path, sys_root) {
+                let link = format!("/dev/{}/{}", dir, name);
+                if link != blk_path {
+                    ret.push(link);
+                }
+            }
+        }
+        ret
+    }
+}
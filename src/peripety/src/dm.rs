@@ -1,51 +1,44 @@
-use super::blk_info::{BlkInfo, BlkType};
+use super::blk_info::{BlkInfo, BlkNode, BlkType};
 use super::error::PeripetyError;
+use super::partition;
+use super::sys_root::SystemRoot;
 use super::sysfs::Sysfs;
 
-use std::ffi::OsStr;
+use regex::Regex;
+use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 
-pub(crate) fn get_holder_dm_name(blk: &str) -> Option<String> {
-    let holders = format!("/sys/block/{}/holders", blk);
-    if let Ok(mut entries) = fs::read_dir(&holders) {
-        if let Some(Ok(holder)) = entries.next() {
-            if let Some(n) = holder.path().file_name() {
-                if let Some(s) = n.to_str() {
-                    if s.starts_with("dm-") {
-                        return Some(s.to_string());
-                    }
-                }
-            }
-        }
-    }
-    None
-}
-
 // Support query on these formats:
 //  * dm-0
-pub(crate) fn blk_info_get_dm(blk: &str) -> Result<BlkInfo, PeripetyError> {
-    let sysfs_uuid = format!("/sys/block/{}/dm/uuid", &blk);
+pub(crate) fn blk_info_get_dm(
+    blk: &str,
+    sys_root: &SystemRoot,
+) -> Result<BlkInfo, PeripetyError> {
+    let sysfs_uuid = sys_root.sysfs_path(&format!("block/{}/dm/uuid", &blk));
 
     if Path::new(&sysfs_uuid).exists() {
-        let sysfs_name = format!("/sys/block/{}/dm/name", &blk);
+        let sysfs_name =
+            sys_root.sysfs_path(&format!("block/{}/dm/name", &blk));
         let name = Sysfs::read(&sysfs_name)?;
         let mut ret = BlkInfo {
             wwid: Sysfs::read(&sysfs_uuid)?,
             blk_type: BlkType::Dm,
             blk_path: format!("/dev/mapper/{}", &name),
-            owners_wwids: Vec::new(),
-            owners_types: Vec::new(),
-            owners_paths: Vec::new(),
+            owners: Vec::new(),
             uuid: None,
             mount_point: None,
+            mounts: Vec::new(),
+            aliases: Vec::new(),
+            extension: HashMap::new(),
+            md_members: Vec::new(),
         };
         if ret.wwid.starts_with("LVM-") {
             ret.blk_type = BlkType::DmLvm;
         } else if ret.wwid.starts_with("mpath-") {
             ret.blk_type = BlkType::DmMultipath;
         }
-        let slave_dir = format!("/sys/block/{}/slaves", &blk);
+        let slave_dir = sys_root.sysfs_path(&format!("block/{}/slaves", &blk));
         let entries = match fs::read_dir(&slave_dir) {
             Ok(e) => e,
             Err(e) => {
@@ -55,67 +48,46 @@ pub(crate) fn blk_info_get_dm(blk: &str) -> Result<BlkInfo, PeripetyError> {
                 )));
             }
         };
-        for entry in entries {
-            let f = match entry {
-                Ok(e) => e.file_name(),
-                Err(_) => continue,
-            };
-            let slave_blk = match f.to_str() {
-                Some(k) => k,
-                None => continue,
-            };
-            if let Ok(slave_info) = BlkInfo::new_skip_extra(slave_blk) {
-                if !ret.owners_wwids.contains(&slave_info.wwid) {
-                    ret.owners_wwids.push(slave_info.wwid.clone());
-                    ret.owners_types
-                        .push(slave_info.blk_type.clone());
-                }
-                if !ret.owners_paths.contains(&slave_info.blk_path) {
-                    ret.owners_paths
-                        .push(slave_info.blk_path.clone());
-                }
-                if slave_info.blk_type == BlkType::DmLvm
-                    || slave_info.blk_type == BlkType::Dm
-                    || slave_info.blk_type == BlkType::DmMultipath
-                {
-                    for sub_slave_blk_path in slave_info.owners_paths {
-                        let sub_slave_blk;
-                        if let Ok(p) =
-                            Path::new(&sub_slave_blk_path).canonicalize()
-                        {
-                            sub_slave_blk =
-                                match p.file_name().and_then(OsStr::to_str) {
-                                    Some(n) => n.to_string(),
-                                    None => continue,
-                                };
-                        } else {
-                            continue;
-                        }
-
-                        if let Ok(sub_slave_info) =
-                            BlkInfo::new_skip_extra(&sub_slave_blk)
-                        {
-                            if !ret.owners_wwids.contains(&sub_slave_info.wwid)
-                            {
-                                ret.owners_wwids.push(sub_slave_info.wwid);
-                                ret.owners_types.push(sub_slave_info.blk_type);
-                            }
-                            if !ret.owners_paths
-                                .contains(&sub_slave_info.blk_path)
-                            {
-                                ret.owners_paths.push(sub_slave_info.blk_path);
-                            }
-                        }
-                    }
-                }
+        let mut slave_blks: Vec<String> = entries
+            .flatten()
+            .filter_map(|e| e.file_name().into_string().ok())
+            .collect();
+        slave_blks.sort();
+        for slave_blk in slave_blks {
+            // Each owner holds its own owners, hence no depth limitation
+            // here.
+            if let Ok(slave_info) =
+                BlkInfo::new_skip_extra(&slave_blk, sys_root)
+            {
+                ret.owners.push(BlkNode::from(slave_info));
             }
         }
-        if ret.owners_wwids.len() == 0 {
+        if ret.owners.is_empty() {
             return Err(PeripetyError::InternalBug(format!(
                 "dm::blk_info_get_dm() not supported blk {}",
                 blk
             )));
         }
+        // Partition created by kpartx has dm uuid like 'part1-mpath-<wwid>'
+        if let Some(cap) = Regex::new(r"^part([0-9]+)-")
+            .expect("BUG: blk_info_get_dm()")
+            // ^ We never panic as above regex is valid.
+            .captures(&ret.wwid.clone())
+        {
+            ret.blk_type = BlkType::Partition;
+            ret.wwid = format!("{}-part{}", ret.owners[0].wwid, &cap[1]);
+            // Unlike kernel partition, the start sector is only stored in dm
+            // table, not sysfs.
+            ret.extension =
+                partition::part_link_extension(&ret.blk_path, sys_root);
+            ret.extension
+                .insert("part_number".to_string(), cap[1].to_string());
+            let size_path =
+                sys_root.sysfs_path(&format!("block/{}/size", blk));
+            if let Ok(v) = Sysfs::read(&size_path) {
+                ret.extension.insert("part_size".to_string(), v);
+            }
+        }
         return Ok(ret);
     }
 
@@ -125,3 +97,50 @@ pub(crate) fn blk_info_get_dm(blk: &str) -> Result<BlkInfo, PeripetyError> {
         blk, sysfs_uuid
     )));
 }
+
+// LVM escapes '-' in VG and LV names as '--' in dm name 'vg-lv'.
+pub(crate) fn lvm_names(dm_name: &str) -> Option<(String, String)> {
+    let chars: Vec<char> = dm_name.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] == '-' {
+            if i + 1 < chars.len() && chars[i + 1] == '-' {
+                i += 2;
+                continue;
+            }
+            let vg: String = chars[..i].iter().collect();
+            let lv: String = chars[i + 1..].iter().collect();
+            if vg.is_empty() || lv.is_empty() {
+                return None;
+            }
+            return Some((vg.replace("--", "-"), lv.replace("--", "-")));
+        }
+        i += 1;
+    }
+    None
+}
+
+// Convert LVM name 'vg/lv' to block path.
+pub(crate) fn lvm_blk_path(
+    vg_lv: &str,
+    sys_root: &SystemRoot,
+) -> Option<String> {
+    let names: Vec<&str> = vg_lv.split('/').collect();
+    if names.len() != 2 || names[0].is_empty() || names[1].is_empty() {
+        return None;
+    }
+    let candidates = [
+        format!("/dev/{}/{}", names[0], names[1]),
+        format!(
+            "/dev/mapper/{}-{}",
+            names[0].replace("-", "--"),
+            names[1].replace("-", "--")
+        ),
+    ];
+    for path in &candidates {
+        if Path::new(&sys_root.real_path(path)).exists() {
+            return Some(path.to_string());
+        }
+    }
+    None
+}
@@ -10,6 +10,9 @@ pub enum PeripetyError {
     InternalBug(String),
     BlockNoExists(String),
     StorageSubSystemParseError(String),
+    ScsiSenseParseError(String),
+    EventTypeParseError(String),
+    StoreError(String),
 }
 
 impl fmt::Display for PeripetyError {
@@ -25,7 +28,10 @@ impl fmt::Display for PeripetyError {
                 | PeripetyError::NoSupport(ref x)
                 | PeripetyError::InternalBug(ref x)
                 | PeripetyError::BlockNoExists(ref x)
-                | PeripetyError::StorageSubSystemParseError(ref x) => x,
+                | PeripetyError::StorageSubSystemParseError(ref x)
+                | PeripetyError::ScsiSenseParseError(ref x)
+                | PeripetyError::EventTypeParseError(ref x)
+                | PeripetyError::StoreError(ref x) => x,
             }
         )
     }
@@ -1,5 +1,8 @@
 use super::error::PeripetyError;
+use super::event_type::EventTypeInfo;
 
+use chrono::{SecondsFormat, TimeZone, Utc};
+use serde::de::{self, Deserializer, Visitor};
 use serde_json;
 use std::collections::HashMap;
 use std::fmt;
@@ -29,7 +32,7 @@ impl FromStr for LogSeverity {
             "2" | "CRITICAL" => Ok(LogSeverity::Ctritical),
             "3" | "ERROR" => Ok(LogSeverity::Error),
             "4" | "WARNING" => Ok(LogSeverity::Warning),
-            "5" | "Notice" => Ok(LogSeverity::Notice),
+            "5" | "NOTICE" => Ok(LogSeverity::Notice),
             "6" | "INFO" => Ok(LogSeverity::Info),
             "7" | "DEBUG" => Ok(LogSeverity::Debug),
             _ => Err(PeripetyError::LogSeverityParseError(format!(
@@ -51,6 +54,10 @@ pub enum StorageSubSystem {
     FsExt4,
     FsXfs,
     Nvme,
+    Block,
+    Iscsi,
+    Smart,
+    MdRaid,
 }
 
 impl FromStr for StorageSubSystem {
@@ -64,6 +71,10 @@ impl FromStr for StorageSubSystem {
             "EXT4" => Ok(StorageSubSystem::FsExt4),
             "XFS" => Ok(StorageSubSystem::FsXfs),
             "NVME" => Ok(StorageSubSystem::Nvme),
+            "BLOCK" => Ok(StorageSubSystem::Block),
+            "ISCSI" => Ok(StorageSubSystem::Iscsi),
+            "SMART" => Ok(StorageSubSystem::Smart),
+            "MD-RAID" => Ok(StorageSubSystem::MdRaid),
             _ => Err(PeripetyError::StorageSubSystemParseError(
                 format!("Invalid StorageSubSystem string {}", s),
             )),
@@ -83,38 +94,112 @@ impl fmt::Display for StorageSubSystem {
             StorageSubSystem::FsExt4 => write!(fmt, "ext4"),
             StorageSubSystem::FsXfs => write!(fmt, "xfs"),
             StorageSubSystem::Nvme => write!(fmt, "NVMe"),
+            StorageSubSystem::Block => write!(fmt, "Block"),
+            StorageSubSystem::Iscsi => write!(fmt, "iSCSI"),
+            StorageSubSystem::Smart => write!(fmt, "SMART"),
+            StorageSubSystem::MdRaid => write!(fmt, "MD-RAID"),
         }
     }
 }
 
+// Version of `StorageEvent` JSON layout. Compatibility policy:
+//  * Adding new field does not bump the version, the new field should have
+//    default value for deserializing older events.
+//  * Renaming, removing or changing type of field bumps the version, and
+//    `StorageEvent::from_json_string()` should still convert all older
+//    versions to current layout.
+//  * Event with newer version is deserialized in best effort: unknown fields
+//    are ignored.
+// Events without `schema_version` are considered as version 0.
+pub const STORAGE_EVENT_SCHEMA_VERSION: u32 = 1;
+
+// Version 0 events saved timestamp as microseconds since epoch.
+fn deserialize_timestamp<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct TimestampVisitor;
+
+    impl<'de> Visitor<'de> for TimestampVisitor {
+        type Value = String;
+
+        fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+            write!(fmt, "RFC 3339 string or microseconds since epoch")
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<String, E> {
+            Ok(v.to_string())
+        }
+
+        fn visit_u64<E: de::Error>(self, v: u64) -> Result<String, E> {
+            self.visit_i64(v as i64)
+        }
+
+        fn visit_i64<E: de::Error>(self, v: i64) -> Result<String, E> {
+            usec_to_rfc3339(v).ok_or_else(|| {
+                E::custom(format!("Invalid timestamp {}", v))
+            })
+        }
+    }
+
+    deserializer.deserialize_any(TimestampVisitor)
+}
+
+// Convert microseconds since epoch to RFC 3339 string in UTC.
+fn usec_to_rfc3339(usec: i64) -> Option<String> {
+    Utc.timestamp_opt(
+        usec.div_euclid(1_000_000),
+        usec.rem_euclid(1_000_000) as u32 * 1000,
+    )
+    .single()
+    .map(|t| t.to_rfc3339_opts(SecondsFormat::Micros, false))
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
 pub struct StorageEvent {
+    // Use 0 when missing, see STORAGE_EVENT_SCHEMA_VERSION.
+    #[serde(default)]
+    pub schema_version: u32,
     pub hostname: String,
     pub severity: LogSeverity,
     pub sub_system: StorageSubSystem,
+    #[serde(deserialize_with = "deserialize_timestamp")]
     pub timestamp: String,
     pub event_id: String,
+    // For failure event, the `event_id` of the event causing this one, or
+    // itself if no root cause is found. Empty for other events.
+    pub root_cause_event_id: String,
+    // The `event_id` of the event this one is generated from, empty for the
+    // event collected from log directly.
+    pub parent_event_id: String,
     pub event_type: String,
     pub dev_wwid: String,
     pub dev_path: String,
     pub owners_wwids: Vec<String>,
     pub owners_paths: Vec<String>,
     // ^ What devices does current dev_wwid depending on.
-    #[serde(skip_serializing, skip_deserializing)]
+    // Version 0 events have `kdev` saved.
+    #[serde(skip_serializing)]
     pub kdev: String, // internal use-only: kernel device name.
     pub msg: String,
     pub raw_msg: String,
+    // Version 0 events use the misspelled 'extention'.
+    #[serde(alias = "extention")]
     pub extension: HashMap<String, String>,
 }
 
 impl Default for StorageEvent {
     fn default() -> StorageEvent {
         StorageEvent {
+            schema_version: STORAGE_EVENT_SCHEMA_VERSION,
             hostname: String::new(),
             severity: LogSeverity::Unknown,
             sub_system: StorageSubSystem::Unknown,
             timestamp: String::new(),
             event_id: String::new(),
+            root_cause_event_id: String::new(),
+            parent_event_id: String::new(),
             event_type: String::new(),
             dev_wwid: String::new(),
             dev_path: String::new(),
@@ -128,7 +213,54 @@ impl Default for StorageEvent {
     }
 }
 
+// FNV-1a hash.
+fn fnv1a_64(offset_basis: u64, data: &[u8]) -> u64 {
+    let mut hash = offset_basis;
+    for b in data {
+        hash ^= u64::from(*b);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
 impl StorageEvent {
+    // Generate UUID formatted event ID from key unique to the event, like
+    // journal cursor. The same key always generates the same ID, hence
+    // re-processing the same log produces the same event ID.
+    pub fn gen_event_id(key: &str) -> String {
+        let h1 = fnv1a_64(0xcbf2_9ce4_8422_2325, key.as_bytes());
+        let h2 = fnv1a_64(h1, key.as_bytes());
+        format!(
+            "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+            h1 >> 32,
+            (h1 >> 16) & 0xffff,
+            h1 & 0xffff,
+            h2 >> 48,
+            h2 & 0xffff_ffff_ffff
+        )
+    }
+
+    // Copy of current event for parser to generate synthetic event with
+    // event ID derived from current one.
+    pub fn to_synthetic(&self, parser_name: &str) -> StorageEvent {
+        let mut event = self.clone();
+        if !self.event_id.is_empty() {
+            event.event_id = StorageEvent::gen_event_id(&format!(
+                "{}/{}",
+                self.event_id, parser_name
+            ));
+            event.parent_event_id = self.event_id.clone();
+        }
+        event
+    }
+
+    // Registry information of `event_type`.
+    pub fn event_type_info(
+        &self,
+    ) -> Result<&'static EventTypeInfo, PeripetyError> {
+        EventTypeInfo::get(&self.event_type)
+    }
+
     pub fn to_json_string(&self) -> Result<String, PeripetyError> {
         match serde_json::to_string(&self) {
             Ok(s) => Ok(s),
@@ -147,11 +279,20 @@ impl StorageEvent {
             ))),
         }
     }
+    // Serde already handled the renamed or retyped fields of older
+    // versions, only the version is updated here.
+    fn migrate(mut event: StorageEvent) -> StorageEvent {
+        if event.schema_version < STORAGE_EVENT_SCHEMA_VERSION {
+            event.schema_version = STORAGE_EVENT_SCHEMA_VERSION;
+        }
+        event
+    }
+
     pub fn from_json_string(
         json_string: &str,
     ) -> Result<StorageEvent, PeripetyError> {
         match serde_json::from_str(json_string) {
-            Ok(e) => Ok(e),
+            Ok(e) => Ok(StorageEvent::migrate(e)),
             Err(e) => Err(PeripetyError::JsonDeserializeError(format!(
                 "{}",
                 e
@@ -171,7 +312,7 @@ impl StorageEvent {
             }
         };
         match serde_json::from_str(tmp_s) {
-            Ok(e) => Ok(e),
+            Ok(e) => Ok(StorageEvent::migrate(e)),
             Err(e) => Err(PeripetyError::JsonDeserializeError(format!(
                 "{}",
                 e
@@ -179,3 +320,36 @@ impl StorageEvent {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usec_to_rfc3339() {
+        assert_eq!(
+            usec_to_rfc3339(0),
+            Some("1970-01-01T00:00:00.000000+00:00".to_string())
+        );
+        assert_eq!(
+            usec_to_rfc3339(1_520_000_000_123_456),
+            Some("2018-03-02T14:13:20.123456+00:00".to_string())
+        );
+        assert_eq!(
+            usec_to_rfc3339(-1),
+            Some("1969-12-31T23:59:59.999999+00:00".to_string())
+        );
+        assert_eq!(usec_to_rfc3339(i64::MAX), None);
+    }
+
+    #[test]
+    fn test_version_0_timestamp() {
+        let event = StorageEvent::from_json_string(
+            r#"{"timestamp": 1520000000123456, "extention": {"a": "b"}}"#,
+        )
+        .unwrap();
+        assert_eq!(event.schema_version, STORAGE_EVENT_SCHEMA_VERSION);
+        assert_eq!(event.timestamp, "2018-03-02T14:13:20.123456+00:00");
+        assert_eq!(event.extension.get("a"), Some(&"b".to_string()));
+    }
+}
@@ -0,0 +1,495 @@
+// Registry of all event types peripety could generate. The `event_type` of
+// `StorageEvent` should be one of them, so consumers could depend on it.
+// Event types not shipped with peripety, like vendor specific ones of parser
+// plugins, should be registered by `EventTypeInfo::register()`.
+
+use super::error::PeripetyError;
+use super::event::{LogSeverity, StorageSubSystem};
+
+use serde_json;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::RwLock;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum EventTypeKind {
+    Failure,
+    Recovery,
+    Info,
+}
+
+impl fmt::Display for EventTypeKind {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EventTypeKind::Failure => write!(fmt, "failure"),
+            EventTypeKind::Recovery => write!(fmt, "recovery"),
+            EventTypeKind::Info => write!(fmt, "info"),
+        }
+    }
+}
+
+impl FromStr for EventTypeKind {
+    type Err = PeripetyError;
+    fn from_str(s: &str) -> Result<EventTypeKind, PeripetyError> {
+        match s.to_lowercase().as_ref() {
+            "failure" => Ok(EventTypeKind::Failure),
+            "recovery" => Ok(EventTypeKind::Recovery),
+            "info" => Ok(EventTypeKind::Info),
+            _ => Err(PeripetyError::EventTypeParseError(format!(
+                "Invalid event type kind {}",
+                s
+            ))),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct EventTypeInfo {
+    pub name: &'static str,
+    // Used when log does not provide severity.
+    pub severity: LogSeverity,
+    pub sub_systems: &'static [StorageSubSystem],
+    pub kind: EventTypeKind,
+    pub description: &'static str,
+}
+
+const EVENT_TYPES: &[EventTypeInfo] = &[
+    EventTypeInfo {
+        name: "DM_MPATH_PATH_FAILED",
+        severity: LogSeverity::Warning,
+        sub_systems: &[StorageSubSystem::Multipath],
+        kind: EventTypeKind::Failure,
+        description: "Path of multipath device failed",
+    },
+    EventTypeInfo {
+        name: "DM_MPATH_PATH_REINSTATED",
+        severity: LogSeverity::Info,
+        sub_systems: &[StorageSubSystem::Multipath],
+        kind: EventTypeKind::Recovery,
+        description: "Failed path of multipath device is back online",
+    },
+    EventTypeInfo {
+        name: "DM_MPATH_CHECKER_FAILED_PATH",
+        severity: LogSeverity::Warning,
+        sub_systems: &[StorageSubSystem::Multipath],
+        kind: EventTypeKind::Failure,
+        description: "Path checker of multipathd found path failed",
+    },
+    EventTypeInfo {
+        name: "DM_MPATH_ALL_PATHS_DOWN",
+        severity: LogSeverity::Error,
+        sub_systems: &[StorageSubSystem::Multipath],
+        kind: EventTypeKind::Failure,
+        description: "Multipath device has no active path",
+    },
+    EventTypeInfo {
+        name: "DM_MPATH_REMAINING_PATHS",
+        severity: LogSeverity::Info,
+        sub_systems: &[StorageSubSystem::Multipath],
+        kind: EventTypeKind::Info,
+        description: "Active path count of multipath device changed",
+    },
+    EventTypeInfo {
+        name: "DM_DIRTY_LOG_READ_FAILED",
+        severity: LogSeverity::Error,
+        sub_systems: &[StorageSubSystem::DmDirtyLog],
+        kind: EventTypeKind::Failure,
+        description: "Failed to read header on dirty region log device",
+    },
+    EventTypeInfo {
+        name: "DM_DIRTY_LOG_WRITE_FAILED",
+        severity: LogSeverity::Error,
+        sub_systems: &[StorageSubSystem::DmDirtyLog],
+        kind: EventTypeKind::Failure,
+        description: "Failed to write header on dirty region log device",
+    },
+    EventTypeInfo {
+        name: "SCSI_UNALIGNED_PARTIAL_COMPLETION",
+        severity: LogSeverity::Warning,
+        sub_systems: &[StorageSubSystem::Scsi],
+        kind: EventTypeKind::Failure,
+        description: "SCSI command completed with residual count not \
+                      aligned to sector size",
+    },
+    EventTypeInfo {
+        name: "SCSI_SPINNING_UP_DISK",
+        severity: LogSeverity::Info,
+        sub_systems: &[StorageSubSystem::Scsi],
+        kind: EventTypeKind::Info,
+        description: "SCSI disk is spinning up",
+    },
+    EventTypeInfo {
+        name: "SCSI_FAILED_RESULT",
+        severity: LogSeverity::Warning,
+        sub_systems: &[StorageSubSystem::Scsi],
+        kind: EventTypeKind::Failure,
+        description: "SCSI command failed with host and driver status",
+    },
+    EventTypeInfo {
+        name: "SCSI_SENSE_KEY",
+        severity: LogSeverity::Warning,
+        sub_systems: &[StorageSubSystem::Scsi],
+        kind: EventTypeKind::Failure,
+        description: "SCSI command failed with sense key",
+    },
+    EventTypeInfo {
+        name: "SCSI_MEDIUM_ERROR",
+        severity: LogSeverity::Error,
+        sub_systems: &[StorageSubSystem::Scsi],
+        kind: EventTypeKind::Failure,
+        description: "SCSI command failed with medium error sense key",
+    },
+    EventTypeInfo {
+        name: "SCSI_HARDWARE_ERROR",
+        severity: LogSeverity::Error,
+        sub_systems: &[StorageSubSystem::Scsi],
+        kind: EventTypeKind::Failure,
+        description: "SCSI command failed with hardware error sense key",
+    },
+    EventTypeInfo {
+        name: "SCSI_ADDITIONAL_SENSE_CODE",
+        severity: LogSeverity::Warning,
+        sub_systems: &[StorageSubSystem::Scsi],
+        kind: EventTypeKind::Failure,
+        description: "Additional sense code and qualifier of failed SCSI \
+                      command",
+    },
+    EventTypeInfo {
+        name: "SCSI_CDB",
+        severity: LogSeverity::Warning,
+        sub_systems: &[StorageSubSystem::Scsi],
+        kind: EventTypeKind::Failure,
+        description: "Command descriptor block of failed SCSI command",
+    },
+    EventTypeInfo {
+        name: "SCSI_DRIVE_IO_ERROR",
+        severity: LogSeverity::Error,
+        sub_systems: &[StorageSubSystem::Scsi],
+        kind: EventTypeKind::Failure,
+        description: "SCSI command failed, assembled from all kernel logs \
+                      of the command",
+    },
+    EventTypeInfo {
+        // The typo is kept for compatibility.
+        name: "SCSI_MEDIUM_ACCESS_TIMEOUT_OFFLINEING_DISK",
+        severity: LogSeverity::Error,
+        sub_systems: &[StorageSubSystem::Scsi],
+        kind: EventTypeKind::Failure,
+        description: "SCSI disk is offlined due to medium access timeout",
+    },
+    EventTypeInfo {
+        name: "DM_FS_MOUNTED",
+        severity: LogSeverity::Info,
+        sub_systems: &[StorageSubSystem::FsExt4, StorageSubSystem::FsXfs],
+        kind: EventTypeKind::Info,
+        description: "File system mounted",
+    },
+    EventTypeInfo {
+        name: "DM_FS_UNMOUNTED",
+        severity: LogSeverity::Info,
+        sub_systems: &[StorageSubSystem::FsExt4, StorageSubSystem::FsXfs],
+        kind: EventTypeKind::Info,
+        description: "File system unmounted",
+    },
+    EventTypeInfo {
+        name: "DM_FS_IO_ERROR",
+        severity: LogSeverity::Error,
+        sub_systems: &[StorageSubSystem::FsExt4, StorageSubSystem::FsXfs],
+        kind: EventTypeKind::Failure,
+        description: "File system got I/O error",
+    },
+    EventTypeInfo {
+        name: "BLK_IO_ERROR",
+        severity: LogSeverity::Error,
+        sub_systems: &[StorageSubSystem::Block],
+        kind: EventTypeKind::Failure,
+        description: "Block layer got I/O error on request",
+    },
+    EventTypeInfo {
+        name: "BLK_BUFFER_IO_ERROR",
+        severity: LogSeverity::Error,
+        sub_systems: &[StorageSubSystem::Block],
+        kind: EventTypeKind::Failure,
+        description: "Buffer I/O error on block device",
+    },
+    EventTypeInfo {
+        name: "BLK_ADDED",
+        severity: LogSeverity::Info,
+        sub_systems: &[StorageSubSystem::Block],
+        kind: EventTypeKind::Info,
+        description: "Block device added",
+    },
+    EventTypeInfo {
+        name: "BLK_REMOVED",
+        severity: LogSeverity::Warning,
+        sub_systems: &[StorageSubSystem::Block],
+        kind: EventTypeKind::Info,
+        description: "Block device removed",
+    },
+    EventTypeInfo {
+        name: "BLK_CHANGED",
+        severity: LogSeverity::Info,
+        sub_systems: &[StorageSubSystem::Block],
+        kind: EventTypeKind::Info,
+        description: "Block device changed, like resized or media changed",
+    },
+    EventTypeInfo {
+        name: "ISCSI_CONN_SHUTDOWN",
+        severity: LogSeverity::Warning,
+        sub_systems: &[StorageSubSystem::Iscsi],
+        kind: EventTypeKind::Failure,
+        description: "iSCSI connection to target is shutdown",
+    },
+    EventTypeInfo {
+        name: "ISCSI_CONN_OPERATIONAL",
+        severity: LogSeverity::Info,
+        sub_systems: &[StorageSubSystem::Iscsi],
+        kind: EventTypeKind::Recovery,
+        description: "iSCSI connection to target is operational",
+    },
+    EventTypeInfo {
+        name: "ISCSI_CONN_ERROR",
+        severity: LogSeverity::Warning,
+        sub_systems: &[StorageSubSystem::Iscsi],
+        kind: EventTypeKind::Failure,
+        description: "Kernel reported iSCSI connection error",
+    },
+    EventTypeInfo {
+        name: "ISCSI_LOGIN_FAILED",
+        severity: LogSeverity::Error,
+        sub_systems: &[StorageSubSystem::Iscsi],
+        kind: EventTypeKind::Failure,
+        description: "iSCSI login rejected by target",
+    },
+    EventTypeInfo {
+        name: "LVM_THIN_POOL_USAGE_HIGH",
+        severity: LogSeverity::Warning,
+        sub_systems: &[StorageSubSystem::LvmThin],
+        kind: EventTypeKind::Failure,
+        description: "Data or metadata usage of LVM thin pool is high",
+    },
+    EventTypeInfo {
+        name: "SMART_DISK_FAIL",
+        severity: LogSeverity::Ctritical,
+        sub_systems: &[StorageSubSystem::Smart],
+        kind: EventTypeKind::Failure,
+        description: "SMART self-check indicates disk failure",
+    },
+    EventTypeInfo {
+        name: "SMART_ATTRIBUTE_FAILED",
+        severity: LogSeverity::Error,
+        sub_systems: &[StorageSubSystem::Smart],
+        kind: EventTypeKind::Failure,
+        description: "SMART attribute is below threshold",
+    },
+    EventTypeInfo {
+        name: "SMART_UNREADABLE_SECTORS",
+        severity: LogSeverity::Warning,
+        sub_systems: &[StorageSubSystem::Smart],
+        kind: EventTypeKind::Failure,
+        description: "SMART reported pending or offline uncorrectable sectors",
+    },
+    EventTypeInfo {
+        name: "MD_RAID_FAIL",
+        severity: LogSeverity::Error,
+        sub_systems: &[StorageSubSystem::MdRaid],
+        kind: EventTypeKind::Failure,
+        description: "Member of MD RAID failed",
+    },
+    EventTypeInfo {
+        name: "MD_RAID_DEGRADED",
+        severity: LogSeverity::Error,
+        sub_systems: &[StorageSubSystem::MdRaid],
+        kind: EventTypeKind::Failure,
+        description: "MD RAID is degraded",
+    },
+    EventTypeInfo {
+        name: "MD_RAID_EVENT",
+        severity: LogSeverity::Info,
+        sub_systems: &[StorageSubSystem::MdRaid],
+        kind: EventTypeKind::Info,
+        description: "Other events reported by mdadm monitor",
+    },
+    EventTypeInfo {
+        name: "ROOT_CAUSE_SUMMARY",
+        severity: LogSeverity::Warning,
+        // Inherits the sub system of root cause event.
+        sub_systems: &[],
+        kind: EventTypeKind::Info,
+        description: "Summary of events caused by the same root cause event",
+    },
+];
+
+// Event types registered at runtime. They are never freed, so `get()` could
+// return the same lifetime as build-in ones.
+static REGISTERED_EVENT_TYPES: RwLock<Vec<&'static EventTypeInfo>> =
+    RwLock::new(Vec::new());
+
+fn leak_str(s: &str) -> &'static str {
+    Box::leak(s.to_string().into_boxed_str())
+}
+
+impl EventTypeInfo {
+    // All build-in event types.
+    pub fn all() -> &'static [EventTypeInfo] {
+        EVENT_TYPES
+    }
+
+    // Event types registered by `register()` in current process.
+    pub fn registered() -> Vec<&'static EventTypeInfo> {
+        match REGISTERED_EVENT_TYPES.read() {
+            Ok(r) => r.clone(),
+            Err(e) => e.into_inner().clone(),
+        }
+    }
+
+    pub fn get(name: &str) -> Result<&'static EventTypeInfo, PeripetyError> {
+        if let Some(t) = EVENT_TYPES.iter().find(|t| t.name == name) {
+            return Ok(t);
+        }
+        match EventTypeInfo::registered().into_iter().find(|t| t.name == name)
+        {
+            Some(t) => Ok(t),
+            None => Err(PeripetyError::EventTypeParseError(format!(
+                "Unknown event type {}",
+                name
+            ))),
+        }
+    }
+
+    // Register event type not shipped with peripety. The name should be
+    // upper case letters, digits and underscores. Registering the same
+    // event type again is allowed as long as nothing changed.
+    pub fn register(
+        name: &str,
+        severity: LogSeverity,
+        sub_systems: &[StorageSubSystem],
+        kind: EventTypeKind,
+        description: &str,
+    ) -> Result<&'static EventTypeInfo, PeripetyError> {
+        if name.is_empty()
+            || !name.chars().all(|c| {
+                c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_'
+            }) {
+            return Err(PeripetyError::EventTypeParseError(format!(
+                "Invalid event type name '{}'",
+                name
+            )));
+        }
+        if EVENT_TYPES.iter().any(|t| t.name == name) {
+            return Err(PeripetyError::EventTypeParseError(format!(
+                "Event type {} is build-in",
+                name
+            )));
+        }
+        let mut registered = match REGISTERED_EVENT_TYPES.write() {
+            Ok(r) => r,
+            Err(e) => e.into_inner(),
+        };
+        if let Some(t) = registered.iter().find(|t| t.name == name) {
+            if t.severity == severity
+                && t.sub_systems == sub_systems
+                && t.kind == kind
+                && t.description == description
+            {
+                return Ok(t);
+            }
+            return Err(PeripetyError::EventTypeParseError(format!(
+                "Event type {} is already registered differently",
+                name
+            )));
+        }
+        let info: &'static EventTypeInfo = Box::leak(Box::new(EventTypeInfo {
+            name: leak_str(name),
+            severity,
+            sub_systems: Box::leak(sub_systems.to_vec().into_boxed_slice()),
+            kind,
+            description: leak_str(description),
+        }));
+        registered.push(info);
+        Ok(info)
+    }
+
+    pub fn to_json_string_pretty(&self) -> Result<String, PeripetyError> {
+        match serde_json::to_string_pretty(&self) {
+            Ok(s) => Ok(s),
+            Err(e) => Err(PeripetyError::JsonSerializeError(format!(
+                "{}",
+                e
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_register() {
+        let name = "TEST_VENDOR_HBA_PORT_DOWN";
+        assert!(EventTypeInfo::get(name).is_err());
+        let info = EventTypeInfo::register(
+            name,
+            LogSeverity::Error,
+            &[StorageSubSystem::Scsi],
+            EventTypeKind::Failure,
+            "Port of vendor HBA is down",
+        )
+        .unwrap();
+        assert_eq!(EventTypeInfo::get(name).unwrap(), info);
+        assert!(EventTypeInfo::registered().contains(&info));
+
+        // Registering again is allowed only if nothing changed.
+        assert!(EventTypeInfo::register(
+            name,
+            LogSeverity::Error,
+            &[StorageSubSystem::Scsi],
+            EventTypeKind::Failure,
+            "Port of vendor HBA is down",
+        )
+        .is_ok());
+        assert!(EventTypeInfo::register(
+            name,
+            LogSeverity::Warning,
+            &[StorageSubSystem::Scsi],
+            EventTypeKind::Failure,
+            "Port of vendor HBA is down",
+        )
+        .is_err());
+
+        // Build-in ones cannot be overridden.
+        assert!(EventTypeInfo::register(
+            "SCSI_CDB",
+            LogSeverity::Warning,
+            &[StorageSubSystem::Scsi],
+            EventTypeKind::Failure,
+            "",
+        )
+        .is_err());
+        assert!(EventTypeInfo::register(
+            "test-vendor",
+            LogSeverity::Warning,
+            &[],
+            EventTypeKind::Info,
+            "",
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_kind_from_str() {
+        for kind in &[
+            EventTypeKind::Failure,
+            EventTypeKind::Recovery,
+            EventTypeKind::Info,
+        ] {
+            assert_eq!(
+                format!("{}", kind).parse::<EventTypeKind>().unwrap(),
+                *kind
+            );
+        }
+        assert!("Failure".parse::<EventTypeKind>().is_ok());
+        assert!("fail".parse::<EventTypeKind>().is_err());
+    }
+}
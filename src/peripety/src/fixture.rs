@@ -0,0 +1,122 @@
+// Temporary directory tree holding sysfs, devfs and procfs files, for tests
+// running block lookups against a `SystemRoot`. Only built for tests and
+// with the `fixture` feature. The tree is removed on drop.
+
+use super::sys_root::SystemRoot;
+
+use std::env;
+use std::fs;
+use std::os::unix::fs as unix_fs;
+use std::path::Path;
+use std::process;
+
+pub struct Fixture {
+    pub root: String,
+    pub sys_root: SystemRoot,
+}
+
+impl Fixture {
+    // The `name` should be unique among tests as they run in parallel.
+    pub fn new(name: &str) -> Fixture {
+        let root = env::temp_dir()
+            .join(format!("peripety-test-{}-{}", name, process::id()));
+        let root = root.to_str().expect("BUG: Fixture::new()").to_string();
+        // Leftover of previous crashed run.
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).expect("BUG: Fixture::new()");
+        let sys_root = SystemRoot::new(&root);
+        Fixture { root, sys_root }
+    }
+
+    // Convert live system path like `/sys/block/sda` to the path in fixture,
+    // other paths are treated as relative to fixture root.
+    pub fn path(&self, path: &str) -> String {
+        let real_path = self.sys_root.real_path(path);
+        if real_path == path {
+            format!("{}/{}", self.root, path.trim_start_matches('/'))
+        } else {
+            real_path
+        }
+    }
+
+    // Create file with parent folders.
+    pub fn write(&self, path: &str, content: &str) {
+        let path = self.path(path);
+        if let Some(dir) = Path::new(&path).parent() {
+            fs::create_dir_all(dir).expect("BUG: Fixture::write()");
+        }
+        fs::write(&path, content).expect("BUG: Fixture::write()");
+    }
+
+    pub fn mkdir(&self, path: &str) {
+        fs::create_dir_all(self.path(path)).expect("BUG: Fixture::mkdir()");
+    }
+
+    // Create symbolic link `path` pointing to `target`. Absolute target is
+    // converted to path in fixture, relative target is kept.
+    pub fn symlink(&self, target: &str, path: &str) {
+        let path = self.path(path);
+        if let Some(dir) = Path::new(&path).parent() {
+            fs::create_dir_all(dir).expect("BUG: Fixture::symlink()");
+        }
+        let target = if target.starts_with('/') {
+            self.path(target)
+        } else {
+            target.to_string()
+        };
+        unix_fs::symlink(target, &path).expect("BUG: Fixture::symlink()");
+    }
+
+    // Block `name` with `major_minor` in `/sys/block`, `/sys/class/block`,
+    // `/sys/dev/block` and a regular file as node in `/dev`.
+    pub fn add_blk(&self, name: &str, major_minor: &str) {
+        self.write(
+            &format!("/sys/block/{}/dev", name),
+            &format!("{}\n", major_minor),
+        );
+        self.symlink(
+            &format!("../../block/{}", name),
+            &format!("/sys/class/block/{}", name),
+        );
+        self.symlink(
+            &format!("../../block/{}", name),
+            &format!("/sys/dev/block/{}", major_minor),
+        );
+        self.write(&format!("/dev/{}", name), "");
+    }
+
+    // Partition `name` of block `parent`, sysfs folder is under parent's.
+    pub fn add_part(
+        &self,
+        parent: &str,
+        name: &str,
+        number: u32,
+        major_minor: &str,
+    ) {
+        let dir = format!("/sys/block/{}/{}", parent, name);
+        self.write(&format!("{}/dev", dir), &format!("{}\n", major_minor));
+        self.write(&format!("{}/partition", dir), &format!("{}\n", number));
+        let rel = format!("../../block/{}/{}", parent, name);
+        self.symlink(&rel, &format!("/sys/class/block/{}", name));
+        self.symlink(&rel, &format!("/sys/dev/block/{}", major_minor));
+        self.write(&format!("/dev/{}", name), "");
+    }
+
+    // Make block `lower` a slave of `upper`.
+    pub fn add_slave(&self, upper: &str, lower: &str) {
+        self.symlink(
+            &format!("../../{}", lower),
+            &format!("/sys/block/{}/slaves/{}", upper, lower),
+        );
+        self.symlink(
+            &format!("/sys/block/{}", upper),
+            &format!("/sys/class/block/{}/holders/{}", lower, upper),
+        );
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
@@ -3,15 +3,41 @@ extern crate serde;
 extern crate serde_derive;
 extern crate serde_json;
 extern crate regex;
-extern crate libc;
+extern crate chrono;
+#[cfg(feature = "journald")]
+extern crate sdjournal;
 
 pub use self::error::PeripetyError;
-pub use self::event::{LogSeverity, StorageSubSystem, StorageEvent};
-pub use self::blk_info::{BlkType, BlkInfo};
+pub use self::event::{LogSeverity, StorageSubSystem, StorageEvent,
+                      STORAGE_EVENT_SCHEMA_VERSION};
+pub use self::event_type::{EventTypeInfo, EventTypeKind};
+pub use self::blk_info::{BlkType, BlkInfo, BlkNode};
+pub use self::md::{MdMember, MdMemberRole};
+pub use self::mount::MountInfo;
+pub use self::sys_root::SystemRoot;
+pub use self::store::{EventStore, JsonFileStore};
+#[cfg(feature = "journald")]
+pub use self::store::JournaldStore;
 
 mod error;
 mod event;
+mod event_type;
+#[cfg(any(test, feature = "fixture"))]
+#[doc(hidden)]
+pub mod fixture;
 mod blk_info;
+mod devfs;
 mod dm;
+mod loop_dev;
+mod md;
+mod mount;
+mod nvme;
+mod partition;
 mod scsi;
+pub mod scsi_sense;
+mod store;
 mod sysfs;
+mod sys_root;
+mod virtio;
+mod xen;
+mod zram;
@@ -0,0 +1,89 @@
+use super::blk_info::{major_minor_of_dev_t, BlkInfo, BlkNode, BlkType};
+use super::error::PeripetyError;
+use super::mount::major_minor_of_path;
+use super::sys_root::SystemRoot;
+use super::sysfs::Sysfs;
+
+use regex::Regex;
+use std::collections::HashMap;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+pub(crate) fn is_loop_blk(blk: &str) -> bool {
+    Regex::new(r"^loop[0-9]+$")
+        .expect("BUG: is_loop_blk()")
+        // ^ We never panic as above regex is valid.
+        .is_match(blk)
+}
+
+// Support query on these formats:
+//  * loop0
+pub(crate) fn blk_info_get_loop(
+    blk: &str,
+    sys_root: &SystemRoot,
+) -> Result<BlkInfo, PeripetyError> {
+    // The 'loop' folder only exists when loop device is attached to a file.
+    let loop_dir = sys_root.sysfs_path(&format!("block/{}/loop", blk));
+    if !Path::new(&loop_dir).exists() {
+        return Err(PeripetyError::BlockNoExists(format!(
+            "Loop block {} does not exists or is not attached to any file",
+            blk
+        )));
+    }
+    let backing_file = Sysfs::read(&format!("{}/backing_file", loop_dir))?;
+
+    let mut extension = HashMap::new();
+    extension.insert("loop_backing_file".to_string(), backing_file.clone());
+    for (key, file) in &[
+        ("loop_offset", "offset"),
+        ("loop_sizelimit", "sizelimit"),
+        ("loop_autoclear", "autoclear"),
+    ] {
+        if let Ok(v) = Sysfs::read(&format!("{}/{}", loop_dir, file)) {
+            extension.insert(key.to_string(), v);
+        }
+    }
+
+    let mut ret = BlkInfo {
+        wwid: String::new(),
+        blk_type: BlkType::Loop,
+        blk_path: format!("/dev/{}", blk),
+        owners: Vec::new(),
+        uuid: None,
+        mount_point: None,
+        mounts: Vec::new(),
+        aliases: Vec::new(),
+        extension,
+        md_members: Vec::new(),
+    };
+
+    // The device number and inode of backing file will not change even
+    // backing file is renamed. Captured sysfs tree has no backing file.
+    let metadata = if *sys_root == SystemRoot::default() {
+        fs::metadata(&backing_file).ok()
+    } else {
+        None
+    };
+    match metadata {
+        Some(m) => {
+            let major_minor = major_minor_of_dev_t(m.dev());
+            ret.wwid = format!("loop-{}-{}", major_minor, m.ino());
+            ret.extension
+                .insert("loop_backing_inode".to_string(), m.ino().to_string());
+            ret.extension
+                .insert("loop_backing_dev".to_string(), major_minor);
+        }
+        // Backing file is deleted or not visible in current mount namespace.
+        None => ret.wwid = format!("loop-file-{}", backing_file),
+    }
+    // The st_dev of file on btrfs is anonymous device, resolve the real block
+    // via mount table. Backing file might be on tmpfs which has no real
+    // block device, we just have no owners in that case.
+    if let Ok(major_minor) = major_minor_of_path(&backing_file, sys_root) {
+        if let Ok(bi) = BlkInfo::new_skip_extra(&major_minor, sys_root) {
+            ret.owners.push(BlkNode::from(bi));
+        }
+    }
+    Ok(ret)
+}
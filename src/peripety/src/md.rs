@@ -0,0 +1,327 @@
+use super::blk_info::{BlkInfo, BlkNode, BlkType};
+use super::error::PeripetyError;
+use super::sys_root::SystemRoot;
+use super::sysfs::Sysfs;
+
+use regex::Regex;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+#[derive(Clone, PartialEq, Debug, Serialize)]
+pub enum MdMemberRole {
+    Active,
+    Spare,
+    Rebuilding,
+    Faulty,
+    Unknown,
+}
+
+impl fmt::Display for MdMemberRole {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MdMemberRole::Active => write!(fmt, "active"),
+            MdMemberRole::Spare => write!(fmt, "spare"),
+            MdMemberRole::Rebuilding => write!(fmt, "rebuilding"),
+            MdMemberRole::Faulty => write!(fmt, "faulty"),
+            MdMemberRole::Unknown => write!(fmt, "unknown"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct MdMember {
+    pub wwid: String,
+    pub blk_path: String,
+    pub role: MdMemberRole,
+}
+
+pub(crate) fn is_md_blk(blk: &str) -> bool {
+    Regex::new(r"^md[0-9]+$")
+        .expect("BUG: is_md_blk()")
+        // ^ We never panic as above regex is valid.
+        .is_match(blk)
+}
+
+// The `state` sysfs file is comma separated flags like
+// 'in_sync,write_mostly'.
+fn member_role(state: &str, slot: &str) -> MdMemberRole {
+    let flags: Vec<&str> = state.split(',').collect();
+    if flags.contains(&"faulty") {
+        MdMemberRole::Faulty
+    } else if flags.contains(&"in_sync") {
+        MdMemberRole::Active
+    } else if slot != "none" {
+        MdMemberRole::Rebuilding
+    } else if flags.contains(&"spare") {
+        MdMemberRole::Spare
+    } else {
+        MdMemberRole::Unknown
+    }
+}
+
+// Convert mdadm style uuid 'a1b2c3d4:e5f6a7b8:c9d0e1f2:a3b4c5d6' to the
+// kernel one: 'a1b2c3d4-e5f6-a7b8-c9d0-e1f2a3b4c5d6'.
+fn mdadm_uuid_to_kernel(uuid: &str) -> String {
+    let s = uuid.replace(":", "");
+    if s.len() != 32 {
+        return uuid.to_string();
+    }
+    format!(
+        "{}-{}-{}-{}-{}",
+        &s[..8],
+        &s[8..12],
+        &s[12..16],
+        &s[16..20],
+        &s[20..]
+    )
+}
+
+fn array_uuid(blk: &str, sys_root: &SystemRoot) -> Option<String> {
+    let uuid_path = sys_root.sysfs_path(&format!("block/{}/md/uuid", blk));
+    if let Ok(uuid) = Sysfs::read(&uuid_path) {
+        return Some(uuid);
+    }
+
+    // Old kernel does not have 'md/uuid' sysfs file, try udev symbolic link
+    // '/dev/disk/by-id/md-uuid-<uuid>'.
+    let by_id_dir = sys_root.devfs_path("disk/by-id");
+    let blk_real_path = Path::new(&sys_root.devfs_path(blk)).canonicalize();
+    let blk_real_path = match blk_real_path {
+        Ok(p) => p,
+        Err(_) => return None,
+    };
+    if let Ok(entries) = fs::read_dir(&by_id_dir) {
+        for entry in entries.flatten() {
+            let name = match entry.file_name().into_string() {
+                Ok(n) => n,
+                Err(_) => continue,
+            };
+            if !name.starts_with("md-uuid-") {
+                continue;
+            }
+            if let Ok(p) = entry.path().canonicalize() {
+                if p == blk_real_path {
+                    return Some(mdadm_uuid_to_kernel(
+                        &name["md-uuid-".len()..],
+                    ));
+                }
+            }
+        }
+    }
+    None
+}
+
+// Save members into `md_members` and `owners` of provided BlkInfo.
+fn get_members(
+    blk: &str,
+    sys_root: &SystemRoot,
+    blk_info: &mut BlkInfo,
+) -> Result<(), PeripetyError> {
+    let md_dir = sys_root.sysfs_path(&format!("block/{}/md", blk));
+    let entries = match fs::read_dir(&md_dir) {
+        Ok(e) => e,
+        Err(e) => {
+            return Err(PeripetyError::InternalBug(format!(
+                "md::get_members(): Failed to read_dir {}: {}",
+                md_dir, e
+            )));
+        }
+    };
+    for entry in entries.flatten() {
+        let name = match entry.file_name().into_string() {
+            Ok(n) => n,
+            Err(_) => continue,
+        };
+        if !name.starts_with("dev-") {
+            continue;
+        }
+        let member_dir = format!("{}/{}", md_dir, name);
+        let member_link = format!("{}/block", member_dir);
+        let member_blk = match fs::read_link(&member_link) {
+            Ok(p) => match p.file_name().and_then(|n| n.to_str()) {
+                Some(n) => n.to_string(),
+                None => continue,
+            },
+            // Member might be removed already.
+            Err(_) => continue,
+        };
+        // The state is gone when member is being removed.
+        let role = match Sysfs::read(&format!("{}/state", member_dir)) {
+            Ok(state) => {
+                let slot = Sysfs::read(&format!("{}/slot", member_dir))
+                    .unwrap_or_else(|_| "none".to_string());
+                member_role(&state, &slot)
+            }
+            Err(_) => MdMemberRole::Unknown,
+        };
+        match BlkInfo::new_skip_extra(&member_blk, sys_root) {
+            Ok(bi) => {
+                blk_info.md_members.push(MdMember {
+                    wwid: bi.wwid.clone(),
+                    blk_path: bi.blk_path.clone(),
+                    role,
+                });
+                blk_info.owners.push(BlkNode::from(bi));
+            }
+            Err(_) => blk_info.md_members.push(MdMember {
+                wwid: String::new(),
+                blk_path: format!("/dev/{}", member_blk),
+                role,
+            }),
+        }
+    }
+    Ok(())
+}
+
+// Support query on these formats:
+//  * md0
+pub(crate) fn blk_info_get_md(
+    blk: &str,
+    sys_root: &SystemRoot,
+) -> Result<BlkInfo, PeripetyError> {
+    let md_dir = sys_root.sysfs_path(&format!("block/{}/md", blk));
+    if !Path::new(&md_dir).exists() {
+        return Err(PeripetyError::BlockNoExists(format!(
+            "MD RAID {} does not exists",
+            blk
+        )));
+    }
+
+    let wwid = match array_uuid(blk, sys_root) {
+        Some(u) => u,
+        None => {
+            return Err(PeripetyError::InternalBug(format!(
+                "md::blk_info_get_md(): Failed to find uuid of {}",
+                blk
+            )));
+        }
+    };
+
+    let mut extension = HashMap::new();
+    for (key, file) in &[
+        ("md_level", "level"),
+        ("md_array_state", "array_state"),
+        ("md_raid_disks", "raid_disks"),
+        ("md_degraded", "degraded"),
+        ("md_metadata_version", "metadata_version"),
+        ("md_sync_action", "sync_action"),
+    ] {
+        if let Ok(v) = Sysfs::read(&format!("{}/{}", md_dir, file)) {
+            extension.insert(key.to_string(), v);
+        }
+    }
+
+    let mut ret = BlkInfo {
+        wwid,
+        blk_type: BlkType::MdRaid,
+        blk_path: format!("/dev/{}", blk),
+        owners: Vec::new(),
+        uuid: None,
+        mount_point: None,
+        mounts: Vec::new(),
+        aliases: Vec::new(),
+        extension,
+        md_members: Vec::new(),
+    };
+    get_members(blk, sys_root, &mut ret)?;
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fixture::Fixture;
+
+    fn add_member(f: &Fixture, md: &str, blk: &str, state: &str, slot: &str) {
+        let dir = format!("/sys/block/{}/md/dev-{}", md, blk);
+        f.symlink(&format!("/sys/block/{}", blk), &format!("{}/block", dir));
+        if !state.is_empty() {
+            f.write(&format!("{}/state", dir), &format!("{}\n", state));
+        }
+        f.write(&format!("{}/slot", dir), &format!("{}\n", slot));
+        f.add_slave(md, blk);
+    }
+
+    #[test]
+    fn test_md_members() {
+        let f = Fixture::new("md");
+        for (i, blk) in ["sda", "sdb", "sdc", "sdd"].iter().enumerate() {
+            f.add_blk(blk, &format!("8:{}", i * 16));
+            f.write(
+                &format!("/sys/block/{}/device/wwid", blk),
+                &format!("naa.600140{}\n", i),
+            );
+        }
+        f.add_blk("md0", "9:0");
+        f.write(
+            "/sys/block/md0/md/uuid",
+            "a1b2c3d4-e5f6-a7b8-c9d0-e1f2a3b4c5d6\n",
+        );
+        f.write("/sys/block/md0/md/level", "raid1\n");
+        f.write("/sys/block/md0/md/array_state", "clean\n");
+        add_member(&f, "md0", "sda", "in_sync", "0");
+        add_member(&f, "md0", "sdb", "faulty", "1");
+        add_member(&f, "md0", "sdc", "spare", "none");
+        // Being removed.
+        add_member(&f, "md0", "sdd", "", "none");
+        let sys_root = &f.sys_root;
+
+        // Member disk is resolved to the array.
+        let blk_info = BlkInfo::new("sda", sys_root).unwrap();
+        assert_eq!(blk_info.wwid, "a1b2c3d4-e5f6-a7b8-c9d0-e1f2a3b4c5d6");
+        assert_eq!(blk_info.blk_type, BlkType::MdRaid);
+        assert_eq!(blk_info.blk_path, "/dev/md0");
+        assert_eq!(
+            blk_info.extension.get("md_level"),
+            Some(&"raid1".to_string())
+        );
+        assert_eq!(blk_info.md_members.len(), 4);
+        assert_eq!(blk_info.owners.len(), 4);
+        for (path, wwid, role) in &[
+            ("/dev/sda", "naa.6001400", MdMemberRole::Active),
+            ("/dev/sdb", "naa.6001401", MdMemberRole::Faulty),
+            ("/dev/sdc", "naa.6001402", MdMemberRole::Spare),
+            ("/dev/sdd", "naa.6001403", MdMemberRole::Unknown),
+        ] {
+            let member = blk_info
+                .md_members
+                .iter()
+                .find(|m| &m.blk_path == path)
+                .unwrap();
+            assert_eq!(&member.wwid, wwid);
+            assert_eq!(&member.role, role);
+        }
+    }
+
+    #[test]
+    fn test_md_uuid_fallback() {
+        let f = Fixture::new("md-uuid");
+        f.add_blk("md1", "9:1");
+        f.mkdir("/sys/block/md1/md");
+        f.symlink(
+            "../../md1",
+            "/dev/disk/by-id/md-uuid-a1b2c3d4:e5f6a7b8:c9d0e1f2:a3b4c5d6",
+        );
+        let blk_info = BlkInfo::new_skip_extra("md1", &f.sys_root).unwrap();
+        assert_eq!(blk_info.wwid, "a1b2c3d4-e5f6-a7b8-c9d0-e1f2a3b4c5d6");
+        assert!(blk_info.md_members.is_empty());
+
+        f.add_blk("md2", "9:2");
+        f.mkdir("/sys/block/md2/md");
+        assert!(BlkInfo::new_skip_extra("md2", &f.sys_root).is_err());
+    }
+
+    #[test]
+    fn test_member_role() {
+        assert_eq!(
+            member_role("in_sync,write_mostly", "0"),
+            MdMemberRole::Active
+        );
+        assert_eq!(member_role("faulty,in_sync", "0"), MdMemberRole::Faulty);
+        assert_eq!(member_role("", "1"), MdMemberRole::Rebuilding);
+        assert_eq!(member_role("spare", "none"), MdMemberRole::Spare);
+        assert_eq!(member_role("", "none"), MdMemberRole::Unknown);
+    }
+}
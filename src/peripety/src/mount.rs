@@ -0,0 +1,506 @@
+use super::blk_info::major_minor_of_dev_t;
+use super::error::PeripetyError;
+use super::sys_root::SystemRoot;
+use super::sysfs::Sysfs;
+
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Listing mount namespaces reads the namespace of every process, cache the
+// list for this many seconds. Namespace created in between is missed until
+// cache expires.
+static MNT_NS_CACHE_TIMEOUT: u64 = 60;
+
+struct MntNsCache {
+    sys_root: SystemRoot,
+    time: Instant,
+    pids: Vec<(String, Option<String>)>,
+}
+
+static MNT_NS_CACHE: Mutex<Option<MntNsCache>> = Mutex::new(None);
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct MountInfo {
+    pub mount_point: String,
+    // Path of the mount root inside the filesystem. It is not '/' for bind
+    // mount or btrfs subvolume.
+    pub root: String,
+    pub fs_type: String,
+    pub source: String,
+    pub mount_options: String,
+    pub super_options: String,
+    // True if either the mount or the filesystem superblock is read-only.
+    pub read_only: bool,
+    // True if the filesystem superblock is read-only, for example remounted
+    // read-only by kernel on error.
+    pub fs_read_only: bool,
+    // Like 'mnt:[4026531840]', None if namespace is unknown.
+    pub mnt_ns: Option<String>,
+}
+
+// The mountinfo escape space, tab, newline and backslash as octal '\040'.
+fn unescape(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut ret: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\'
+            && i + 3 < bytes.len()
+            && bytes[i + 1..i + 4].iter().all(|b| (b'0'..=b'7').contains(b))
+        {
+            let c = bytes[i + 1..i + 4]
+                .iter()
+                .fold(0u32, |acc, b| acc * 8 + u32::from(b - b'0'));
+            ret.push(c as u8);
+            i += 4;
+            continue;
+        }
+        ret.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&ret).to_string()
+}
+
+fn has_ro_flag(options: &str) -> bool {
+    options.split(',').any(|o| o == "ro")
+}
+
+// Line format of mountinfo(documented in proc(5)):
+//  36 35 98:0 /mnt1 /mnt2 rw,noatime master:1 - ext3 /dev/root rw,errors=ro
+// Return the major:minor and parsed MountInfo.
+fn parse_line(
+    line: &str,
+    mnt_ns: &Option<String>,
+) -> Option<(String, MountInfo)> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() < 10 {
+        return None;
+    }
+    // Optional fields are terminated by a single hyphen.
+    let sep = match fields[6..].iter().position(|f| *f == "-") {
+        Some(i) => i + 6,
+        None => return None,
+    };
+    if fields.len() < sep + 4 {
+        return None;
+    }
+    let mount_options = fields[5].to_string();
+    let super_options = fields[sep + 3].to_string();
+    let fs_read_only = has_ro_flag(&super_options);
+    let read_only = has_ro_flag(&mount_options) || fs_read_only;
+    Some((
+        fields[2].to_string(),
+        MountInfo {
+            mount_point: unescape(fields[4]),
+            root: unescape(fields[3]),
+            fs_type: fields[sep + 1].to_string(),
+            source: unescape(fields[sep + 2]),
+            mount_options,
+            super_options,
+            read_only,
+            fs_read_only,
+            mnt_ns: mnt_ns.clone(),
+        },
+    ))
+}
+
+fn mnt_ns_of(pid: &str, sys_root: &SystemRoot) -> Option<String> {
+    let ns_path = sys_root.procfs_path(&format!("{}/ns/mnt", pid));
+    match fs::read_link(&ns_path) {
+        Ok(p) => p.to_str().map(|s| s.to_string()),
+        Err(_) => None,
+    }
+}
+
+// Sorted process IDs in procfs.
+fn all_pids(sys_root: &SystemRoot) -> Vec<u64> {
+    let entries = match fs::read_dir(sys_root.procfs_path("")) {
+        Ok(e) => e,
+        Err(_) => return Vec::new(),
+    };
+    let mut pids: Vec<u64> = entries
+        .flatten()
+        .filter_map(|e| e.file_name().to_str().and_then(|n| n.parse().ok()))
+        .collect();
+    pids.sort();
+    pids
+}
+
+// Process ID for each mount namespace on the system, 'self' first.
+fn mnt_ns_pids(sys_root: &SystemRoot) -> Vec<(String, Option<String>)> {
+    let mut ret = vec![("self".to_string(), mnt_ns_of("self", sys_root))];
+    let mut seen: Vec<String> = Vec::new();
+    if let Some(ref ns) = ret[0].1 {
+        seen.push(ns.clone());
+    } else {
+        // Cannot tell namespaces apart.
+        return ret;
+    }
+    for pid in all_pids(sys_root) {
+        let pid = pid.to_string();
+        // Process might be gone or we lack the privilege.
+        if let Some(ns) = mnt_ns_of(&pid, sys_root) {
+            if !seen.contains(&ns) {
+                seen.push(ns.clone());
+                ret.push((pid, Some(ns)));
+            }
+        }
+    }
+    ret
+}
+
+// Like mnt_ns_pids(), but cached. A reused process ID pointing to other
+// namespace is not noticed until cache expires.
+fn mnt_ns_pids_cached(sys_root: &SystemRoot) -> Vec<(String, Option<String>)> {
+    let mut cache = match MNT_NS_CACHE.lock() {
+        Ok(c) => c,
+        Err(_) => return mnt_ns_pids(sys_root),
+    };
+    if let Some(ref c) = *cache {
+        if c.sys_root == *sys_root
+            && c.time.elapsed() < Duration::from_secs(MNT_NS_CACHE_TIMEOUT)
+        {
+            return c.pids.clone();
+        }
+    }
+    let pids = mnt_ns_pids(sys_root);
+    *cache = Some(MntNsCache {
+        sys_root: sys_root.clone(),
+        time: Instant::now(),
+        pids: pids.clone(),
+    });
+    pids
+}
+
+// The cached process of namespace `mnt_ns` exited, find another process in
+// the same namespace and update the cache. Namespace is dropped from cache if
+// no process left in it.
+fn mnt_ns_pid_replace(
+    pid: &str,
+    mnt_ns: &str,
+    sys_root: &SystemRoot,
+) -> Option<String> {
+    let new_pid = all_pids(sys_root)
+        .into_iter()
+        .map(|p| p.to_string())
+        .find(|p| mnt_ns_of(p, sys_root).as_deref() == Some(mnt_ns));
+    if let Ok(mut cache) = MNT_NS_CACHE.lock() {
+        if let Some(ref mut c) = *cache {
+            if c.sys_root == *sys_root {
+                match new_pid {
+                    Some(ref p) => {
+                        for entry in c.pids.iter_mut() {
+                            if entry.0 == pid {
+                                entry.0 = p.clone();
+                            }
+                        }
+                    }
+                    None => c.pids.retain(|(p, _)| p != pid),
+                }
+            }
+        }
+    }
+    new_pid
+}
+
+fn real_dev_path(path: &str, sys_root: &SystemRoot) -> Option<String> {
+    if !path.starts_with("/dev/") {
+        return None;
+    }
+    match Path::new(&sys_root.real_path(path)).canonicalize() {
+        Ok(p) => p.to_str().map(|s| s.to_string()),
+        Err(_) => None,
+    }
+}
+
+// The 'major:minor' of block device.
+pub(crate) fn major_minor_of_blk(
+    blk_path: &str,
+    sys_root: &SystemRoot,
+) -> Option<String> {
+    let name = match real_dev_path(blk_path, sys_root) {
+        Some(p) => match Path::new(&p).file_name().and_then(|n| n.to_str()) {
+            Some(n) => n.to_string(),
+            None => return None,
+        },
+        None => return None,
+    };
+    Sysfs::read(&sys_root.sysfs_path(&format!("class/block/{}/dev", name)))
+        .ok()
+}
+
+// Find all mounts of specified block. Mount is matched by major:minor, or
+// by the mount source for filesystems using anonymous device like btrfs.
+// When `all_ns` is true, mounts in every mount namespace are included,
+// otherwise only the ones visible to current process.
+pub(crate) fn mounts_of_blk(
+    blk_path: &str,
+    sys_root: &SystemRoot,
+    all_ns: bool,
+) -> Vec<MountInfo> {
+    if all_ns {
+        mounts_in_ns(blk_path, sys_root, mnt_ns_pids_cached(sys_root))
+    } else {
+        let pids = vec![("self".to_string(), mnt_ns_of("self", sys_root))];
+        mounts_in_ns(blk_path, sys_root, pids)
+    }
+}
+
+// Like mounts_of_blk(), but only mounts in namespaces other than the one of
+// current process.
+pub(crate) fn mounts_of_blk_other_ns(
+    blk_path: &str,
+    sys_root: &SystemRoot,
+) -> Vec<MountInfo> {
+    let pids = mnt_ns_pids_cached(sys_root)
+        .into_iter()
+        .filter(|(pid, _)| pid != "self")
+        .collect();
+    mounts_in_ns(blk_path, sys_root, pids)
+}
+
+// Mountinfo of process `pid`, or of other process in the same namespace if
+// `pid` exited.
+fn mountinfo_of(
+    pid: &str,
+    mnt_ns: &Option<String>,
+    sys_root: &SystemRoot,
+) -> Option<String> {
+    let read = |pid: &str| {
+        Sysfs::read(&sys_root.procfs_path(&format!("{}/mountinfo", pid))).ok()
+    };
+    if let Some(c) = read(pid) {
+        return Some(c);
+    }
+    match *mnt_ns {
+        Some(ref ns) if pid != "self" => {
+            mnt_ns_pid_replace(pid, ns, sys_root).and_then(|p| read(&p))
+        }
+        _ => None,
+    }
+}
+
+fn mounts_in_ns(
+    blk_path: &str,
+    sys_root: &SystemRoot,
+    pids: Vec<(String, Option<String>)>,
+) -> Vec<MountInfo> {
+    let mut ret = Vec::new();
+    let major_minor = major_minor_of_blk(blk_path, sys_root);
+    let blk_real_path = real_dev_path(blk_path, sys_root);
+    if major_minor.is_none() && blk_real_path.is_none() {
+        return ret;
+    }
+    for (pid, mnt_ns) in pids {
+        let content = match mountinfo_of(&pid, &mnt_ns, sys_root) {
+            Some(c) => c,
+            None => continue,
+        };
+        for line in content.lines() {
+            let (mm, mnt) = match parse_line(line, &mnt_ns) {
+                Some(r) => r,
+                None => continue,
+            };
+            let matched = major_minor.as_ref() == Some(&mm)
+                || (blk_real_path.is_some()
+                    && real_dev_path(&mnt.source, sys_root) == blk_real_path);
+            if matched && !ret.contains(&mnt) {
+                ret.push(mnt);
+            }
+        }
+    }
+    ret
+}
+
+// Value of mount option like 'upperdir=/var/lib/docker/overlay2/xxx/diff'.
+fn option_value(options: &str, key: &str) -> Option<String> {
+    let prefix = format!("{}=", key);
+    options
+        .split(',')
+        .find(|o| o.starts_with(&prefix))
+        .map(|o| o[prefix.len()..].to_string())
+}
+
+fn is_under(path: &str, mount_point: &str) -> bool {
+    mount_point == "/"
+        || path == mount_point
+        || path.starts_with(&format!("{}/", mount_point))
+}
+
+// Mount holding the path, found by longest mount point. Used when files are
+// not available, like captured sysfs and procfs tree.
+fn mount_of_path(
+    path: &str,
+    sys_root: &SystemRoot,
+) -> Result<Option<(String, MountInfo)>, PeripetyError> {
+    let content = Sysfs::read(&sys_root.procfs_path("self/mountinfo"))?;
+    let mut ret: Option<(String, MountInfo)> = None;
+    for line in content.lines() {
+        let (mm, mnt) = match parse_line(line, &None) {
+            Some(r) => r,
+            None => continue,
+        };
+        if !is_under(path, &mnt.mount_point) {
+            continue;
+        }
+        // Later mount hides the earlier one on the same mount point.
+        let is_longer = match ret {
+            Some((_, ref m)) => mnt.mount_point.len() >= m.mount_point.len(),
+            None => true,
+        };
+        if is_longer {
+            ret = Some((mm, mnt));
+        }
+    }
+    Ok(ret)
+}
+
+// Mount using specified anonymous device.
+fn mount_of_major_minor(
+    major_minor: &str,
+    sys_root: &SystemRoot,
+) -> Result<Option<MountInfo>, PeripetyError> {
+    let content = Sysfs::read(&sys_root.procfs_path("self/mountinfo"))?;
+    for line in content.lines() {
+        if let Some((mm, mnt)) = parse_line(line, &None) {
+            if mm == major_minor {
+                return Ok(Some(mnt));
+            }
+        }
+    }
+    Ok(None)
+}
+
+// Find the 'major:minor' of block device holding specified file or folder.
+// The btrfs and overlay are using anonymous device(major 0) for st_dev, hence
+// mount table is used for resolving the real block.
+// For `sys_root` other than the live system, the path is not checked but
+// resolved by the mount table of `sys_root`.
+pub(crate) fn major_minor_of_path(
+    path: &str,
+    sys_root: &SystemRoot,
+) -> Result<String, PeripetyError> {
+    let mnt = if *sys_root == SystemRoot::default() {
+        let major_minor = match fs::metadata(path) {
+            Ok(m) => major_minor_of_dev_t(m.dev()),
+            Err(e) => {
+                return Err(PeripetyError::BlockNoExists(format!(
+                    "Failed to stat path '{}': {}",
+                    path, e
+                )));
+            }
+        };
+        if !major_minor.starts_with("0:") {
+            return Ok(major_minor);
+        }
+        mount_of_major_minor(&major_minor, sys_root)?
+    } else {
+        match mount_of_path(path, sys_root)? {
+            Some((ref mm, _)) if !mm.starts_with("0:") => {
+                return Ok(mm.clone())
+            }
+            Some((_, mnt)) => Some(mnt),
+            None => None,
+        }
+    };
+    let mnt = match mnt {
+        Some(m) => m,
+        None => {
+            return Err(PeripetyError::NoSupport(format!(
+                "Path '{}' is not on any block device",
+                path
+            )))
+        }
+    };
+    // btrfs use real block as mount source.
+    if mnt.source.starts_with("/dev/") {
+        if let Some(m) = major_minor_of_blk(&mnt.source, sys_root) {
+            return Ok(m);
+        }
+    }
+    // Changes to overlay are stored in upper layer.
+    if mnt.fs_type == "overlay" {
+        if let Some(dir) = option_value(&mnt.super_options, "upperdir") {
+            return major_minor_of_path(&dir, sys_root);
+        }
+    }
+    Err(PeripetyError::NoSupport(format!(
+        "Path '{}' is on '{}' filesystem which has no backing block",
+        path, mnt.fs_type
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fixture::Fixture;
+
+    static MOUNTINFO: &str = "\
+22 1 8:1 / / rw,relatime - xfs /dev/sda1 rw,attr2
+40 22 0:45 /@ /srv rw,relatime - btrfs /dev/sdb rw,space_cache
+41 22 0:46 / /tmp rw,nosuid - tmpfs tmpfs rw,size=1024k
+42 40 8:32 / /srv/data rw,relatime - ext4 /dev/sdc rw
+";
+
+    #[test]
+    fn test_major_minor_of_path() {
+        let f = Fixture::new("mount");
+        f.write("/proc/self/mountinfo", MOUNTINFO);
+        f.add_blk("sdb", "8:16");
+        let sys_root = &f.sys_root;
+        assert_eq!(
+            major_minor_of_path("/var/lib/a.img", sys_root).unwrap(),
+            "8:1"
+        );
+        assert_eq!(
+            major_minor_of_path("/srv/data/a.img", sys_root).unwrap(),
+            "8:32"
+        );
+        // btrfs is resolved by mount source instead of anonymous device.
+        assert_eq!(
+            major_minor_of_path("/srv/a.img", sys_root).unwrap(),
+            "8:16"
+        );
+        assert!(major_minor_of_path("/tmp/a.img", sys_root).is_err());
+        // Not a sub folder of '/srv'.
+        assert_eq!(
+            major_minor_of_path("/srvx/a.img", sys_root).unwrap(),
+            "8:1"
+        );
+    }
+    #[test]
+    fn test_mounts_of_blk_other_ns() {
+        // Process 100 and 101 share a container namespace.
+        let f = Fixture::new("mount-ns");
+        f.add_blk("sdc", "8:32");
+        f.write("/proc/self/mountinfo", MOUNTINFO);
+        f.symlink("mnt:[1]", "/proc/self/ns/mnt");
+        f.symlink("mnt:[1]", "/proc/1/ns/mnt");
+        for pid in &["100", "101"] {
+            f.write(
+                &format!("/proc/{}/mountinfo", pid),
+                "50 49 8:32 / /data rw - ext4 /dev/sdc rw\n",
+            );
+            f.symlink("mnt:[2]", &format!("/proc/{}/ns/mnt", pid));
+        }
+        let sys_root = &f.sys_root;
+
+        let mnts = mounts_of_blk("/dev/sdc", sys_root, true);
+        let mnt_pnts: Vec<&str> =
+            mnts.iter().map(|m| m.mount_point.as_str()).collect();
+        assert_eq!(mnt_pnts, vec!["/srv/data", "/data"]);
+        assert_eq!(mnts[1].mnt_ns, Some("mnt:[2]".to_string()));
+        let mnts = mounts_of_blk_other_ns("/dev/sdc", sys_root);
+        assert_eq!(mnts.len(), 1);
+        assert_eq!(mnts[0].mount_point, "/data");
+
+        // Cached process exited, the other one in namespace is used.
+        fs::remove_dir_all(f.path("/proc/100")).unwrap();
+        let mnts = mounts_of_blk_other_ns("/dev/sdc", sys_root);
+        assert_eq!(mnts.len(), 1);
+        assert_eq!(mnts[0].mount_point, "/data");
+    }
+}
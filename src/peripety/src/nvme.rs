@@ -0,0 +1,147 @@
+use super::blk_info::{BlkInfo, BlkType};
+use super::error::PeripetyError;
+use super::scsi::pretty_wwid;
+use super::sys_root::SystemRoot;
+use super::sysfs::Sysfs;
+
+use regex::Regex;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+pub(crate) fn is_nvme_blk(blk: &str) -> bool {
+    Regex::new(r"^nvme[0-9]+(?:c[0-9]+)?n[0-9]+$")
+        .expect("BUG: is_nvme_blk()")
+        // ^ We never panic as above regex is valid.
+        .is_match(blk)
+}
+
+// Convert 'nvme0:1'(controller name and namespace ID) to namespace block
+// name.
+pub(crate) fn ctrl_ns_id_to_blk_name(
+    ctrl_name: &str,
+    ns_id: &str,
+    sys_root: &SystemRoot,
+) -> Result<String, PeripetyError> {
+    let ctrl_dir = sys_root.sysfs_path(&format!("class/nvme/{}", ctrl_name));
+    let entries = match fs::read_dir(&ctrl_dir) {
+        Ok(e) => e,
+        Err(_) => {
+            return Err(PeripetyError::BlockNoExists(format!(
+                "NVMe controller {} does not exists",
+                ctrl_name
+            )));
+        }
+    };
+    for entry in entries {
+        let name = match entry {
+            Ok(e) => match e.file_name().into_string() {
+                Ok(n) => n,
+                Err(_) => continue,
+            },
+            Err(_) => continue,
+        };
+        if !is_nvme_blk(&name) {
+            continue;
+        }
+        let nsid_path = format!("{}/{}/nsid", ctrl_dir, name);
+        if let Ok(cur_ns_id) = Sysfs::read(&nsid_path) {
+            if cur_ns_id == ns_id {
+                return Ok(head_blk_name(&name));
+            }
+        }
+    }
+    Err(PeripetyError::BlockNoExists(format!(
+        "NVMe namespace {} does not exists on controller {}",
+        ns_id, ctrl_name
+    )))
+}
+
+// With NVMe native multipath, the namespace of each controller is
+// hidden as 'nvme0c1n1' and user should use the 'nvme0n1' instead.
+fn head_blk_name(blk: &str) -> String {
+    Regex::new(r"^(nvme[0-9]+)c[0-9]+(n[0-9]+)$")
+        .expect("BUG: head_blk_name()")
+        // ^ We never panic as above regex is valid.
+        .replace(blk, "$1$2")
+        .to_string()
+}
+
+fn wwid_of_ns(blk: &str, sys_root: &SystemRoot) -> Option<String> {
+    let wwid_path = sys_root.sysfs_path(&format!("block/{}/wwid", blk));
+    if let Ok(w) = Sysfs::read(&wwid_path) {
+        return Some(pretty_wwid(&w));
+    }
+    // Old kernel does not have 'wwid' sysfs file.
+    for key in &["nguid", "eui"] {
+        let path = sys_root.sysfs_path(&format!("block/{}/{}", blk, key));
+        if let Ok(id) = Sysfs::read(&path) {
+            let id = id.replace(" ", "").replace("-", "");
+            if !id.is_empty() && id.chars().any(|c| c != '0') {
+                return Some(format!("eui.{}", id));
+            }
+        }
+    }
+    None
+}
+
+// Support query on these formats:
+//  * nvme0n1
+//  * nvme0c1n1
+pub(crate) fn blk_info_get_nvme(
+    blk: &str,
+    sys_root: &SystemRoot,
+) -> Result<BlkInfo, PeripetyError> {
+    let name = head_blk_name(blk);
+    let sysfs_dir = sys_root.sysfs_path(&format!("block/{}", name));
+    if !Path::new(&sysfs_dir).exists() {
+        return Err(PeripetyError::BlockNoExists(format!(
+            "NVMe namespace {} does not exists",
+            blk
+        )));
+    }
+    let wwid = match wwid_of_ns(&name, sys_root) {
+        Some(w) => w,
+        None => {
+            return Err(PeripetyError::InternalBug(format!(
+                "nvme::blk_info_get_nvme(): Failed to find wwid of {}",
+                blk
+            )));
+        }
+    };
+
+    let mut extension = HashMap::new();
+    if let Ok(ns_id) = Sysfs::read(&format!("{}/nsid", sysfs_dir)) {
+        extension.insert("nvme_ns_id".to_string(), ns_id);
+    }
+    for key in &["eui", "nguid", "uuid"] {
+        if let Ok(v) = Sysfs::read(&format!("{}/{}", sysfs_dir, key)) {
+            extension.insert(format!("nvme_{}", key), v);
+        }
+    }
+    // The 'device' is controller or subsystem(native NVMe multipath).
+    let ctrl_dir = format!("{}/device", sysfs_dir);
+    if let Ok(p) = Path::new(&ctrl_dir).canonicalize() {
+        if let Some(n) = p.file_name().and_then(|n| n.to_str()) {
+            extension.insert("nvme_ctrl".to_string(), n.to_string());
+        }
+    }
+    for key in &["model", "serial", "firmware_rev", "transport"] {
+        if let Ok(v) = Sysfs::read(&format!("{}/{}", ctrl_dir, key)) {
+            extension.insert(key.to_string(), v.trim().to_string());
+        }
+    }
+
+    Ok(BlkInfo {
+        wwid,
+        blk_type: BlkType::Nvme,
+        blk_path: format!("/dev/{}", &name),
+        owners: Vec::new(),
+        uuid: None,
+        mount_point: None,
+        mounts: Vec::new(),
+        aliases: Vec::new(),
+        extension,
+        md_members: Vec::new(),
+    })
+}
@@ -0,0 +1,121 @@
+use super::blk_info::{BlkInfo, BlkNode, BlkType};
+use super::devfs::Devfs;
+use super::error::PeripetyError;
+use super::sys_root::SystemRoot;
+use super::sysfs::Sysfs;
+
+use std::collections::HashMap;
+use std::path::Path;
+
+pub(crate) fn is_partition(blk: &str, sys_root: &SystemRoot) -> bool {
+    Path::new(&sys_root.sysfs_path(&format!("class/block/{}/partition", blk)))
+        .exists()
+}
+
+// Partition name is not always parent name with number suffix, for example
+// 'nvme0n1p1' and 'mmcblk0p1'. The sysfs folder of partition is always
+// sub-folder of its parent.
+fn parent_blk_name(
+    blk: &str,
+    sys_root: &SystemRoot,
+) -> Result<String, PeripetyError> {
+    let sysfs_dir = sys_root.sysfs_path(&format!("class/block/{}", blk));
+    let real_dir = match Path::new(&sysfs_dir).canonicalize() {
+        Ok(p) => p,
+        Err(e) => {
+            return Err(PeripetyError::InternalBug(format!(
+                "partition::parent_blk_name(): Failed to canonicalize {}: {}",
+                sysfs_dir, e
+            )));
+        }
+    };
+    match real_dir
+        .parent()
+        .and_then(|p| p.file_name())
+        .and_then(|n| n.to_str())
+    {
+        Some(n) => Ok(n.to_string()),
+        None => Err(PeripetyError::InternalBug(format!(
+            "partition::parent_blk_name(): Failed to find parent of {}",
+            blk
+        ))),
+    }
+}
+
+// Save partition number, start sector, size, PARTUUID and PARTLABEL into
+// extension.
+pub(crate) fn part_extension(
+    blk: &str,
+    blk_path: &str,
+    sys_root: &SystemRoot,
+) -> HashMap<String, String> {
+    let mut ret = HashMap::new();
+    let sysfs_dir = sys_root.sysfs_path(&format!("class/block/{}", blk));
+    for (key, file) in &[
+        ("part_number", "partition"),
+        ("part_start", "start"),
+        ("part_size", "size"),
+    ] {
+        if let Ok(v) = Sysfs::read(&format!("{}/{}", sysfs_dir, file)) {
+            ret.insert(key.to_string(), v);
+        }
+    }
+    ret.extend(part_link_extension(blk_path, sys_root));
+    // PARTNAME of uevent is the PARTLABEL of GPT.
+    if !ret.contains_key("part_label") {
+        if let Ok(uevent) = Sysfs::read(&format!("{}/uevent", sysfs_dir)) {
+            for line in uevent.lines() {
+                if let Some(n) = line.strip_prefix("PARTNAME=") {
+                    ret.insert("part_label".to_string(), n.to_string());
+                }
+            }
+        }
+    }
+    ret
+}
+
+// Save PARTUUID and PARTLABEL from udev symbolic links into extension.
+pub(crate) fn part_link_extension(
+    blk_path: &str,
+    sys_root: &SystemRoot,
+) -> HashMap<String, String> {
+    let mut ret = HashMap::new();
+    for (key, dir) in &[
+        ("part_uuid", "disk/by-partuuid"),
+        ("part_label", "disk/by-partlabel"),
+    ] {
+        if let Some(v) = Devfs::link_names(dir, blk_path, sys_root).pop() {
+            ret.insert(key.to_string(), v);
+        }
+    }
+    ret
+}
+
+// Support query on these formats:
+//  * sda1
+//  * nvme0n1p1
+//  * vda1
+//  * md0p1
+pub(crate) fn blk_info_get_partition(
+    blk: &str,
+    sys_root: &SystemRoot,
+) -> Result<BlkInfo, PeripetyError> {
+    let parent_info =
+        BlkInfo::new_skip_extra(&parent_blk_name(blk, sys_root)?, sys_root)?;
+    let part = Sysfs::read(
+        &sys_root.sysfs_path(&format!("class/block/{}/partition", blk)),
+    )?;
+    let blk_path = format!("/dev/{}", blk);
+    Ok(BlkInfo {
+        wwid: format!("{}-part{}", &parent_info.wwid, part),
+        blk_type: BlkType::Partition,
+        extension: part_extension(blk, &blk_path, sys_root),
+        blk_path,
+        owners: vec![BlkNode::from(parent_info)],
+        uuid: None,
+        mount_point: None,
+        mounts: Vec::new(),
+        aliases: Vec::new(),
+        md_members: Vec::new(),
+    })
+}
@@ -1,15 +1,19 @@
 use super::blk_info::{BlkInfo, BlkType};
 use super::error::PeripetyError;
+use super::sys_root::SystemRoot;
 use super::sysfs::Sysfs;
 
 use regex::Regex;
+use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 
 pub(crate) fn scsi_id_to_blk_name(
     scsi_id: &str,
+    sys_root: &SystemRoot,
 ) -> Result<String, PeripetyError> {
-    let sysfs_path = format!("/sys/class/scsi_disk/{}/device/block", scsi_id);
+    let sysfs_path = sys_root
+        .sysfs_path(&format!("class/scsi_disk/{}/device/block", scsi_id));
     if !Path::new(&sysfs_path).exists() {
         return Err(PeripetyError::BlockNoExists(format!(
             "SCSI ID {} does not exists",
@@ -55,42 +59,21 @@ pub(crate) fn pretty_wwid(wwid: &str) -> String {
 // Support query on these formats:
 //  * 4:0:0:1
 //  * sda
-//  * sda1
-pub(crate) fn blk_info_get_scsi(blk: &str) -> Result<BlkInfo, PeripetyError> {
+pub(crate) fn blk_info_get_scsi(
+    blk: &str,
+    sys_root: &SystemRoot,
+) -> Result<BlkInfo, PeripetyError> {
     let name;
 
-    // Check if partition
-    if let Ok(reg) = Regex::new("^(sd[a-z]+)([0-9]+)$") {
-        if let Some(cap) = reg.captures(blk) {
-            let name = cap.get(1)
-                .expect("BUG: blk_info_get_scsi()")
-                // ^ We never panic as above regex is valid.
-                .as_str();
-            let part = cap.get(2)
-                .expect("BUG: blk_info_get_scsi()")
-                // ^ We never panic as above regex is valid.
-                .as_str();
-            let blk_info = blk_info_get_scsi(&name)?;
-            return Ok(BlkInfo {
-                wwid: format!("{}-part{}", blk_info.wwid, part),
-                blk_type: BlkType::Partition,
-                blk_path: format!("/dev/{}", &blk),
-                owners_wwids: vec![blk_info.wwid],
-                owners_types: vec![BlkType::Scsi],
-                owners_paths: vec![blk_info.blk_path],
-                uuid: None,
-                mount_point: None,
-            });
-        }
-    }
-
     // Try 4:0:0:1 format
-    let mut sysfs_path = format!("/sys/class/scsi_disk/{}/device/wwid", &blk);
+    let mut sysfs_path = sys_root
+        .sysfs_path(&format!("class/scsi_disk/{}/device/wwid", &blk));
     if Path::new(&sysfs_path).exists() {
-        name = scsi_id_to_blk_name(blk)?;
+        name = scsi_id_to_blk_name(blk, sys_root)?;
     } else {
         // Try sda format
-        sysfs_path = format!("/sys/block/{}/device/wwid", &blk);
+        sysfs_path =
+            sys_root.sysfs_path(&format!("block/{}/device/wwid", &blk));
         name = blk.to_string();
     }
 
@@ -99,11 +82,13 @@ pub(crate) fn blk_info_get_scsi(blk: &str) -> Result<BlkInfo, PeripetyError> {
             wwid: pretty_wwid(&Sysfs::read(&sysfs_path)?),
             blk_type: BlkType::Scsi,
             blk_path: format!("/dev/{}", &name),
-            owners_wwids: Vec::new(),
-            owners_types: Vec::new(),
-            owners_paths: Vec::new(),
+            owners: Vec::new(),
             uuid: None,
             mount_point: None,
+            mounts: Vec::new(),
+            aliases: Vec::new(),
+            extension: HashMap::new(),
+            md_members: Vec::new(),
         });
     }
 
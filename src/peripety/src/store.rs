@@ -0,0 +1,430 @@
+// Storage of events generated by peripetyd, shared by the daemon for saving
+// and by `prpt` for querying and monitoring.
+//
+// Two backends are provided:
+//  * JournaldStore:    systemd journal entries with `IS_PERIPETY=TRUE`,
+//                      requires the `journald` feature.
+//  * JsonFileStore:    append-only file with one JSON event per line,
+//                      rotated by size and age, for hosts with volatile
+//                      journal.
+
+use super::error::PeripetyError;
+use super::event::StorageEvent;
+
+use chrono::{DateTime, Utc};
+#[cfg(feature = "journald")]
+use sdjournal;
+use std::collections::VecDeque;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::thread::sleep;
+use std::time::Duration;
+
+pub trait EventStore {
+    fn save(&mut self, event: &StorageEvent) -> Result<(), PeripetyError>;
+
+    // Return saved events on or newer than `since`(microseconds since
+    // 1970-01-01 UTC) in the order of saving.
+    fn query(
+        &mut self,
+        since: Option<u64>,
+    ) -> Result<Vec<StorageEvent>, PeripetyError>;
+
+    // Block till next event is saved. Only events saved after the first
+    // call are returned.
+    fn monitor(&mut self) -> Result<StorageEvent, PeripetyError>;
+}
+
+// The Display of SdJournalError is broken, use Debug instead.
+#[cfg(feature = "journald")]
+fn journal_error(e: sdjournal::SdJournalError) -> PeripetyError {
+    PeripetyError::StoreError(format!("Journal error: {:?}", e))
+}
+
+fn io_error(path: &str, e: ::std::io::Error) -> PeripetyError {
+    PeripetyError::StoreError(format!("Failed to access {}: {}", path, e))
+}
+
+fn timestamp_usec(event: &StorageEvent) -> Option<i64> {
+    match DateTime::parse_from_rfc3339(&event.timestamp) {
+        Ok(t) => Some(
+            t.timestamp() * 1_000_000 + t.timestamp_subsec_micros() as i64,
+        ),
+        Err(_) => None,
+    }
+}
+
+fn now_usec() -> i64 {
+    let now = Utc::now();
+    now.timestamp() * 1_000_000 + now.timestamp_subsec_micros() as i64
+}
+
+#[cfg(feature = "journald")]
+pub struct JournaldStore {
+    // Journal opened by monitor().
+    journal: Option<sdjournal::Journal>,
+}
+
+#[cfg(feature = "journald")]
+impl Default for JournaldStore {
+    fn default() -> JournaldStore {
+        JournaldStore::new()
+    }
+}
+
+#[cfg(feature = "journald")]
+impl JournaldStore {
+    pub fn new() -> JournaldStore {
+        JournaldStore { journal: None }
+    }
+
+    fn open_journal() -> Result<sdjournal::Journal, PeripetyError> {
+        let mut journal = sdjournal::Journal::new().map_err(journal_error)?;
+        journal
+            .add_match("IS_PERIPETY=TRUE")
+            .map_err(journal_error)?;
+        Ok(journal)
+    }
+}
+
+#[cfg(feature = "journald")]
+impl EventStore for JournaldStore {
+    fn save(&mut self, event: &StorageEvent) -> Result<(), PeripetyError> {
+        let mut logs = Vec::new();
+        logs.push(("IS_PERIPETY".to_string(), "TRUE".to_string()));
+        logs.push((
+            "PRIORITY".to_string(),
+            format!("{}", event.severity as u8),
+        ));
+        if !event.msg.is_empty() {
+            logs.push(("MESSAGE".to_string(), event.msg.clone()));
+        }
+        logs.push(("DEV_WWID".to_string(), event.dev_wwid.clone()));
+        logs.push(("DEV_PATH".to_string(), event.dev_path.clone()));
+        for owners_wwid in &event.owners_wwids {
+            logs.push(("OWNERS_WWIDS".to_string(), owners_wwid.clone()));
+        }
+        for owners_path in &event.owners_paths {
+            logs.push(("OWNERS_PATHS".to_string(), owners_path.clone()));
+        }
+        for (key, value) in &event.extension {
+            logs.push((
+                format!("EXT_{}", key.to_uppercase()),
+                value.clone(),
+            ));
+        }
+        logs.push(("EVENT_TYPE".to_string(), event.event_type.clone()));
+        logs.push(("EVENT_ID".to_string(), event.event_id.clone()));
+        logs.push((
+            "ROOT_CAUSE_EVENT_ID".to_string(),
+            event.root_cause_event_id.clone(),
+        ));
+        logs.push((
+            "PARENT_EVENT_ID".to_string(),
+            event.parent_event_id.clone(),
+        ));
+        logs.push(("SUB_SYSTEM".to_string(), event.sub_system.to_string()));
+        logs.push(("JSON".to_string(), event.to_json_string()?));
+        sdjournal::send_journal_list(&logs).map_err(journal_error)
+    }
+
+    fn query(
+        &mut self,
+        since: Option<u64>,
+    ) -> Result<Vec<StorageEvent>, PeripetyError> {
+        let mut journal = JournaldStore::open_journal()?;
+        // We never want to block, so set the timeout to 0
+        journal.timeout_us = 0;
+        if let Some(since) = since {
+            journal.seek_realtime_usec(since).map_err(journal_error)?;
+        }
+        let mut ret = Vec::new();
+        for entry in &mut journal {
+            let entry = entry.map_err(journal_error)?;
+            if let Some(j) = entry.get("JSON") {
+                ret.push(StorageEvent::from_json_string(j)?);
+            }
+        }
+        Ok(ret)
+    }
+
+    fn monitor(&mut self) -> Result<StorageEvent, PeripetyError> {
+        if self.journal.is_none() {
+            let mut journal = JournaldStore::open_journal()?;
+            // Wait forever.
+            journal.timeout_us = u64::MAX;
+            journal.seek_tail().map_err(journal_error)?;
+            self.journal = Some(journal);
+        }
+        let journal = match self.journal {
+            Some(ref mut j) => j,
+            None => {
+                return Err(PeripetyError::InternalBug(
+                    "JournaldStore::monitor(): journal not opened".to_string(),
+                ))
+            }
+        };
+        loop {
+            match journal.next() {
+                Some(Ok(entry)) => {
+                    if let Some(j) = entry.get("JSON") {
+                        return StorageEvent::from_json_string(j);
+                    }
+                }
+                Some(Err(e)) => return Err(journal_error(e)),
+                // Woke up without new entry, avoid spinning on a journal
+                // which keeps waking us up.
+                None => sleep(Duration::from_secs(1)),
+            }
+        }
+    }
+}
+
+pub struct JsonFileStore {
+    pub path: String,
+    // Rotate the file once reached this size in bytes, 0 means no limit.
+    pub max_size: u64,
+    // Rotate the file once its first event is older than this many seconds,
+    // 0 means no limit.
+    pub max_age: u64,
+    // Number of rotated files to keep as `<path>.1`, `<path>.2`, etc.
+    pub rotate_count: u32,
+    // Inode and timestamp of first event of current file.
+    first_event: Option<(u64, i64)>,
+    // Inode and offset of current file for monitor().
+    follow: Option<(u64, u64)>,
+    pending: VecDeque<StorageEvent>,
+}
+
+impl JsonFileStore {
+    pub fn new(path: &str) -> JsonFileStore {
+        JsonFileStore {
+            path: path.to_string(),
+            max_size: 10 * 1024 * 1024,
+            max_age: 30 * 24 * 60 * 60,
+            rotate_count: 5,
+            first_event: None,
+            follow: None,
+            pending: VecDeque::new(),
+        }
+    }
+
+    fn rotated_path(&self, index: u32) -> String {
+        format!("{}.{}", self.path, index)
+    }
+
+    fn first_event_usec(&mut self, inode: u64) -> Option<i64> {
+        if let Some((i, t)) = self.first_event {
+            if i == inode {
+                return Some(t);
+            }
+        }
+        let fd = File::open(&self.path).ok()?;
+        let mut line = String::new();
+        BufReader::new(fd).read_line(&mut line).ok()?;
+        let t = timestamp_usec(&StorageEvent::from_json_string(&line).ok()?)?;
+        self.first_event = Some((inode, t));
+        Some(t)
+    }
+
+    fn rotate_if_needed(&mut self) -> Result<(), PeripetyError> {
+        let metadata = match fs::metadata(&self.path) {
+            Ok(m) => m,
+            Err(_) => return Ok(()),
+        };
+        let mut rotate = self.max_size != 0 && metadata.len() >= self.max_size;
+        if !rotate && self.max_age != 0 {
+            if let Some(t) = self.first_event_usec(metadata.ino()) {
+                rotate = now_usec() - t >= self.max_age as i64 * 1_000_000;
+            }
+        }
+        if !rotate {
+            return Ok(());
+        }
+        self.first_event = None;
+        if self.rotate_count == 0 {
+            return fs::remove_file(&self.path)
+                .map_err(|e| io_error(&self.path, e));
+        }
+        for i in (1..self.rotate_count).rev() {
+            let old = self.rotated_path(i);
+            if Path::new(&old).exists() {
+                fs::rename(&old, self.rotated_path(i + 1))
+                    .map_err(|e| io_error(&old, e))?;
+            }
+        }
+        fs::rename(&self.path, self.rotated_path(1))
+            .map_err(|e| io_error(&self.path, e))
+    }
+
+    // Read complete lines appended since last read.
+    fn read_new(&mut self) -> Result<(), PeripetyError> {
+        let metadata = match fs::metadata(&self.path) {
+            Ok(m) => m,
+            Err(_) => {
+                // Read from the beginning once created.
+                self.follow = Some((0, 0));
+                return Ok(());
+            }
+        };
+        let (inode, mut offset) = match self.follow {
+            // Skip existing events.
+            None => (metadata.ino(), metadata.len()),
+            Some((i, o)) => {
+                // Rotated or truncated, start from the beginning.
+                if i != metadata.ino() || metadata.len() < o {
+                    (metadata.ino(), 0)
+                } else {
+                    (i, o)
+                }
+            }
+        };
+        if metadata.len() > offset {
+            let mut fd =
+                File::open(&self.path).map_err(|e| io_error(&self.path, e))?;
+            fd.seek(SeekFrom::Start(offset))
+                .map_err(|e| io_error(&self.path, e))?;
+            let mut data = Vec::new();
+            fd.read_to_end(&mut data)
+                .map_err(|e| io_error(&self.path, e))?;
+            // The last line might still be in writing.
+            if let Some(end) = data.iter().rposition(|b| *b == b'\n') {
+                for line in String::from_utf8_lossy(&data[..end]).lines() {
+                    if let Ok(event) = StorageEvent::from_json_string(line) {
+                        self.pending.push_back(event);
+                    }
+                }
+                offset += end as u64 + 1;
+            }
+        }
+        self.follow = Some((inode, offset));
+        Ok(())
+    }
+}
+
+impl EventStore for JsonFileStore {
+    fn save(&mut self, event: &StorageEvent) -> Result<(), PeripetyError> {
+        self.rotate_if_needed()?;
+        if let Some(dir) = Path::new(&self.path).parent() {
+            if !dir.as_os_str().is_empty() && !dir.exists() {
+                fs::create_dir_all(dir)
+                    .map_err(|e| io_error(&self.path, e))?;
+            }
+        }
+        let mut line = event.to_json_string()?;
+        line.push('\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut fd| fd.write_all(line.as_bytes()))
+            .map_err(|e| io_error(&self.path, e))
+    }
+
+    fn query(
+        &mut self,
+        since: Option<u64>,
+    ) -> Result<Vec<StorageEvent>, PeripetyError> {
+        // Oldest file first. Rotated files are read regardless of current
+        // `rotate_count`.
+        let mut paths = Vec::new();
+        let mut i = 1;
+        while Path::new(&self.rotated_path(i)).exists() {
+            paths.insert(0, self.rotated_path(i));
+            i += 1;
+        }
+        if Path::new(&self.path).exists() {
+            paths.push(self.path.clone());
+        }
+
+        let mut ret = Vec::new();
+        for path in &paths {
+            let fd = File::open(path).map_err(|e| io_error(path, e))?;
+            for line in BufReader::new(fd).lines() {
+                let line = line.map_err(|e| io_error(path, e))?;
+                // Skip line corrupted by crash or power lost.
+                let event = match StorageEvent::from_json_string(&line) {
+                    Ok(e) => e,
+                    Err(_) => continue,
+                };
+                if let Some(since) = since {
+                    match timestamp_usec(&event) {
+                        Some(t) if t >= since as i64 => (),
+                        _ => continue,
+                    }
+                }
+                ret.push(event);
+            }
+        }
+        Ok(ret)
+    }
+
+    fn monitor(&mut self) -> Result<StorageEvent, PeripetyError> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Ok(event);
+            }
+            let is_first = self.follow.is_none();
+            self.read_new()?;
+            if !is_first && self.pending.is_empty() {
+                sleep(Duration::from_secs(1));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fixture::Fixture;
+
+    fn event(index: u32) -> StorageEvent {
+        StorageEvent {
+            msg: format!("{}", index),
+            timestamp: format!("2018-03-02T14:13:2{}.000000+00:00", index),
+            ..Default::default()
+        }
+    }
+
+    fn msgs(events: &[StorageEvent]) -> Vec<&str> {
+        events.iter().map(|e| e.msg.as_str()).collect()
+    }
+
+    #[test]
+    fn test_json_file_store_rotate() {
+        let f = Fixture::new("store");
+        let path = f.path("events.json");
+        let path = path.as_str();
+
+        let mut store = JsonFileStore::new(path);
+        // Rotate on every save except the first.
+        store.max_size = 1;
+        store.max_age = 0;
+        store.rotate_count = 3;
+        for i in 0..5 {
+            store.save(&event(i)).unwrap();
+        }
+        assert!(Path::new(&format!("{}.3", path)).exists());
+        assert!(!Path::new(&format!("{}.4", path)).exists());
+        assert_eq!(
+            fs::read_to_string(format!("{}.1", path)).unwrap(),
+            format!("{}\n", event(3).to_json_string().unwrap())
+        );
+
+        // The oldest event was dropped by rotation.
+        let events = store.query(None).unwrap();
+        assert_eq!(msgs(&events), vec!["1", "2", "3", "4"]);
+
+        // 2018-03-02T14:13:23Z
+        let events = store.query(Some(1_520_000_003_000_000)).unwrap();
+        assert_eq!(msgs(&events), vec!["3", "4"]);
+
+        // Rotated files beyond current rotate_count are still read.
+        store.rotate_count = 1;
+        let events = store.query(None).unwrap();
+        assert_eq!(msgs(&events), vec!["1", "2", "3", "4"]);
+    }
+}
@@ -0,0 +1,69 @@
+// Holds the root folders of sysfs, devfs and procfs used for block
+// information lookup. The default is the live system, but all of them could
+// point to a captured directory tree for offline debugging.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SystemRoot {
+    pub sysfs: String,
+    pub devfs: String,
+    pub procfs: String,
+}
+
+impl Default for SystemRoot {
+    fn default() -> SystemRoot {
+        SystemRoot {
+            sysfs: "/sys".to_string(),
+            devfs: "/dev".to_string(),
+            procfs: "/proc".to_string(),
+        }
+    }
+}
+
+impl SystemRoot {
+    // Use `<root>/sys`, `<root>/dev` and `<root>/proc`.
+    pub fn new(root: &str) -> SystemRoot {
+        let root = root.trim_end_matches('/');
+        SystemRoot {
+            sysfs: format!("{}/sys", root),
+            devfs: format!("{}/dev", root),
+            procfs: format!("{}/proc", root),
+        }
+    }
+
+    pub fn sysfs_path(&self, sub_path: &str) -> String {
+        format!("{}/{}", self.sysfs, sub_path.trim_start_matches('/'))
+    }
+
+    pub fn devfs_path(&self, sub_path: &str) -> String {
+        format!("{}/{}", self.devfs, sub_path.trim_start_matches('/'))
+    }
+
+    pub fn procfs_path(&self, sub_path: &str) -> String {
+        format!("{}/{}", self.procfs, sub_path.trim_start_matches('/'))
+    }
+
+    // Convert path like `/dev/sda` or `/sys/block/sda` to the path
+    // under current root. Other paths are returned untouched.
+    pub fn real_path(&self, path: &str) -> String {
+        if let Some(p) = strip_dir(path, "/sys") {
+            return self.sysfs_path(p);
+        }
+        if let Some(p) = strip_dir(path, "/dev") {
+            return self.devfs_path(p);
+        }
+        if let Some(p) = strip_dir(path, "/proc") {
+            return self.procfs_path(p);
+        }
+        path.to_string()
+    }
+}
+
+fn strip_dir<'a>(path: &'a str, dir: &str) -> Option<&'a str> {
+    if path == dir {
+        return Some("");
+    }
+    match path.strip_prefix(dir) {
+        Some(p) if p.starts_with('/') => Some(p),
+        _ => None,
+    }
+}
@@ -0,0 +1,87 @@
+use super::blk_info::{BlkInfo, BlkType};
+use super::error::PeripetyError;
+use super::sys_root::SystemRoot;
+use super::sysfs::Sysfs;
+
+use regex::Regex;
+use std::collections::HashMap;
+use std::path::Path;
+
+pub(crate) fn is_virtio_blk(blk: &str) -> bool {
+    Regex::new(r"^vd[a-z]+$")
+        .expect("BUG: is_virtio_blk()")
+        // ^ We never panic as above regex is valid.
+        .is_match(blk)
+}
+
+// Return the name of virtio device('virtio1') and its parent(PCI address or
+// 'virtio-mmio' address).
+fn virtio_dev_and_parent(
+    blk: &str,
+    sys_root: &SystemRoot,
+) -> Option<(String, String)> {
+    let dev_dir = sys_root.sysfs_path(&format!("block/{}/device", blk));
+    let real_dir = Path::new(&dev_dir).canonicalize().ok()?;
+    let virtio_dev = real_dir.file_name()?.to_str()?.to_string();
+    let parent = real_dir.parent()?.file_name()?.to_str()?.to_string();
+    Some((virtio_dev, parent))
+}
+
+// Support query on these formats:
+//  * vda
+pub(crate) fn blk_info_get_virtio(
+    blk: &str,
+    sys_root: &SystemRoot,
+) -> Result<BlkInfo, PeripetyError> {
+    let sysfs_dir = sys_root.sysfs_path(&format!("block/{}", blk));
+    if !Path::new(&sysfs_dir).exists() {
+        return Err(PeripetyError::BlockNoExists(format!(
+            "VirtIO block {} does not exists",
+            blk
+        )));
+    }
+    let mut extension = HashMap::new();
+    let serial = Sysfs::read(&format!("{}/serial", sysfs_dir))
+        .unwrap_or_default()
+        .trim()
+        .to_string();
+    let (virtio_dev, parent) = virtio_dev_and_parent(blk, sys_root)
+        .unwrap_or_default();
+
+    // Use the same format as udev /dev/disk/by-id/virtio-<serial>.
+    // When serial is not defined by hypervisor, the PCI address is the only
+    // thing stay the same after reboot.
+    let wwid = if !serial.is_empty() {
+        format!("virtio-{}", serial)
+    } else if !parent.is_empty() {
+        format!("virtio-path-{}", parent)
+    } else {
+        return Err(PeripetyError::InternalBug(format!(
+            "virtio::blk_info_get_virtio(): Failed to find wwid of {}",
+            blk
+        )));
+    };
+
+    if !serial.is_empty() {
+        extension.insert("serial".to_string(), serial);
+    }
+    if !virtio_dev.is_empty() {
+        extension.insert("virtio_dev".to_string(), virtio_dev);
+    }
+    if !parent.is_empty() {
+        extension.insert("virtio_parent".to_string(), parent);
+    }
+
+    Ok(BlkInfo {
+        wwid,
+        blk_type: BlkType::Virtio,
+        blk_path: format!("/dev/{}", blk),
+        owners: Vec::new(),
+        uuid: None,
+        mount_point: None,
+        mounts: Vec::new(),
+        aliases: Vec::new(),
+        extension,
+        md_members: Vec::new(),
+    })
+}
@@ -0,0 +1,58 @@
+use super::blk_info::{BlkInfo, BlkType};
+use super::error::PeripetyError;
+use super::sys_root::SystemRoot;
+use super::sysfs::Sysfs;
+
+use regex::Regex;
+use std::collections::HashMap;
+use std::path::Path;
+
+pub(crate) fn is_xen_blk(blk: &str) -> bool {
+    Regex::new(r"^xvd[a-z]+$")
+        .expect("BUG: is_xen_blk()")
+        // ^ We never panic as above regex is valid.
+        .is_match(blk)
+}
+
+// Support query on these formats:
+//  * xvda
+pub(crate) fn blk_info_get_xen(
+    blk: &str,
+    sys_root: &SystemRoot,
+) -> Result<BlkInfo, PeripetyError> {
+    // The 'device' is linked to xen bus device like 'vbd-51712' where 51712
+    // is the virtual device number defined in domain configure.
+    let dev_dir = sys_root.sysfs_path(&format!("block/{}/device", blk));
+    let vbd = match Path::new(&dev_dir)
+        .canonicalize()
+        .ok()
+        .and_then(|p| p.file_name().and_then(|n| n.to_str()).map(String::from))
+    {
+        Some(v) => v,
+        None => {
+            return Err(PeripetyError::BlockNoExists(format!(
+                "Xen block {} does not exists",
+                blk
+            )));
+        }
+    };
+
+    let mut extension = HashMap::new();
+    extension.insert("xen_vbd".to_string(), vbd.clone());
+    if let Ok(n) = Sysfs::read(&format!("{}/nodename", dev_dir)) {
+        extension.insert("xen_nodename".to_string(), n);
+    }
+
+    Ok(BlkInfo {
+        wwid: format!("xen-{}", vbd),
+        blk_type: BlkType::Xen,
+        blk_path: format!("/dev/{}", blk),
+        owners: Vec::new(),
+        uuid: None,
+        mount_point: None,
+        mounts: Vec::new(),
+        aliases: Vec::new(),
+        extension,
+        md_members: Vec::new(),
+    })
+}
@@ -0,0 +1,66 @@
+use super::blk_info::{BlkInfo, BlkType};
+use super::error::PeripetyError;
+use super::sys_root::SystemRoot;
+use super::sysfs::Sysfs;
+
+use regex::Regex;
+use std::collections::HashMap;
+use std::path::Path;
+
+pub(crate) fn is_zram_blk(blk: &str) -> bool {
+    Regex::new(r"^zram[0-9]+$")
+        .expect("BUG: is_zram_blk()")
+        // ^ We never panic as above regex is valid.
+        .is_match(blk)
+}
+
+// The 'comp_algorithm' sysfs file list all supported algorithms with
+// current one in brackets: 'lzo [lz4] zstd'.
+fn current_comp_algorithm(content: &str) -> Option<String> {
+    for algo in content.split_whitespace() {
+        if algo.starts_with('[') && algo.ends_with(']') {
+            return Some(algo[1..algo.len() - 1].to_string());
+        }
+    }
+    None
+}
+
+// Support query on these formats:
+//  * zram0
+pub(crate) fn blk_info_get_zram(
+    blk: &str,
+    sys_root: &SystemRoot,
+) -> Result<BlkInfo, PeripetyError> {
+    let sysfs_dir = sys_root.sysfs_path(&format!("block/{}", blk));
+    if !Path::new(&sysfs_dir).exists() {
+        return Err(PeripetyError::BlockNoExists(format!(
+            "zram block {} does not exists",
+            blk
+        )));
+    }
+    let mut extension = HashMap::new();
+    if let Ok(s) = Sysfs::read(&format!("{}/disksize", sysfs_dir)) {
+        extension.insert("zram_disksize".to_string(), s);
+    }
+    if let Some(a) = Sysfs::read(&format!("{}/comp_algorithm", sysfs_dir))
+        .ok()
+        .and_then(|c| current_comp_algorithm(&c))
+    {
+        extension.insert("zram_comp_algorithm".to_string(), a);
+    }
+
+    // zram is created on demand and has no persistent identifier, the
+    // kernel name is the best we have.
+    Ok(BlkInfo {
+        wwid: format!("zram-{}", blk),
+        blk_type: BlkType::Zram,
+        blk_path: format!("/dev/{}", blk),
+        owners: Vec::new(),
+        uuid: None,
+        mount_point: None,
+        mounts: Vec::new(),
+        aliases: Vec::new(),
+        extension,
+        md_members: Vec::new(),
+    })
+}
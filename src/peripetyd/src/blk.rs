@@ -0,0 +1,78 @@
+use data::EventType;
+use parser::Parser;
+use peripety::{BlkInfo, StorageEvent, StorageSubSystem, SystemRoot};
+
+fn parse_event(
+    event: &StorageEvent,
+    sys_root: &SystemRoot,
+) -> Option<StorageEvent> {
+    let mut event = event.to_synthetic("blk");
+    match BlkInfo::new(&event.kdev, sys_root) {
+        Ok(blk_info) => {
+            // The failed I/O is in 512 bytes sectors, or in logical blocks of
+            // the block size used by the buffer cache which is unknown here.
+            let block = match event.extension.get("sector") {
+                Some(s) => Some((s.clone(), "sector")),
+                None => event
+                    .extension
+                    .get("logical_block")
+                    .map(|b| (b.clone(), "logical_block")),
+            };
+            if let Some((b, unit)) = block {
+                event
+                    .extension
+                    .insert("block_range_start".to_string(), b);
+                event
+                    .extension
+                    .insert("block_range_size".to_string(), "1".to_string());
+                event
+                    .extension
+                    .insert("block_range_unit".to_string(), unit.to_string());
+            }
+            event.msg = format!(
+                "{}, blk_wwid: '{}', blk_path: '{}'",
+                event.raw_msg, blk_info.wwid, blk_info.blk_path,
+            );
+            event.dev_path = blk_info.blk_path.clone();
+            event.dev_wwid = blk_info.wwid.clone();
+            event.owners_wwids = blk_info.owners_wwids();
+            event.owners_paths = blk_info.owners_paths();
+
+            Some(event)
+        }
+        Err(e) => {
+            println!("blk_parser: {}", e);
+            None
+        }
+    }
+}
+
+pub struct BlkParser {
+    sys_root: SystemRoot,
+}
+
+impl BlkParser {
+    pub fn new(sys_root: &SystemRoot) -> BlkParser {
+        BlkParser {
+            sys_root: sys_root.clone(),
+        }
+    }
+}
+
+impl Parser for BlkParser {
+    fn name(&self) -> &str {
+        "blk"
+    }
+
+    fn filter_event_type(&self) -> Vec<EventType> {
+        vec![EventType::Raw]
+    }
+
+    fn filter_event_subsys(&self) -> Option<Vec<StorageSubSystem>> {
+        Some(vec![StorageSubSystem::Block])
+    }
+
+    fn parse(&mut self, event: &StorageEvent) -> Vec<StorageEvent> {
+        parse_event(event, &self.sys_root).into_iter().collect()
+    }
+}
@@ -2,6 +2,7 @@ use data::RegexConfStr;
 
 pub const BUILD_IN_REGEX_CONFS: &[RegexConfStr] = &[
     RegexConfStr {
+        identifier: "kernel",
         starts_with: Some("device-mapper: multipath:"),
         regex: r"(?x)
                 ^device-mapper:\s
@@ -12,6 +13,7 @@ pub const BUILD_IN_REGEX_CONFS: &[RegexConfStr] = &[
         event_type: "DM_MPATH_PATH_FAILED",
     },
     RegexConfStr {
+        identifier: "kernel",
         starts_with: Some("device-mapper: multipath:"),
         regex: r"(?x)
                 ^device-mapper:\s
@@ -22,6 +24,7 @@ pub const BUILD_IN_REGEX_CONFS: &[RegexConfStr] = &[
         event_type: "DM_MPATH_PATH_REINSTATED",
     },
     RegexConfStr {
+        identifier: "kernel",
         starts_with: Some("device-mapper: dirty region log:"),
         regex: r"(?x)
                 ^device-mapper:\s
@@ -33,6 +36,7 @@ pub const BUILD_IN_REGEX_CONFS: &[RegexConfStr] = &[
         event_type: "DM_DIRTY_LOG_READ_FAILED",
     },
     RegexConfStr {
+        identifier: "kernel",
         starts_with: Some("device-mapper: dirty region log:"),
         regex: r"(?x)
                 ^device-mapper:\s
@@ -44,6 +48,7 @@ pub const BUILD_IN_REGEX_CONFS: &[RegexConfStr] = &[
         event_type: "DM_DIRTY_LOG_WRITE_FAILED",
     },
     RegexConfStr {
+        identifier: "kernel",
         starts_with: Some("sd "),
         regex: r"(?x)
                 ^sd\ \d+:\d+:\d+:\d+:\s
@@ -55,6 +60,7 @@ pub const BUILD_IN_REGEX_CONFS: &[RegexConfStr] = &[
         event_type: "SCSI_UNALIGNED_PARTIAL_COMPLETION",
     },
     RegexConfStr {
+        identifier: "kernel",
         starts_with: Some("sd "),
         regex: r"(?x)
                 ^sd\ \d+:\d+:\d+:\d+:\s
@@ -65,27 +71,57 @@ pub const BUILD_IN_REGEX_CONFS: &[RegexConfStr] = &[
         event_type: "SCSI_SPINNING_UP_DISK",
     },
     RegexConfStr {
+        identifier: "kernel",
         starts_with: Some("sd "),
         regex: r"(?x)
                 ^sd\ \d+:\d+:\d+:\d+:\s
                 \[(?P<kdev>sd[a-z]+)\]\s
-                tag\#\d+\ Sense\ Key\ :\ (?P<sense_key>[^\[\]]+)\s
+                (?:tag\#(?P<tag>\d+)\ )?FAILED\ Result:\s
+                hostbyte=(?P<hostbyte>[A-Z_0-9]+)\s
+                driverbyte=(?P<driverbyte>[A-Z_0-9]+)
+                ",
+        sub_system: "SCSI",
+        event_type: "SCSI_FAILED_RESULT",
+    },
+    RegexConfStr {
+        identifier: "kernel",
+        starts_with: Some("sd "),
+        regex: r"(?x)
+                ^sd\ \d+:\d+:\d+:\d+:\s
+                \[(?P<kdev>sd[a-z]+)\]\s
+                (?:tag\#(?P<tag>\d+)\ )?Sense\ Key\ :\s
+                (?P<sense_key_msg>[^\[\]]+)\s
                 \[(?P<is_deferred>(?:deferred)|(?:current))\]
                 ",
         sub_system: "SCSI",
         event_type: "SCSI_SENSE_KEY",
     },
     RegexConfStr {
+        identifier: "kernel",
         starts_with: Some("sd "),
         regex: r"(?x)
                 ^sd\ \d+:\d+:\d+:\d+:\s
                 \[(?P<kdev>sd[a-z]+)\]\s
-                tag\#\d+\ Add\.\ Sense:\ (?P<asc>.+)$
+                (?:tag\#(?P<tag>\d+)\ )?Add\.\ Sense:\ (?P<sense_msg>.+)$
                 ",
         sub_system: "SCSI",
         event_type: "SCSI_ADDITIONAL_SENSE_CODE",
     },
     RegexConfStr {
+        identifier: "kernel",
+        starts_with: Some("sd "),
+        regex: r"(?x)
+                ^sd\ \d+:\d+:\d+:\d+:\s
+                \[(?P<kdev>sd[a-z]+)\]\s
+                (?:tag\#(?P<tag>\d+)\ )?CDB:\s
+                (?:.*?[^0-9a-f\s]\S*\ )?
+                (?P<cdb>[0-9a-f]{2}(?:\ [0-9a-f]{2})+)$
+                ",
+        sub_system: "SCSI",
+        event_type: "SCSI_CDB",
+    },
+    RegexConfStr {
+        identifier: "kernel",
         starts_with: Some("sd "),
         regex: r"(?x)
                 ^sd\ \d+:\d+:\d+:\d+:\s
@@ -96,6 +132,7 @@ pub const BUILD_IN_REGEX_CONFS: &[RegexConfStr] = &[
         event_type: "SCSI_MEDIUM_ACCESS_TIMEOUT_OFFLINEING_DISK",
     },
     RegexConfStr {
+        identifier: "kernel",
         starts_with: Some("EXT4-fs "),
         regex: r"(?x)
                 ^EXT4-fs\s
@@ -106,6 +143,7 @@ pub const BUILD_IN_REGEX_CONFS: &[RegexConfStr] = &[
         event_type: "DM_FS_MOUNTED",
     },
     RegexConfStr {
+        identifier: "kernel",
         starts_with: Some("XFS "),
         regex: r"(?x)
                 ^XFS \s
@@ -115,6 +153,7 @@ pub const BUILD_IN_REGEX_CONFS: &[RegexConfStr] = &[
         event_type: "DM_FS_MOUNTED",
     },
     RegexConfStr {
+        identifier: "kernel",
         starts_with: Some("XFS "),
         regex: r"(?x)
                 ^XFS\s
@@ -124,6 +163,7 @@ pub const BUILD_IN_REGEX_CONFS: &[RegexConfStr] = &[
         event_type: "DM_FS_UNMOUNTED",
     },
     RegexConfStr {
+        identifier: "kernel",
         starts_with: Some("XFS "),
         regex: r"(?x)
                 ^XFS \s
@@ -133,6 +173,7 @@ pub const BUILD_IN_REGEX_CONFS: &[RegexConfStr] = &[
         event_type: "DM_FS_IO_ERROR",
     },
     RegexConfStr {
+        identifier: "kernel",
         starts_with: Some("EXT4-fs "),
         regex: r"(?x)
                 ^EXT4-fs\s
@@ -144,6 +185,7 @@ pub const BUILD_IN_REGEX_CONFS: &[RegexConfStr] = &[
         event_type: "DM_FS_IO_ERROR",
     },
     RegexConfStr {
+        identifier: "kernel",
         starts_with: Some("JBD2: "),
         regex: r"(?x)
                 ^JBD2:\s
@@ -153,4 +195,237 @@ pub const BUILD_IN_REGEX_CONFS: &[RegexConfStr] = &[
         sub_system: "ext4",
         event_type: "DM_FS_IO_ERROR",
     },
+    RegexConfStr {
+        identifier: "kernel",
+        starts_with: None,
+        // Prefixed by 'blk_update_request' or 'print_req_error' depending
+        // on kernel version, and no prefix since kernel 5.16.
+        regex: r"(?x)
+                ^(?:(?:print_req_error|blk_update_request):\s)?
+                (?P<blk_error>[A-Za-z/\ ]+)\ error,\ dev\s
+                (?P<kdev>[^\s,]+),\ sector\ (?P<sector>\d+)
+                ",
+        sub_system: "block",
+        event_type: "BLK_IO_ERROR",
+    },
+    RegexConfStr {
+        identifier: "kernel",
+        starts_with: Some("Buffer I/O error on dev"),
+        regex: r"(?x)
+                ^Buffer\ I/O\ error\ on\ dev(?:ice)?\s
+                (?P<kdev>[^\s,]+),\ logical\ block\s
+                (?P<logical_block>\d+)
+                ",
+        sub_system: "block",
+        event_type: "BLK_BUFFER_IO_ERROR",
+    },
+    // Logs of userspace daemons.
+    RegexConfStr {
+        identifier: "multipathd",
+        starts_with: None,
+        regex: r"(?x)
+                ^(?P<kdev>[^\s:]+):\ remaining\ active\ paths:\ 0$
+                ",
+        sub_system: "multipath",
+        event_type: "DM_MPATH_ALL_PATHS_DOWN",
+    },
+    RegexConfStr {
+        identifier: "multipathd",
+        starts_with: None,
+        regex: r"(?x)
+                ^(?P<kdev>[^\s:]+):\ remaining\ active\ paths:\s
+                (?P<active_paths>\d+)$
+                ",
+        sub_system: "multipath",
+        event_type: "DM_MPATH_REMAINING_PATHS",
+    },
+    RegexConfStr {
+        identifier: "multipathd",
+        starts_with: Some("checker failed path "),
+        regex: r"(?x)
+                ^checker\ failed\ path\ (?P<kdev>\d+:\d+)\s
+                in\ map\ (?P<mpath_name>\S+)$
+                ",
+        sub_system: "multipath",
+        event_type: "DM_MPATH_CHECKER_FAILED_PATH",
+    },
+    RegexConfStr {
+        identifier: "iscsid",
+        starts_with: Some("Connection"),
+        regex: r"(?x)
+                ^Connection(?P<iscsi_conn>\d+:\d+)\ to\s
+                \[target:\ (?P<kdev>[^,]+),\ portal:\ (?P<portal>[^\]]+)\]\s
+                through\ \[iface:\ (?P<iface>[^\]]+)\]\ is\ shutdown\.$
+                ",
+        sub_system: "iSCSI",
+        event_type: "ISCSI_CONN_SHUTDOWN",
+    },
+    RegexConfStr {
+        identifier: "iscsid",
+        starts_with: Some("Connection"),
+        regex: r"(?x)
+                ^Connection(?P<iscsi_conn>\d+:\d+)\ to\s
+                \[target:\ (?P<kdev>[^,]+),\ portal:\ (?P<portal>[^\]]+)\]\s
+                through\ \[iface:\ (?P<iface>[^\]]+)\]\ is\ operational\ now$
+                ",
+        sub_system: "iSCSI",
+        event_type: "ISCSI_CONN_OPERATIONAL",
+    },
+    RegexConfStr {
+        identifier: "iscsid",
+        starts_with: Some("Kernel reported iSCSI connection "),
+        regex: r"(?x)
+                ^Kernel\ reported\ iSCSI\ connection\s
+                (?P<iscsi_conn>\d+:\d+)\ error\s
+                \((?P<iscsi_err>[^)]+)\)
+                ",
+        sub_system: "iSCSI",
+        event_type: "ISCSI_CONN_ERROR",
+    },
+    RegexConfStr {
+        identifier: "iscsid",
+        starts_with: Some("conn "),
+        regex: r"(?x)
+                ^conn\ \d+\ login\ rejected:\ (?P<reason>.+)$
+                ",
+        sub_system: "iSCSI",
+        event_type: "ISCSI_LOGIN_FAILED",
+    },
+    RegexConfStr {
+        identifier: "dmeventd",
+        starts_with: Some("WARNING: Thin pool "),
+        regex: r"(?x)
+                ^WARNING:\ Thin\ pool\ (?P<kdev>\S+)\s
+                (?P<thin_pool_space>data|metadata)\ is\ now\s
+                (?P<thin_pool_usage>[0-9.]+)%\ full\.$
+                ",
+        sub_system: "LVM-ThinProvisioning",
+        event_type: "LVM_THIN_POOL_USAGE_HIGH",
+    },
+    RegexConfStr {
+        identifier: "smartd",
+        starts_with: Some("Device: "),
+        regex: r"(?x)
+                ^Device:\ (?P<kdev>/dev/\S+)(?:\ \[[^\]]+\])*,\s
+                FAILED\ SMART\ self-check
+                ",
+        sub_system: "SMART",
+        event_type: "SMART_DISK_FAIL",
+    },
+    RegexConfStr {
+        identifier: "smartd",
+        starts_with: Some("Device: "),
+        regex: r"(?x)
+                ^Device:\ (?P<kdev>/dev/\S+)(?:\ \[[^\]]+\])*,\s
+                Failed\ SMART\ (?:usage|prefailure)\ Attribute:\s
+                (?P<smart_attr_id>\d+)\ (?P<smart_attr_name>[^\s.]+)
+                ",
+        sub_system: "SMART",
+        event_type: "SMART_ATTRIBUTE_FAILED",
+    },
+    RegexConfStr {
+        identifier: "smartd",
+        starts_with: Some("Device: "),
+        regex: r"(?x)
+                ^Device:\ (?P<kdev>/dev/\S+)(?:\ \[[^\]]+\])*,\s
+                (?P<smart_bad_sectors>\d+)\s
+                (?P<smart_bad_sector_type>Currently\ unreadable\ \(pending\)|
+                                          Offline\ uncorrectable)\s
+                sectors
+                ",
+        sub_system: "SMART",
+        event_type: "SMART_UNREADABLE_SECTORS",
+    },
+    RegexConfStr {
+        identifier: "mdadm",
+        starts_with: None,
+        regex: r"(?x)
+                ^(?P<md_event>Fail|FailSpare)\ event\ detected\ on\ md\s
+                device\ (?P<kdev>[^\s,]+)
+                (?:,\ component\ device\ (?P<md_component>\S+))?
+                ",
+        sub_system: "MD-RAID",
+        event_type: "MD_RAID_FAIL",
+    },
+    RegexConfStr {
+        identifier: "mdadm",
+        starts_with: Some("DegradedArray "),
+        regex: r"(?x)
+                ^(?P<md_event>DegradedArray)\ event\ detected\ on\ md\s
+                device\ (?P<kdev>[^\s,]+)
+                ",
+        sub_system: "MD-RAID",
+        event_type: "MD_RAID_DEGRADED",
+    },
+    RegexConfStr {
+        identifier: "mdadm",
+        starts_with: None,
+        regex: r"(?x)
+                ^(?P<md_event>[A-Za-z]+)\ event\ detected\ on\ md\s
+                device\ (?P<kdev>[^\s,]+)
+                (?:,\ component\ device\ (?P<md_component>\S+))?
+                ",
+        sub_system: "MD-RAID",
+        event_type: "MD_RAID_EVENT",
+    },
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use data::RegexConf;
+
+    fn regex_conf(event_type: &str) -> RegexConf {
+        BUILD_IN_REGEX_CONFS
+            .iter()
+            .find(|r| r.event_type == event_type)
+            .unwrap()
+            .to_regex_conf()
+    }
+
+    #[test]
+    fn test_all_regex_valid() {
+        for regex_conf_str in BUILD_IN_REGEX_CONFS {
+            regex_conf_str.to_regex_conf();
+        }
+    }
+
+    #[test]
+    fn test_scsi_cdb() {
+        let regex = regex_conf("SCSI_CDB").regex;
+        let lines = &[
+            (
+                "sd 0:0:0:0: [sda] tag#0 CDB: Write(10) 2a 00 00 00 27 00 \
+                 00 00 40 00",
+                "2a 00 00 00 27 00 00 00 40 00",
+            ),
+            (
+                "sd 0:0:0:0: [sda] CDB: Synchronize Cache(10) 35 00 00 00 00 \
+                 00 00 00 00 00",
+                "35 00 00 00 00 00 00 00 00 00",
+            ),
+            (
+                "sd 2:0:0:1: [sdb] tag#3 CDB: Write same(16) 93 08 00 00 00 \
+                 00 00 00 00 00 00 00 00 08 00 00",
+                "93 08 00 00 00 00 00 00 00 00 00 00 00 08 00 00",
+            ),
+            (
+                "sd 2:0:0:1: [sdb] tag#3 CDB: ATA command pass through(16) \
+                 85 06 2c 00 00 00 00 00 00 00 00 00 00 00 e5 00",
+                "85 06 2c 00 00 00 00 00 00 00 00 00 00 00 e5 00",
+            ),
+            // Opcode name is optional.
+            (
+                "sd 0:0:0:0: [sda] CDB: 28 00 00 00 00 08 00 00 08 00",
+                "28 00 00 00 00 08 00 00 08 00",
+            ),
+        ];
+        for &(line, cdb) in lines {
+            let cap = regex.captures(line).expect(line);
+            assert_eq!(&cap["cdb"], cdb);
+        }
+        let cap = regex.captures(lines[0].0).unwrap();
+        assert_eq!(&cap["kdev"], "sda");
+        assert_eq!(&cap["tag"], "0");
+    }
+}
@@ -1,5 +1,6 @@
-// Collector is supposed to get log from systemd journal and generate
-// event with kdev and sub system type.
+// Collector is supposed to get log from systemd journal(or /dev/kmsg when
+// journald is not available) and generate event with kdev and sub system
+// type.
 
 // Many code are copied from Tony's
 // https://github.com/tasleson/storage_event_monitor/blob/master/src/main.rs
@@ -17,12 +18,64 @@ use std::sync::mpsc::{Receiver, Sender};
 use buildin_regex::BUILD_IN_REGEX_CONFS;
 use conf::ConfCollector;
 use data::RegexConf;
+use kmsg::Kmsg;
+
+pub static SOURCE_JOURNALD: &str = "journald";
+pub static SOURCE_KMSG: &str = "kmsg";
+
+// Kernel rate limited log 'buffer_io_error: 506 callbacks suppressed'
+// means 506 messages are dropped before the next message of the same
+// function. Return the function name and the count.
+fn parse_callbacks_suppressed(msg: &str) -> Option<(String, u64)> {
+    if !msg.ends_with(" callbacks suppressed") {
+        return None;
+    }
+    let mut words = msg.split_whitespace();
+    let func = match words.next() {
+        Some(f) if f.ends_with(':') => f.trim_end_matches(':'),
+        _ => return None,
+    };
+    match words.next().map(|c| c.parse::<u64>()) {
+        Some(Ok(count)) => Some((func.to_string(), count)),
+        _ => None,
+    }
+}
+
+// Functions printing rate limited logs without function name prefix, with
+// event type of their logs.
+static SUPPRESSED_FUNCS: &[(&str, &str)] = &[
+    ("buffer_io_error", "BLK_BUFFER_IO_ERROR"),
+    ("blk_print_req_error", "BLK_IO_ERROR"),
+    ("print_req_error", "BLK_IO_ERROR"),
+];
+
+// Whether the 'callbacks suppressed' log of `func` is about this message.
+fn is_suppressed_by(func: &str, msg: &str, event_type: &str) -> bool {
+    msg.starts_with(&format!("{}: ", func))
+        || SUPPRESSED_FUNCS
+            .iter()
+            .any(|&(f, t)| f == func && t == event_type)
+}
+
+// Collect logs of all daemons having build-in regex by default.
+fn default_identifiers() -> Vec<String> {
+    let mut ret: Vec<String> = Vec::new();
+    for regex_conf_str in BUILD_IN_REGEX_CONFS {
+        let identifier = regex_conf_str.identifier.to_string();
+        if identifier != "kernel" && !ret.contains(&identifier) {
+            ret.push(identifier);
+        }
+    }
+    ret
+}
 
 fn process_journal_entry(
     entry: &HashMap<String, String>,
     sender: &Sender<StorageEvent>,
     buildin_regex_confs: &Vec<RegexConf>,
     user_regex_confs: &Vec<RegexConf>,
+    identifiers: &[String],
+    suppressed: &mut Option<(String, u64)>,
 ) {
     let msg = match entry.get("MESSAGE") {
         Some(m) => {
@@ -34,9 +87,10 @@ fn process_journal_entry(
         None => return,
     };
 
-    if !entry.contains_key("SYSLOG_IDENTIFIER") {
-        return;
-    }
+    let identifier = match entry.get("SYSLOG_IDENTIFIER") {
+        Some(i) => i,
+        None => return,
+    };
 
     // Skip messages generated by peripetyd.
     if entry.get("IS_PERIPETY") == Some(&"TRUE".to_string()) {
@@ -45,10 +99,21 @@ fn process_journal_entry(
 
     // The /dev/kmsg can hold userspace log, hence using `_TRANSPORT=kernel` is
     // not correct here.
-    if entry.get("SYSLOG_IDENTIFIER") != Some(&"kernel".to_string()) {
+    let is_kernel = identifier == "kernel";
+    if !is_kernel && !identifiers.contains(identifier) {
         return;
     }
 
+    let mut kernel_suppressed = None;
+    if is_kernel {
+        if let Some(s) = parse_callbacks_suppressed(msg) {
+            *suppressed = Some(s);
+            return;
+        }
+        // The suppressed count only applies to the message right after it.
+        kernel_suppressed = suppressed.take();
+    }
+
     let mut event: StorageEvent = Default::default();
 
     // Currently, SCSI layer have limited structured log holding
@@ -70,6 +135,9 @@ fn process_journal_entry(
         .iter()
         .chain(user_regex_confs.iter())
     {
+        if &regex_conf.identifier != identifier {
+            continue;
+        }
         // Save CPU if event.sub_system is defined and not matching with regex
         // config.
         if event.sub_system != StorageSubSystem::Unknown
@@ -87,7 +155,9 @@ fn process_journal_entry(
             if let Some(m) = cap.name("kdev") {
                 event.kdev = m.as_str().to_string();
             }
-            if event.kdev.len() == 0 {
+            // Userspace log might not mention any device, like iSCSI login
+            // failure.
+            if event.kdev.is_empty() && is_kernel {
                 continue;
             }
 
@@ -117,7 +187,10 @@ fn process_journal_entry(
         }
     }
 
-    if event.sub_system == StorageSubSystem::Unknown || event.kdev.len() == 0 {
+    if event.sub_system == StorageSubSystem::Unknown
+        || (is_kernel && event.kdev.is_empty())
+        || (!is_kernel && event.event_type.is_empty())
+    {
         return;
     }
 
@@ -129,12 +202,15 @@ fn process_journal_entry(
 
     if let Some(t) = entry.get("__REALTIME_TIMESTAMP") {
         let tp = match t.parse::<i64>() {
-            Ok(t) => t,
-            Err(_) => return,
+            Ok(t) if t >= 0 => t,
+            _ => return,
+        };
+        let secs = tp / 10i64.pow(6);
+        let nsecs = (tp % 10i64.pow(6)) as u32 * 1000;
+        event.timestamp = match Local.timestamp_opt(secs, nsecs).single() {
+            Some(t) => t.to_rfc3339_opts(SecondsFormat::Micros, false),
+            None => return,
         };
-        event.timestamp = Local
-            .timestamp(tp / 10i64.pow(6), (tp % 10i64.pow(6)) as u32)
-            .to_rfc3339_opts(SecondsFormat::Micros, false)
     } else {
         return;
     }
@@ -149,8 +225,43 @@ fn process_journal_entry(
         }
     }
 
+    // Use the default severity of event type if log has no priority.
+    if event.severity == LogSeverity::Unknown {
+        if let Ok(t) = event.event_type_info() {
+            event.severity = t.severity;
+        }
+    }
+
     event.raw_msg = msg.to_string();
-    //TODO(Gris Ge): Generate event_id here.
+    // Drop the count if the message is from other function.
+    if let Some((func, count)) = kernel_suppressed
+        .filter(|(f, _)| is_suppressed_by(f, msg, &event.event_type))
+    {
+        event
+            .extension
+            .insert("kernel_suppressed".to_string(), format!("{}", count));
+        event
+            .extension
+            .insert("kernel_suppressed_func".to_string(), func);
+    }
+
+    // Journal cursor contains boot ID and is unique to each entry, so does
+    // kmsg sequence number within the boot.
+    let cursor = entry.get("__CURSOR");
+    event.event_id = match (cursor, entry.get("KMSG_SEQNUM")) {
+        (Some(c), _) => StorageEvent::gen_event_id(c),
+        (None, Some(seq)) => StorageEvent::gen_event_id(&format!(
+            "{}/kmsg/{}",
+            entry.get("_BOOT_ID").map(|s| s.as_str()).unwrap_or(""),
+            seq
+        )),
+        (None, None) => StorageEvent::gen_event_id(&format!(
+            "{}/{}/{}",
+            entry.get("_BOOT_ID").map(|s| s.as_str()).unwrap_or(""),
+            entry.get("__REALTIME_TIMESTAMP").map(|s| s.as_str()).unwrap_or(""),
+            msg
+        )),
+    };
 
     //TODO(Gris Ge): Need to skip journal entry when that one is created by
     //               peripety.
@@ -159,10 +270,82 @@ fn process_journal_entry(
     }
 }
 
+fn buildin_regex_confs() -> Vec<RegexConf> {
+    let mut ret: Vec<RegexConf> = Vec::new();
+    for regex_conf_str in BUILD_IN_REGEX_CONFS {
+        ret.push(regex_conf_str.to_regex_conf());
+    }
+    ret
+}
+
+fn apply_conf(
+    conf: ConfCollector,
+    identifiers: &mut Vec<String>,
+    user_regex_confs: &mut Vec<RegexConf>,
+) {
+    *identifiers = conf.identifiers.unwrap_or_else(default_identifiers);
+    user_regex_confs.clear();
+    for regex in conf.regexs {
+        match regex.to_regex_conf() {
+            Ok(r) => user_regex_confs.push(r),
+            Err(e) => {
+                println!("collector: Invalid config: {}", e);
+                continue;
+            }
+        }
+    }
+}
+
+// Apply the config changed by main thread if any.
+fn update_conf(
+    config_changed: &Receiver<ConfCollector>,
+    identifiers: &mut Vec<String>,
+    user_regex_confs: &mut Vec<RegexConf>,
+) {
+    if let Ok(conf) = config_changed.try_recv() {
+        apply_conf(conf, identifiers, user_regex_confs);
+    }
+}
+
 pub fn new(
     sender: &Sender<StorageEvent>,
     config_changed: &Receiver<ConfCollector>,
+    source: &str,
 ) {
+    let mut suppressed = None;
+
+    let mut identifiers = default_identifiers();
+
+    // Setup initial regex conf.
+    let buildin_regex_confs = buildin_regex_confs();
+    let mut user_regex_confs: Vec<RegexConf> = Vec::new();
+
+    if source == SOURCE_KMSG {
+        let kmsg = match Kmsg::new() {
+            Ok(k) => k,
+            Err(e) => {
+                println!("collector: {}", e);
+                return;
+            }
+        };
+        for entry in kmsg {
+            update_conf(
+                config_changed,
+                &mut identifiers,
+                &mut user_regex_confs,
+            );
+            process_journal_entry(
+                &entry,
+                sender,
+                &buildin_regex_confs,
+                &user_regex_confs,
+                &identifiers,
+                &mut suppressed,
+            )
+        }
+        return;
+    }
+
     let mut journal =
         sdjournal::Journal::new().expect("Failed to open systemd journal");
     // We never want to block, so set the timeout to 0
@@ -172,16 +355,6 @@ pub fn new(
         .seek_tail()
         .expect("Unable to seek to end of journal!");
 
-    // Setup initial regex conf.
-    let mut buildin_regex_confs: Vec<RegexConf> = Vec::new();
-    let mut user_regex_confs: Vec<RegexConf> = Vec::new();
-
-    // Read config to add more RegexConf.
-    for regex_conf_str in BUILD_IN_REGEX_CONFS {
-        let regex_conf = regex_conf_str.to_regex_conf();
-        buildin_regex_confs.push(regex_conf);
-    }
-
     loop {
         let mut fds = FdSet::new();
         fds.insert(journal.as_raw_fd());
@@ -201,26 +374,18 @@ pub fn new(
         for entry in &mut journal {
             match entry {
                 Ok(entry) => {
-                    if let Ok(conf) = config_changed.try_recv() {
-                        user_regex_confs.clear();
-                        for regex in conf.regexs {
-                            match regex.to_regex_conf() {
-                                Ok(r) => user_regex_confs.push(r),
-                                Err(e) => {
-                                    println!(
-                                        "collector: Invalid config: {}",
-                                        e
-                                    );
-                                    continue;
-                                }
-                            }
-                        }
-                    }
+                    update_conf(
+                        config_changed,
+                        &mut identifiers,
+                        &mut user_regex_confs,
+                    );
                     process_journal_entry(
                         &entry,
                         sender,
                         &buildin_regex_confs,
                         &user_regex_confs,
+                        &identifiers,
+                        &mut suppressed,
                     )
                 }
                 Err(e) => {
@@ -230,3 +395,93 @@ pub fn new(
         }
     }
 }
+
+// Process saved log entries instead of live system.
+pub fn replay(
+    entries: &[HashMap<String, String>],
+    sender: &Sender<StorageEvent>,
+    conf: Option<ConfCollector>,
+) {
+    let mut suppressed = None;
+    let buildin_regex_confs = buildin_regex_confs();
+    let mut identifiers = default_identifiers();
+    let mut user_regex_confs: Vec<RegexConf> = Vec::new();
+    if let Some(conf) = conf {
+        apply_conf(conf, &mut identifiers, &mut user_regex_confs);
+    }
+    for entry in entries {
+        process_journal_entry(
+            entry,
+            sender,
+            &buildin_regex_confs,
+            &user_regex_confs,
+            &identifiers,
+            &mut suppressed,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_callbacks_suppressed() {
+        assert_eq!(
+            parse_callbacks_suppressed(
+                "buffer_io_error: 506 callbacks suppressed"
+            ),
+            Some(("buffer_io_error".to_string(), 506))
+        );
+        assert_eq!(parse_callbacks_suppressed("sd 0:0:0:0: [sda] Done"), None);
+        assert_eq!(
+            parse_callbacks_suppressed("foo: many callbacks suppressed"),
+            None
+        );
+
+        assert!(is_suppressed_by(
+            "buffer_io_error",
+            "Buffer I/O error on dev sda, logical block 0, async page read",
+            "BLK_BUFFER_IO_ERROR"
+        ));
+        assert!(is_suppressed_by(
+            "blk_update_request",
+            "blk_update_request: I/O error, dev sda, sector 100",
+            "BLK_IO_ERROR"
+        ));
+        // Message from other function.
+        assert!(!is_suppressed_by(
+            "buffer_io_error",
+            "blk_update_request: I/O error, dev sda, sector 100",
+            "BLK_IO_ERROR"
+        ));
+        assert!(!is_suppressed_by(
+            "ext4_end_bio",
+            "Buffer I/O error on dev sda, logical block 0, async page read",
+            "BLK_BUFFER_IO_ERROR"
+        ));
+    }
+    #[test]
+    fn test_replay_bad_timestamp() {
+        let mut entries = Vec::new();
+        for tp in &["-1", "abc", "9223372036854775807", "1530000000000001"] {
+            let mut entry = HashMap::new();
+            entry.insert("SYSLOG_IDENTIFIER".to_string(), "kernel".to_string());
+            entry.insert(
+                "MESSAGE".to_string(),
+                "blk_update_request: I/O error, dev sda, sector 100"
+                    .to_string(),
+            );
+            entry.insert("__REALTIME_TIMESTAMP".to_string(), tp.to_string());
+            entries.push(entry);
+        }
+        let (sender, receiver) = mpsc::channel();
+        replay(&entries, &sender, None);
+        // Only the last entry has valid timestamp.
+        let events: Vec<StorageEvent> = receiver.try_iter().collect();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kdev, "sda");
+        assert_eq!(events[0].event_type, "BLK_IO_ERROR");
+    }
+}
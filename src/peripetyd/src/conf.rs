@@ -1,5 +1,5 @@
 use data::RegexConf;
-use peripety::{PeripetyError, StorageSubSystem};
+use peripety::{EventTypeInfo, PeripetyError, StorageSubSystem};
 use regex::Regex;
 use std::fs::File;
 use std::io::Read;
@@ -12,10 +12,28 @@ static CONFIG_PATH: &'static str = "/etc/peripetyd.conf";
 pub struct ConfMain {
     pub notify_stdout: Option<bool>,
     pub save_to_journald: Option<bool>,
+    // Where to save events, 'journald' or 'file'. Default is 'journald'.
+    pub store: Option<String>,
+    // Path of JSON lines file for 'file' store.
+    pub store_file: Option<String>,
+    // Rotate the file once reached this size in MiB.
+    pub store_file_max_size: Option<u64>,
+    // Rotate the file once its first event is older than this many days.
+    pub store_file_max_age: Option<u64>,
+    // Number of rotated files to keep.
+    pub store_file_rotate_count: Option<u32>,
+    // Time window in seconds for linking events to their root cause,
+    // 0 disables root cause analysis.
+    pub root_cause_window: Option<u64>,
+    // Time window in seconds for merging identical events, 0 disables
+    // the merging.
+    pub dedup_window: Option<u64>,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct ConfCollectorRegex {
+    // The `SYSLOG_IDENTIFIER` of log, default is 'kernel'.
+    pub identifier: Option<String>,
     pub regex: String,
     pub event_type: String,
     pub starts_with: Option<String>,
@@ -42,7 +60,16 @@ impl ConfCollectorRegex {
                 )))
             }
         };
+        if let Err(e) = EventTypeInfo::get(&self.event_type) {
+            return Err(PeripetyError::ConfError(format!(
+                "Invalid event_type: {}",
+                e
+            )));
+        }
         Ok(RegexConf {
+            identifier: self.identifier
+                .clone()
+                .unwrap_or_else(|| "kernel".to_string()),
             starts_with: self.starts_with.clone(),
             regex: regex,
             sub_system: sub_system,
@@ -53,49 +80,186 @@ impl ConfCollectorRegex {
 
 #[derive(Deserialize, Debug)]
 pub struct ConfCollector {
+    // Where to collect kernel log from, 'journald' or 'kmsg'. Default is
+    // 'journald'. Changes require daemon restart.
+    pub source: Option<String>,
+    // `SYSLOG_IDENTIFIER` of userspace daemons to collect logs from, kernel
+    // log is always collected. Default is all daemons with build-in regex.
+    pub identifiers: Option<Vec<String>>,
     pub regexs: Vec<ConfCollectorRegex>,
 }
 
+// Event type generated by parser plugin but not shipped with peripety.
+#[derive(Deserialize, Debug, Clone)]
+pub struct ConfPluginEventType {
+    pub name: String,
+    pub severity: String,
+    // 'failure', 'recovery' or 'info'.
+    pub kind: String,
+    pub description: String,
+    // Default is none.
+    pub sub_systems: Option<Vec<String>>,
+}
+
+impl ConfPluginEventType {
+    pub fn register(&self) -> Result<(), String> {
+        let mut sub_systems = Vec::new();
+        if let Some(ref subs) = self.sub_systems {
+            for s in subs {
+                sub_systems.push(
+                    s.parse::<StorageSubSystem>()
+                        .map_err(|e| format!("{}", e))?,
+                );
+            }
+        }
+        EventTypeInfo::register(
+            &self.name,
+            self.severity.parse().map_err(|e| format!("{}", e))?,
+            &sub_systems,
+            self.kind.parse().map_err(|e| format!("{}", e))?,
+            &self.description,
+        )
+        .map_err(|e| format!("{}", e))?;
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ConfPluginParser {
+    pub name: String,
+    // Executable to spawn, events are sent to its stdin.
+    pub exec: String,
+    pub args: Option<Vec<String>>,
+    // Event types to receive, 'raw' or 'synthetic'. Default is 'raw'.
+    pub event_types: Option<Vec<String>>,
+    // Sub-systems to receive. Default is all.
+    pub sub_systems: Option<Vec<String>>,
+    // Seconds to wait for reply of each event. Default is 5.
+    pub timeout: Option<u64>,
+    // Event types generated by the executable which are not build-in.
+    // Changes require daemon restart.
+    pub new_event_types: Option<Vec<ConfPluginEventType>>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ConfHook {
+    pub name: String,
+    // Command to run for each matching event, event is passed as JSON to
+    // its stdin.
+    pub exec: String,
+    pub args: Option<Vec<String>>,
+    // Only run for event with equal or higher severity. Default is all.
+    pub severity: Option<String>,
+    // Only run for event of these event types. Default is all.
+    pub event_types: Option<Vec<String>>,
+    // Only run for event of these sub-systems. Default is all.
+    pub sub_systems: Option<Vec<String>>,
+    // Maximum number of commands running at the same time, the remaining
+    // events are queued. Default is 4.
+    pub max_concurrent: Option<usize>,
+    // Maximum events queued, further events are dropped. Default is 100.
+    pub queue_size: Option<usize>,
+    // Seconds before the command is killed. Default is 30.
+    pub timeout: Option<u64>,
+}
+
 #[derive(Deserialize, Debug)]
 pub struct Conf {
     pub main: ConfMain,
     pub collector: ConfCollector,
+    // The `[parsers.<name>]` sections passed to each parser.
+    pub parsers: Option<toml::value::Table>,
+    pub plugin_parsers: Option<Vec<ConfPluginParser>>,
+    pub hooks: Option<Vec<ConfHook>>,
 }
 
-pub fn load_conf() -> Option<Conf> {
+// Like load_conf(), but quiet. Return None if config file does not exist.
+pub fn read_conf() -> Result<Option<Conf>, String> {
     let path = Path::new(CONFIG_PATH);
     if !path.exists() {
-        println!("Config file {} does not exist", CONFIG_PATH);
-        return None;
+        return Ok(None);
     }
 
-    let mut fd = match File::open(path) {
-        Ok(fd) => fd,
-        Err(e) => {
-            println!(
-                "Failed to open config file {}, error {}",
-                CONFIG_PATH, e
-            );
-            return None;
-        }
-    };
+    let mut fd = File::open(path).map_err(|e| {
+        format!("Failed to open config file {}, error {}", CONFIG_PATH, e)
+    })?;
     let mut contents = String::new();
-    if let Err(e) = fd.read_to_string(&mut contents) {
-        println!(
-            "Fail to read config file {}, error {}",
+    fd.read_to_string(&mut contents).map_err(|e| {
+        format!("Fail to read config file {}, error {}", CONFIG_PATH, e)
+    })?;
+    match toml::from_str(&contents) {
+        Ok(c) => Ok(Some(c)),
+        Err(e) => Err(format!(
+            "Fail to parse config file {}, error {}",
             CONFIG_PATH, e
-        );
-        return None;
+        )),
     }
+}
 
-    match toml::from_str(&contents) {
-        Ok(c) => Some(c),
+impl Conf {
+    // Register `new_event_types` of all plugin parsers. Also done when
+    // plugin parser starts, but the `[[collector.regexs]]` and tools like
+    // prpt need them without starting plugins.
+    pub fn register_event_types(&self) -> Result<(), String> {
+        for plugin in self.plugin_parsers.iter().flatten() {
+            for t in plugin.new_event_types.iter().flatten() {
+                t.register().map_err(|e| {
+                    format!("Plugin parser {}: {}", plugin.name, e)
+                })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn load_conf() -> Option<Conf> {
+    match read_conf() {
+        Ok(Some(c)) => {
+            if let Err(e) = c.register_event_types() {
+                println!("{}", e);
+            }
+            Some(c)
+        }
+        Ok(None) => {
+            println!("Config file {} does not exist", CONFIG_PATH);
+            None
+        }
         Err(e) => {
-            println!(
-                "Fail to parse config file {}, error {}",
-                CONFIG_PATH, e
-            );
+            println!("{}", e);
             None
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plugin_event_type_in_collector_regex() {
+        let conf: Conf = toml::from_str(
+            r#"
+[main]
+
+[[collector.regexs]]
+regex = "^vendor: (?P<kdev>sd[a-z]+) failed$"
+event_type = "TEST_CONF_VENDOR_FAILURE"
+sub_system = "scsi"
+
+[[plugin_parsers]]
+name = "vendor"
+exec = "/usr/bin/vendor_parser"
+
+[[plugin_parsers.new_event_types]]
+name = "TEST_CONF_VENDOR_FAILURE"
+severity = "error"
+kind = "failure"
+description = "Vendor failure"
+"#,
+        ).unwrap();
+        assert!(conf.collector.regexs[0].to_regex_conf().is_err());
+        conf.register_event_types().unwrap();
+        let regex_conf = conf.collector.regexs[0].to_regex_conf().unwrap();
+        assert_eq!(regex_conf.event_type, "TEST_CONF_VENDOR_FAILURE");
+    }
+}
@@ -0,0 +1,76 @@
+// Parser for logs of userspace storage daemons whose sub system has no
+// dedicated parser: dmeventd, iscsid, smartd and mdadm monitor.
+
+use data::EventType;
+use parser::Parser;
+use peripety::{BlkInfo, StorageEvent, StorageSubSystem, SystemRoot};
+
+fn parse_event(
+    event: &StorageEvent,
+    sys_root: &SystemRoot,
+) -> StorageEvent {
+    let mut event = event.to_synthetic("daemon");
+    // The dmeventd logs device mapper name.
+    let blk_info = if event.kdev.is_empty() {
+        None
+    } else {
+        BlkInfo::new(&event.kdev, sys_root)
+            .or_else(|_| {
+                BlkInfo::new(&format!("/dev/mapper/{}", event.kdev), sys_root)
+            })
+            .ok()
+    };
+    match blk_info {
+        Some(blk_info) => {
+            event.msg = format!(
+                "{}, blk_wwid: '{}', blk_path: '{}'",
+                event.raw_msg, blk_info.wwid, blk_info.blk_path,
+            );
+            event.dev_path = blk_info.blk_path.clone();
+            event.dev_wwid = blk_info.wwid.clone();
+            event.owners_wwids = blk_info.owners_wwids();
+            event.owners_paths = blk_info.owners_paths();
+        }
+        // Not a block, like iSCSI target name.
+        None => {
+            event.msg = event.raw_msg.clone();
+            event.dev_wwid = event.kdev.clone();
+        }
+    }
+    event
+}
+
+pub struct DaemonParser {
+    sys_root: SystemRoot,
+}
+
+impl DaemonParser {
+    pub fn new(sys_root: &SystemRoot) -> DaemonParser {
+        DaemonParser {
+            sys_root: sys_root.clone(),
+        }
+    }
+}
+
+impl Parser for DaemonParser {
+    fn name(&self) -> &str {
+        "daemon"
+    }
+
+    fn filter_event_type(&self) -> Vec<EventType> {
+        vec![EventType::Raw]
+    }
+
+    fn filter_event_subsys(&self) -> Option<Vec<StorageSubSystem>> {
+        Some(vec![
+            StorageSubSystem::LvmThin,
+            StorageSubSystem::Iscsi,
+            StorageSubSystem::Smart,
+            StorageSubSystem::MdRaid,
+        ])
+    }
+
+    fn parse(&mut self, event: &StorageEvent) -> Vec<StorageEvent> {
+        vec![parse_event(event, &self.sys_root)]
+    }
+}
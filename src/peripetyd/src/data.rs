@@ -1,4 +1,7 @@
-use peripety::{StorageEvent, StorageSubSystem};
+use peripety::{
+    EventTypeInfo, StorageEvent, StorageSubSystem, SystemRoot,
+};
+use nix::unistd::gethostname;
 use regex::Regex;
 use std::fs;
 use std::io::Read;
@@ -20,6 +23,8 @@ pub struct ParserInfo {
 
 #[derive(Clone, Debug)]
 pub struct RegexConf {
+    // Only apply to log with this `SYSLOG_IDENTIFIER`.
+    pub identifier: String,
     pub starts_with: Option<String>,
     pub regex: Regex,
     pub sub_system: StorageSubSystem,
@@ -28,6 +33,7 @@ pub struct RegexConf {
 
 #[derive(Clone, Debug)]
 pub struct RegexConfStr<'a> {
+    pub identifier: &'a str,
     pub starts_with: Option<&'a str>,
     pub regex: &'a str,
     pub sub_system: &'a str,
@@ -37,6 +43,7 @@ pub struct RegexConfStr<'a> {
 impl<'a> RegexConfStr<'a> {
     pub fn to_regex_conf(&self) -> RegexConf {
         RegexConf {
+            identifier: self.identifier.to_string(),
             starts_with: self.starts_with.map(|s| s.to_string()),
             regex: Regex::new(self.regex).expect(&format!(
                 "BUG: data.rs has invalid regex: {}",
@@ -49,7 +56,12 @@ impl<'a> RegexConfStr<'a> {
                 .expect("BUG: data.rs has invalid sub_system"),
             // ^ We panic when hard-coded sub_system is not valid. It's
             // developer's fault.
-            event_type: self.event_type.to_string(),
+            event_type: EventTypeInfo::get(self.event_type)
+                .expect("BUG: data.rs has invalid event_type")
+                .name
+                .to_string(),
+            // ^ We panic when hard-coded event_type is not registered. It's
+            // developer's fault.
         }
     }
 }
@@ -57,8 +69,12 @@ impl<'a> RegexConfStr<'a> {
 pub struct Sysfs;
 
 impl Sysfs {
-    pub fn scsi_id_of_disk(name: &str) -> Option<String> {
-        let sysfs_path = format!("/sys/block/{}/device", name);
+    pub fn scsi_id_of_disk(
+        name: &str,
+        sys_root: &SystemRoot,
+    ) -> Option<String> {
+        let sysfs_path =
+            sys_root.sysfs_path(&format!("block/{}/device", name));
         match fs::read_link(&sysfs_path) {
             Ok(p) => {
                 if let Some(p) = p.file_name() {
@@ -110,3 +126,17 @@ impl Sysfs {
         contents
     }
 }
+
+pub fn get_hostname() -> String {
+    let mut buff = [0u8; 256];
+    match gethostname(&mut buff) {
+        Ok(h) => h.to_string_lossy().to_string(),
+        Err(_) => "".to_string(),
+    }
+}
+
+pub fn get_boot_id(sys_root: &SystemRoot) -> String {
+    fs::read_to_string(sys_root.procfs_path("sys/kernel/random/boot_id"))
+        .map(|s| s.trim().to_string())
+        .unwrap_or_default()
+}
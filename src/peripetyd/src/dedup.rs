@@ -0,0 +1,312 @@
+// Collapse the burst of identical events(same device and event type) into
+// single event. A failing disk could log thousands of I/O errors per second.
+//
+// The first event of each device and event type is sent out right away and
+// held for the time window, identical events within the window are merged
+// into it. Once the window expired, if any event was merged, an aggregate
+// event is sent out as a copy of the first one with these extension entries:
+//  * dedup_count:          number of events merged, including the first.
+//  * first_timestamp:      timestamp of the first event.
+//  * last_timestamp:       timestamp of the last event.
+//  * kernel_suppressed:    sum of kernel 'callbacks suppressed' counts.
+//  * block_range_start/block_range_size:
+//                          smallest range covering all merged events in
+//                          `block_range_unit` of the first event: 'sector'
+//                          (512 bytes), 'lba'(logical block of SCSI disk) or
+//                          'logical_block'(block of buffer cache).
+
+use chrono::{DateTime, Duration, FixedOffset, Local};
+use peripety::StorageEvent;
+
+struct HeldEvent {
+    dev: String,
+    // Events of multipath paths have `dev_wwid` of the map.
+    kdev: String,
+    event: StorageEvent,
+    count: u64,
+    kernel_suppressed: u64,
+    first_time: DateTime<FixedOffset>,
+    last_timestamp: String,
+    // [start, end) of blocks, in the unit of the first event.
+    block_range: Option<(u64, u64)>,
+    block_range_unit: Option<String>,
+}
+
+pub struct Dedup {
+    window: Duration,
+    held: Vec<HeldEvent>,
+}
+
+fn now() -> DateTime<FixedOffset> {
+    let now = Local::now();
+    now.with_timezone(now.offset())
+}
+
+fn event_time(event: &StorageEvent) -> DateTime<FixedOffset> {
+    DateTime::parse_from_rfc3339(&event.timestamp).unwrap_or_else(|_| now())
+}
+
+// Events from different parsers might only have some of these.
+fn dev_of(event: &StorageEvent) -> &str {
+    if !event.dev_wwid.is_empty() {
+        &event.dev_wwid
+    } else if !event.dev_path.is_empty() {
+        &event.dev_path
+    } else {
+        &event.kdev
+    }
+}
+
+fn ext_u64(event: &StorageEvent, key: &str) -> Option<u64> {
+    event.extension.get(key).and_then(|v| v.parse::<u64>().ok())
+}
+
+fn block_range_unit_of(event: &StorageEvent) -> Option<String> {
+    event.extension.get("block_range_unit").cloned()
+}
+
+fn block_range_of(event: &StorageEvent) -> Option<(u64, u64)> {
+    let start = ext_u64(event, "block_range_start")?;
+    let size = ext_u64(event, "block_range_size").unwrap_or(1);
+    Some((start, start.saturating_add(size)))
+}
+
+impl HeldEvent {
+    fn new(event: StorageEvent, time: DateTime<FixedOffset>) -> HeldEvent {
+        HeldEvent {
+            dev: dev_of(&event).to_string(),
+            kdev: event.kdev.clone(),
+            count: 1,
+            kernel_suppressed: ext_u64(&event, "kernel_suppressed")
+                .unwrap_or(0),
+            first_time: time,
+            last_timestamp: event.timestamp.clone(),
+            block_range: block_range_of(&event),
+            block_range_unit: block_range_unit_of(&event),
+            event,
+        }
+    }
+
+    fn merge(&mut self, event: &StorageEvent) {
+        self.count += 1;
+        self.kernel_suppressed +=
+            ext_u64(event, "kernel_suppressed").unwrap_or(0);
+        self.last_timestamp = event.timestamp.clone();
+        // Ranges in different units cannot be merged.
+        if block_range_unit_of(event) != self.block_range_unit {
+            return;
+        }
+        if let Some((start, end)) = block_range_of(event) {
+            self.block_range = match self.block_range {
+                Some((s, e)) => Some((s.min(start), e.max(end))),
+                None => Some((start, end)),
+            };
+        }
+    }
+
+    fn matches(&self, event: &StorageEvent) -> bool {
+        self.event.event_type == event.event_type
+            && self.dev == dev_of(event)
+            && self.kdev == event.kdev
+    }
+
+    // None if no event merged, the first event was already sent out.
+    fn into_aggregate(self) -> Option<StorageEvent> {
+        if self.count == 1 {
+            return None;
+        }
+        let mut event = self.event;
+        if !event.event_id.is_empty() {
+            event.parent_event_id = event.event_id.clone();
+            event.event_id = StorageEvent::gen_event_id(&format!(
+                "{}/dedup",
+                event.event_id
+            ));
+        }
+        event.extension.insert(
+            "first_timestamp".to_string(),
+            event.timestamp.clone(),
+        );
+        event
+            .extension
+            .insert("last_timestamp".to_string(), self.last_timestamp.clone());
+        event.timestamp = self.last_timestamp;
+        event
+            .extension
+            .insert("dedup_count".to_string(), format!("{}", self.count));
+        event.extension.insert(
+            "kernel_suppressed".to_string(),
+            format!("{}", self.kernel_suppressed),
+        );
+        if let Some((start, end)) = self.block_range {
+            event
+                .extension
+                .insert("block_range_start".to_string(), format!("{}", start));
+            event.extension.insert(
+                "block_range_size".to_string(),
+                format!("{}", end - start),
+            );
+        }
+        let mut note = format!("repeated {} times", self.count);
+        if self.kernel_suppressed != 0 {
+            note = format!(
+                "{}, kernel suppressed {} messages",
+                note, self.kernel_suppressed
+            );
+        }
+        event.msg = format!("{} ({})", event.msg, note);
+        Some(event)
+    }
+}
+
+impl Dedup {
+    pub fn new(window_secs: u64) -> Dedup {
+        Dedup {
+            window: Duration::seconds(window_secs as i64),
+            held: Vec::new(),
+        }
+    }
+
+    // Return the provided event if it is the first of its kind, and the
+    // aggregate events whose time window expired.
+    pub fn process(&mut self, event: StorageEvent) -> Vec<StorageEvent> {
+        let time = event_time(&event);
+        let mut ret = self.expire(time);
+        // Nothing to compare with.
+        if event.event_type.is_empty() || dev_of(&event).is_empty() {
+            ret.push(event);
+            return ret;
+        }
+        match self.held.iter_mut().find(|h| h.matches(&event)) {
+            Some(h) => h.merge(&event),
+            None => {
+                self.held.push(HeldEvent::new(event.clone(), time));
+                ret.push(event);
+            }
+        };
+        ret
+    }
+
+    pub fn expire(
+        &mut self,
+        time: DateTime<FixedOffset>,
+    ) -> Vec<StorageEvent> {
+        let mut ret = Vec::new();
+        let mut i = 0;
+        while i < self.held.len() {
+            if self.held[i].first_time + self.window > time {
+                i += 1;
+                continue;
+            }
+            if let Some(e) = self.held.remove(i).into_aggregate() {
+                ret.push(e);
+            }
+        }
+        ret
+    }
+
+    // Expire with current time, used when no event is received for a while.
+    pub fn expire_now(&mut self) -> Vec<StorageEvent> {
+        self.expire(now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(secs: u32, kdev: &str, sector: u64) -> StorageEvent {
+        let mut event = StorageEvent {
+            event_id: format!("{}-{}-{}", secs, kdev, sector),
+            event_type: "BLK_IO_ERROR".to_string(),
+            timestamp: format!("2018-03-02T14:13:{:02}.000000+00:00", secs),
+            dev_wwid: "mpath-36001405".to_string(),
+            kdev: kdev.to_string(),
+            msg: "I/O error".to_string(),
+            ..Default::default()
+        };
+        event
+            .extension
+            .insert("block_range_start".to_string(), format!("{}", sector));
+        event
+            .extension
+            .insert("block_range_size".to_string(), "8".to_string());
+        event
+            .extension
+            .insert("block_range_unit".to_string(), "sector".to_string());
+        event
+    }
+
+    fn time(secs: u32) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(&format!(
+            "2018-03-02T14:13:{:02}.000000+00:00",
+            secs
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_first_event_not_delayed() {
+        let mut dedup = Dedup::new(2);
+        let events = dedup.process(event(0, "8:0", 100));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0], event(0, "8:0", 100));
+        assert!(dedup.process(event(1, "8:0", 200)).is_empty());
+    }
+
+    #[test]
+    fn test_window_expired() {
+        let mut dedup = Dedup::new(2);
+        dedup.process(event(0, "8:0", 100));
+        dedup.process(event(1, "8:0", 200));
+        dedup.process(event(1, "8:0", 50));
+        assert!(dedup.expire(time(1)).is_empty());
+
+        let events = dedup.expire(time(2));
+        assert_eq!(events.len(), 1);
+        let e = &events[0];
+        assert_eq!(e.parent_event_id, "0-8:0-100");
+        assert_ne!(e.event_id, "0-8:0-100");
+        assert_eq!(e.timestamp, "2018-03-02T14:13:01.000000+00:00");
+        assert_eq!(e.extension["dedup_count"], "3");
+        assert_eq!(
+            e.extension["first_timestamp"],
+            "2018-03-02T14:13:00.000000+00:00"
+        );
+        assert_eq!(e.extension["block_range_start"], "50");
+        assert_eq!(e.extension["block_range_size"], "158");
+        assert_eq!(e.msg, "I/O error (repeated 3 times)");
+
+        // New window after expired.
+        assert_eq!(dedup.process(event(3, "8:0", 100)).len(), 1);
+    }
+
+    #[test]
+    fn test_single_event_no_aggregate() {
+        let mut dedup = Dedup::new(2);
+        dedup.process(event(0, "8:0", 100));
+        assert!(dedup.expire(time(5)).is_empty());
+    }
+
+    #[test]
+    fn test_path_events_not_merged() {
+        // Paths of the same multipath map.
+        let mut dedup = Dedup::new(2);
+        assert_eq!(dedup.process(event(0, "8:0", 100)).len(), 1);
+        assert_eq!(dedup.process(event(0, "8:16", 100)).len(), 1);
+    }
+
+    #[test]
+    fn test_block_range_unit() {
+        let mut dedup = Dedup::new(2);
+        dedup.process(event(0, "8:0", 100));
+        let mut e = event(1, "8:0", 0);
+        e.extension
+            .insert("block_range_unit".to_string(), "lba".to_string());
+        dedup.process(e);
+        let events = dedup.expire(time(2));
+        assert_eq!(events[0].extension["block_range_start"], "100");
+        assert_eq!(events[0].extension["block_range_size"], "8");
+        assert_eq!(events[0].extension["block_range_unit"], "sector");
+    }
+}
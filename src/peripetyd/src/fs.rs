@@ -1,21 +1,22 @@
-use data::{EventType, ParserInfo};
-use peripety::{BlkInfo, StorageEvent, StorageSubSystem};
-use std::sync::mpsc;
-use std::sync::mpsc::Sender;
-use std::thread::spawn;
+use data::EventType;
+use parser::Parser;
+use peripety::{BlkInfo, StorageEvent, StorageSubSystem, SystemRoot};
 
-fn parse_event(event: &StorageEvent, sender: &Sender<StorageEvent>) {
-    let mut event = event.clone();
-    match BlkInfo::new(&event.kdev) {
+fn parse_event(
+    event: &StorageEvent,
+    sys_root: &SystemRoot,
+) -> Option<StorageEvent> {
+    let mut event = event.to_synthetic("fs");
+    match BlkInfo::new(&event.kdev, sys_root) {
         Ok(blk_info) => {
-            let uuid = match blk_info.uuid {
+            let uuid = match blk_info.uuid.clone() {
                 Some(u) => u,
                 None => {
                     println!(
                         "fs_parser: Failed to find uuid of block {}",
                         &event.kdev
                     );
-                    return;
+                    return None;
                 }
             };
             event.msg = format!(
@@ -26,18 +27,41 @@ fn parse_event(event: &StorageEvent, sender: &Sender<StorageEvent>) {
                 blk_info.blk_path,
             );
 
-            if let Some(mnt_pnt) = blk_info.mount_point {
+            // Report mounts in all namespaces as containers might be
+            // affected also.
+            let mut mounts = blk_info.mounts.clone();
+            mounts.extend(BlkInfo::get_mounts_other_ns(
+                &blk_info.blk_path,
+                sys_root,
+            ));
+            if let Some(mnt) = mounts.first() {
+                event
+                    .extension
+                    .insert("mount_point".to_string(), mnt.mount_point.clone());
+                event
+                    .extension
+                    .insert("fs_type".to_string(), mnt.fs_type.clone());
+            }
+            if !mounts.is_empty() {
+                let mnt_pnts: Vec<&str> =
+                    mounts.iter().map(|m| m.mount_point.as_str()).collect();
+                let read_only = mounts.iter().any(|m| m.fs_read_only);
                 event.msg = format!(
-                    "{}, mount_point: '{}'",
-                    event.msg, mnt_pnt
+                    "{}, mount_points: '{}', read_only: {}",
+                    event.msg,
+                    mnt_pnts.join(","),
+                    read_only
                 );
                 event
                     .extension
-                    .insert("mount_point".to_string(), mnt_pnt.clone());
+                    .insert("mount_points".to_string(), mnt_pnts.join(","));
+                event
+                    .extension
+                    .insert("read_only".to_string(), read_only.to_string());
             }
             event.dev_path = blk_info.blk_path.clone();
-            event.owners_wwids = blk_info.owners_wwids;
-            event.owners_paths = blk_info.owners_paths;
+            event.owners_wwids = blk_info.owners_wwids();
+            event.owners_paths = blk_info.owners_paths();
             event.owners_wwids.insert(0, blk_info.wwid);
             event.owners_paths.insert(0, blk_info.blk_path);
             event
@@ -45,34 +69,44 @@ fn parse_event(event: &StorageEvent, sender: &Sender<StorageEvent>) {
                 .insert("uuid".to_string(), uuid.clone());
             event.dev_wwid = uuid;
 
-            if let Err(e) = sender.send(event) {
-                println!("fs_parser: Failed to send event: {}", e);
-            }
+            Some(event)
+        }
+        Err(e) => {
+            println!("fs_parser: {}", e);
+            None
         }
-        Err(e) => println!("fs_parser: {}", e),
     }
 }
 
-pub fn parser_start(sender: Sender<StorageEvent>) -> ParserInfo {
-    let (event_in_sender, event_in_recver) = mpsc::channel();
-    let name = "fs".to_string();
-    let filter_event_type = vec![EventType::Raw];
-    let filter_event_subsys = vec![
-        StorageSubSystem::FsExt4,
-        StorageSubSystem::FsXfs,
-    ];
+pub struct FsParser {
+    sys_root: SystemRoot,
+}
 
-    spawn(move || loop {
-        match event_in_recver.recv() {
-            Ok(event) => parse_event(&event, &sender),
-            Err(e) => println!("fs_parser: Failed to receive event: {}", e),
+impl FsParser {
+    pub fn new(sys_root: &SystemRoot) -> FsParser {
+        FsParser {
+            sys_root: sys_root.clone(),
         }
-    });
+    }
+}
+
+impl Parser for FsParser {
+    fn name(&self) -> &str {
+        "fs"
+    }
+
+    fn filter_event_type(&self) -> Vec<EventType> {
+        vec![EventType::Raw]
+    }
+
+    fn filter_event_subsys(&self) -> Option<Vec<StorageSubSystem>> {
+        Some(vec![
+            StorageSubSystem::FsExt4,
+            StorageSubSystem::FsXfs,
+        ])
+    }
 
-    ParserInfo {
-        sender: event_in_sender,
-        name: name,
-        filter_event_type: filter_event_type,
-        filter_event_subsys: Some(filter_event_subsys),
+    fn parse(&mut self, event: &StorageEvent) -> Vec<StorageEvent> {
+        parse_event(event, &self.sys_root).into_iter().collect()
     }
 }
@@ -0,0 +1,294 @@
+// Notifier running local commands on events, for paging, opening tickets or
+// triggering failover scripts.
+//
+// The event is passed as JSON to stdin of the command, with these
+// environment variables set: EVENT_ID, EVENT_TYPE, SEVERITY, SUB_SYSTEM,
+// DEV_WWID and DEV_PATH.
+
+use conf::ConfHook;
+use peripety::{EventTypeInfo, LogSeverity, StorageEvent, StorageSubSystem};
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{sleep, Builder};
+use std::time::{Duration, Instant};
+
+static DEFAULT_HOOK_MAX_CONCURRENT: usize = 4;
+static DEFAULT_HOOK_TIMEOUT: u64 = 30;
+static DEFAULT_HOOK_QUEUE_SIZE: usize = 100;
+// Interval of checking whether command exited.
+static HOOK_POLL_INTERVAL_MS: u64 = 100;
+
+pub struct Hook {
+    name: String,
+    severity: Option<LogSeverity>,
+    event_types: Option<Vec<String>>,
+    sub_systems: Option<Vec<StorageSubSystem>>,
+    // Queue of events consumed by worker threads.
+    sender: SyncSender<StorageEvent>,
+    // Events dropped due to full queue.
+    dropped: AtomicUsize,
+}
+
+fn run_command(conf: &ConfHook, timeout: Duration, event: &StorageEvent) {
+    let json = match event.to_json_string() {
+        Ok(j) => j,
+        Err(e) => {
+            println!("{}_hook: {}", conf.name, e);
+            return;
+        }
+    };
+    let mut child = match Command::new(&conf.exec)
+        .args(conf.args.clone().unwrap_or_default())
+        .env("EVENT_ID", &event.event_id)
+        .env("EVENT_TYPE", &event.event_type)
+        .env("SEVERITY", format!("{:?}", event.severity))
+        .env("SUB_SYSTEM", event.sub_system.to_string())
+        .env("DEV_WWID", &event.dev_wwid)
+        .env("DEV_PATH", &event.dev_path)
+        .stdin(Stdio::piped())
+        .spawn()
+    {
+        Ok(c) => c,
+        Err(e) => {
+            println!("{}_hook: Failed to run {}: {}", conf.name, conf.exec, e);
+            return;
+        }
+    };
+    // Command not reading stdin is not an error. Written by a thread so
+    // the timeout also applies to command not reading stdin, the write fails
+    // once the command is killed. Dropping stdin to send EOF.
+    if let Some(mut stdin) = child.stdin.take() {
+        let ret = Builder::new()
+            .name(format!("{}_hook_stdin", conf.name))
+            .spawn(move || {
+                let _ = stdin.write_all(json.as_bytes());
+            });
+        if let Err(e) = ret {
+            println!("{}_hook: Failed to start stdin thread: {}", conf.name, e);
+        }
+    }
+
+    let start = Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => {
+                if !status.success() {
+                    println!(
+                        "{}_hook: {} failed on event {}: {}",
+                        conf.name, conf.exec, event.event_id, status
+                    );
+                }
+                return;
+            }
+            Ok(None) => {
+                if start.elapsed() >= timeout {
+                    println!(
+                        "{}_hook: {} timeout on event {}, killing",
+                        conf.name, conf.exec, event.event_id
+                    );
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return;
+                }
+                sleep(Duration::from_millis(HOOK_POLL_INTERVAL_MS));
+            }
+            Err(e) => {
+                println!(
+                    "{}_hook: Failed to wait {}: {}",
+                    conf.name, conf.exec, e
+                );
+                return;
+            }
+        }
+    }
+}
+
+fn worker(
+    conf: &ConfHook,
+    timeout: Duration,
+    recver: &Arc<Mutex<Receiver<StorageEvent>>>,
+) {
+    loop {
+        // The lock is released before running the command.
+        let event = match recver.lock() {
+            Ok(r) => r.recv(),
+            Err(_) => return,
+        };
+        match event {
+            Ok(event) => run_command(conf, timeout, &event),
+            Err(_) => return,
+        }
+    }
+}
+
+impl Hook {
+    // Validate the config and start the worker threads.
+    pub fn new(conf: &ConfHook) -> Result<Hook, String> {
+        let severity = match conf.severity {
+            Some(ref s) => {
+                Some(s.parse::<LogSeverity>().map_err(|e| format!("{}", e))?)
+            }
+            None => None,
+        };
+        if let Some(ref ets) = conf.event_types {
+            for et in ets {
+                EventTypeInfo::get(et).map_err(|e| format!("{}", e))?;
+            }
+        }
+        let sub_systems = match conf.sub_systems {
+            Some(ref subs) => {
+                let mut sub_systems = Vec::new();
+                for s in subs {
+                    sub_systems.push(
+                        s.parse::<StorageSubSystem>()
+                            .map_err(|e| format!("{}", e))?,
+                    );
+                }
+                Some(sub_systems)
+            }
+            None => None,
+        };
+        let max_concurrent = match conf.max_concurrent {
+            Some(0) => return Err("max_concurrent cannot be 0".to_string()),
+            Some(m) => m,
+            None => DEFAULT_HOOK_MAX_CONCURRENT,
+        };
+        let queue_size = match conf.queue_size {
+            Some(0) => return Err("queue_size cannot be 0".to_string()),
+            Some(q) => q,
+            None => DEFAULT_HOOK_QUEUE_SIZE,
+        };
+        let timeout =
+            Duration::from_secs(conf.timeout.unwrap_or(DEFAULT_HOOK_TIMEOUT));
+
+        let (sender, recver) = mpsc::sync_channel(queue_size);
+        let recver = Arc::new(Mutex::new(recver));
+        for i in 0..max_concurrent {
+            let conf = conf.clone();
+            let recver = recver.clone();
+            Builder::new()
+                .name(format!("{}_hook_{}", conf.name, i))
+                .spawn(move || worker(&conf, timeout, &recver))
+                .map_err(|e| format!("Failed to start worker thread: {}", e))?;
+        }
+
+        Ok(Hook {
+            name: conf.name.clone(),
+            severity,
+            event_types: conf.event_types.clone(),
+            sub_systems,
+            sender,
+            dropped: AtomicUsize::new(0),
+        })
+    }
+
+    fn is_match(&self, event: &StorageEvent) -> bool {
+        if let Some(ref s) = self.severity {
+            if s < &event.severity {
+                return false;
+            }
+        }
+        if let Some(ref ets) = self.event_types {
+            if !ets.contains(&event.event_type) {
+                return false;
+            }
+        }
+        if let Some(ref subs) = self.sub_systems {
+            if !subs.contains(&event.sub_system) {
+                return false;
+            }
+        }
+        true
+    }
+
+    // Queue the event if matches the filters, never blocks. The event is
+    // dropped if the queue is full.
+    pub fn notify(&self, event: &StorageEvent) {
+        if !self.is_match(event) {
+            return;
+        }
+        match self.sender.try_send(event.clone()) {
+            Ok(_) => (),
+            Err(TrySendError::Full(_)) => {
+                let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+                println!(
+                    "{}_hook: Queue full, dropped event {}, {} dropped in \
+                     total",
+                    self.name, event.event_id, dropped
+                );
+            }
+            Err(e) => {
+                println!("{}_hook: Failed to queue event: {}", self.name, e)
+            }
+        }
+    }
+}
+
+pub fn new_hooks(confs: &[ConfHook]) -> Vec<Hook> {
+    let mut hooks = Vec::new();
+    for conf in confs {
+        match Hook::new(conf) {
+            Ok(h) => {
+                println!("{}_hook: Ready", conf.name);
+                hooks.push(h);
+            }
+            Err(e) => println!("{}_hook: Invalid config: {}", conf.name, e),
+        }
+    }
+    hooks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conf(script: &str, timeout: u64) -> ConfHook {
+        ConfHook {
+            name: "test".to_string(),
+            exec: "sh".to_string(),
+            args: Some(vec!["-c".to_string(), script.to_string()]),
+            severity: None,
+            event_types: None,
+            sub_systems: None,
+            max_concurrent: Some(1),
+            queue_size: Some(1),
+            timeout: Some(timeout),
+        }
+    }
+
+    fn event(id: &str) -> StorageEvent {
+        StorageEvent {
+            event_id: id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_timeout_covers_stdin() {
+        // Never read stdin, so writing large event blocks.
+        let mut event = event("a");
+        event.msg = "a".repeat(1 << 20);
+        let start = Instant::now();
+        run_command(&conf("exec sleep 30", 30), Duration::from_secs(1), &event);
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_queue_full() {
+        let hook = Hook::new(&conf("sleep 2", 5)).unwrap();
+        for i in 0..5 {
+            hook.notify(&event(&format!("{}", i)));
+        }
+        // One running and one queued at most.
+        assert!(hook.dropped.load(Ordering::Relaxed) >= 3);
+        assert!(Hook::new(&ConfHook {
+            queue_size: Some(0),
+            ..conf("true", 5)
+        })
+        .is_err());
+    }
+}
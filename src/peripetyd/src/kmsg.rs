@@ -0,0 +1,253 @@
+// Reader of kernel log buffer `/dev/kmsg` for hosts without systemd journal,
+// like initramfs or minimal containers.
+//
+// Each record is converted to the same fields journald provides for kernel
+// log, so collector could handle both in the same way. Record format is
+// documented in kernel Documentation/ABI/testing/dev-kmsg:
+//
+//  6,1239,9436467326,-;sd 6:0:0:0: [sdf] tag#2 FAILED Result: ...
+//   SUBSYSTEM=scsi
+//   DEVICE=+scsi:6:0:0:0
+
+use data::{get_boot_id, get_hostname};
+use nix::libc;
+use peripety::SystemRoot;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::str;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+static KMSG_PATH: &str = "/dev/kmsg";
+// Kernel never returns record larger than this.
+static KMSG_RECORD_MAX_SIZE: usize = 8192;
+
+pub struct Kmsg {
+    file: File,
+    hostname: String,
+    boot_id: String,
+    // Realtime of boot in microseconds, record timestamp is monotonic.
+    boot_time_usec: i64,
+    last_seq: Option<u64>,
+    buff: Vec<u8>,
+}
+
+fn monotonic_usec() -> i64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe {
+        libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts);
+    }
+    ts.tv_sec * 1_000_000 + ts.tv_nsec / 1000
+}
+
+fn realtime_usec() -> i64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as i64 * 1_000_000 + d.subsec_micros() as i64,
+        Err(_) => 0,
+    }
+}
+
+// Kernel escapes non-printable characters and backslash as '\xNN'.
+fn unescape(msg: &str) -> String {
+    let bytes = msg.as_bytes();
+    let mut ret = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' && i + 4 <= bytes.len() && bytes[i + 1] == b'x' {
+            if let Ok(h) = str::from_utf8(&bytes[i + 2..i + 4]) {
+                if let Ok(b) = u8::from_str_radix(h, 16) {
+                    ret.push(b);
+                    i += 4;
+                    continue;
+                }
+            }
+        }
+        ret.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&ret).to_string()
+}
+
+// Userspace could also write to /dev/kmsg, like 'systemd[1]: Started ...'.
+// Return the identifier and the message without it.
+fn parse_identifier(msg: &str) -> Option<(String, String)> {
+    let i = msg.find(": ")?;
+    let ident = match msg[..i].find('[') {
+        Some(j) if msg[..i].ends_with(']') => &msg[..j],
+        _ => &msg[..i],
+    };
+    if ident.is_empty() || ident.contains(' ') {
+        return None;
+    }
+    Some((ident.to_string(), msg[i + 2..].to_string()))
+}
+
+// Convert kmsg record to journal fields. The `boot_time_usec` is the
+// realtime of boot as record only holds monotonic timestamp.
+pub fn parse_record(
+    record: &str,
+    boot_time_usec: i64,
+) -> Option<HashMap<String, String>> {
+    let mut lines = record.lines();
+    let line = lines.next()?;
+    let i = line.find(';')?;
+    let mut fields = line[..i].split(',');
+    let prio = fields.next()?.parse::<u32>().ok()?;
+    let seq = fields.next()?.parse::<u64>().ok()?;
+    let ts = fields.next()?.parse::<i64>().ok()?;
+
+    let mut entry = HashMap::new();
+    let mut msg = unescape(&line[i + 1..]);
+    let facility = prio >> 3;
+    if facility == 0 {
+        entry.insert("SYSLOG_IDENTIFIER".to_string(), "kernel".to_string());
+    } else {
+        let (ident, m) = parse_identifier(&msg)?;
+        entry.insert("SYSLOG_IDENTIFIER".to_string(), ident);
+        msg = m;
+    }
+    entry.insert("MESSAGE".to_string(), msg);
+    entry.insert("PRIORITY".to_string(), format!("{}", prio & 7));
+    entry.insert(
+        "__REALTIME_TIMESTAMP".to_string(),
+        format!("{}", boot_time_usec + ts),
+    );
+    // Sequence number is unique within the boot.
+    entry.insert("KMSG_SEQNUM".to_string(), format!("{}", seq));
+
+    for line in lines {
+        let line = match line.strip_prefix(' ') {
+            Some(l) => l,
+            None => continue,
+        };
+        if let Some(v) = line.strip_prefix("SUBSYSTEM=") {
+            entry.insert("_KERNEL_SUBSYSTEM".to_string(), unescape(v));
+        } else if let Some(v) = line.strip_prefix("DEVICE=") {
+            entry.insert("_KERNEL_DEVICE".to_string(), unescape(v));
+        }
+    }
+    Some(entry)
+}
+
+impl Kmsg {
+    pub fn new() -> Result<Kmsg, String> {
+        let mut file = File::open(KMSG_PATH)
+            .map_err(|e| format!("Failed to open {}: {}", KMSG_PATH, e))?;
+        // Jump to the end as we cannot annotate old log entries.
+        file.seek(SeekFrom::End(0))
+            .map_err(|e| format!("Failed to seek {}: {}", KMSG_PATH, e))?;
+        Ok(Kmsg {
+            file,
+            hostname: get_hostname(),
+            boot_id: get_boot_id(&SystemRoot::default()),
+            boot_time_usec: realtime_usec() - monotonic_usec(),
+            last_seq: None,
+            buff: vec![0u8; KMSG_RECORD_MAX_SIZE],
+        })
+    }
+
+    fn parse_record(
+        &mut self,
+        record: &str,
+    ) -> Option<HashMap<String, String>> {
+        let mut entry = parse_record(record, self.boot_time_usec)?;
+        entry.insert("_HOSTNAME".to_string(), self.hostname.clone());
+        entry.insert("_BOOT_ID".to_string(), self.boot_id.clone());
+
+        let seq = entry.get("KMSG_SEQNUM")?.parse::<u64>().ok()?;
+        if let Some(last_seq) = self.last_seq {
+            if seq > last_seq + 1 {
+                println!(
+                    "collector: Lost {} kernel messages due to kmsg ring \
+                     buffer overrun",
+                    seq - last_seq - 1
+                );
+            }
+        }
+        self.last_seq = Some(seq);
+        Some(entry)
+    }
+}
+
+// Block till next kernel log. Return None on unrecoverable error.
+impl Iterator for Kmsg {
+    type Item = HashMap<String, String>;
+
+    fn next(&mut self) -> Option<HashMap<String, String>> {
+        loop {
+            let len = match self.file.read(&mut self.buff) {
+                Ok(l) => l,
+                Err(e) => {
+                    // Our position was overwritten, next read() will start
+                    // from the oldest record. The lost count is reported
+                    // once we got that record.
+                    if e.raw_os_error() == Some(libc::EPIPE)
+                        || e.raw_os_error() == Some(libc::EINTR)
+                    {
+                        continue;
+                    }
+                    println!(
+                        "collector: Failed to read {}: {}",
+                        KMSG_PATH, e
+                    );
+                    return None;
+                }
+            };
+            let record =
+                String::from_utf8_lossy(&self.buff[..len]).to_string();
+            if let Some(entry) = self.parse_record(&record) {
+                return Some(entry);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_record() {
+        let entry = parse_record(
+            "3,1239,9436467326,-;sd 6:0:0:0: [sdf] tag#2 Add. Sense: \
+             Unrecovered read error\\x0a\n \
+             SUBSYSTEM=scsi\n \
+             DEVICE=+scsi:6:0:0:0\n",
+            1_000_000,
+        )
+        .unwrap();
+        assert_eq!(entry["SYSLOG_IDENTIFIER"], "kernel");
+        assert_eq!(
+            entry["MESSAGE"],
+            "sd 6:0:0:0: [sdf] tag#2 Add. Sense: Unrecovered read error\n"
+        );
+        assert_eq!(entry["PRIORITY"], "3");
+        assert_eq!(entry["KMSG_SEQNUM"], "1239");
+        assert_eq!(entry["__REALTIME_TIMESTAMP"], "9437467326");
+        assert_eq!(entry["_KERNEL_SUBSYSTEM"], "scsi");
+        assert_eq!(entry["_KERNEL_DEVICE"], "+scsi:6:0:0:0");
+    }
+
+    #[test]
+    fn test_parse_userspace_record() {
+        // Facility 3(daemon), priority 4.
+        let entry =
+            parse_record("28,7,100,-;multipathd[512]: sdb: failed\n", 0)
+                .unwrap();
+        assert_eq!(entry["SYSLOG_IDENTIFIER"], "multipathd");
+        assert_eq!(entry["MESSAGE"], "sdb: failed");
+        assert_eq!(entry["PRIORITY"], "4");
+        assert!(!entry.contains_key("_KERNEL_DEVICE"));
+    }
+
+    #[test]
+    fn test_parse_invalid_record() {
+        assert_eq!(parse_record("", 0), None);
+        assert_eq!(parse_record("6,1,2 no separator", 0), None);
+        assert_eq!(parse_record("6,x,2,-;msg", 0), None);
+        assert_eq!(parse_record("6,1;msg", 0), None);
+    }
+}
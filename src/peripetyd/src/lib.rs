@@ -0,0 +1,392 @@
+extern crate chan_signal;
+extern crate nix;
+extern crate peripety;
+extern crate regex;
+extern crate sdjournal;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate toml;
+extern crate chrono;
+
+mod collector;
+mod conf;
+mod data;
+mod fs;
+mod hook;
+mod kmsg;
+mod mpath;
+mod parser;
+mod plugin;
+mod replay;
+mod scsi;
+mod buildin_regex;
+mod blk;
+mod daemon;
+mod dedup;
+mod root_cause;
+mod udev;
+
+use chan_signal::Signal;
+use conf::ConfMain;
+use data::ParserInfo;
+use dedup::Dedup;
+use peripety::{EventStore, JournaldStore, JsonFileStore, StorageEvent,
+               SystemRoot};
+use root_cause::RootCause;
+
+pub use data::EventType;
+pub use parser::{Parser, ParserRegistry};
+pub use replay::replay;
+use std::sync::mpsc;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::thread::{sleep, Builder};
+use std::time::Duration;
+
+static DEFAULT_ROOT_CAUSE_WINDOW: u64 = 10;
+static DEFAULT_DEDUP_WINDOW: u64 = 2;
+static DEFAULT_STORE_FILE: &str = "/var/lib/peripety/events.json";
+const STORE_JOURNALD: &str = "journald";
+const STORE_FILE: &str = "file";
+// Replay is done once no event comes out of parsers for this many seconds.
+static REPLAY_IDLE_TIMEOUT: u64 = 3;
+
+// Event store selected in config, None if saving is disabled.
+fn new_store(daemon_conf: &Option<ConfMain>) -> Option<Box<dyn EventStore>> {
+    let c = match *daemon_conf {
+        Some(ref c) => c,
+        None => return Some(Box::new(JournaldStore::new())),
+    };
+    match c.store.as_deref() {
+        Some(STORE_FILE) => {
+            let path = c.store_file
+                .clone()
+                .unwrap_or_else(|| DEFAULT_STORE_FILE.to_string());
+            let mut store = JsonFileStore::new(&path);
+            if let Some(s) = c.store_file_max_size {
+                store.max_size = s * 1024 * 1024;
+            }
+            if let Some(a) = c.store_file_max_age {
+                store.max_age = a * 24 * 60 * 60;
+            }
+            if let Some(r) = c.store_file_rotate_count {
+                store.rotate_count = r;
+            }
+            Some(Box::new(store))
+        }
+        Some(s) if s != STORE_JOURNALD => {
+            println!("Invalid store '{}', using journald", s);
+            Some(Box::new(JournaldStore::new()))
+        }
+        _ => match c.save_to_journald {
+            Some(false) => None,
+            _ => Some(Box::new(JournaldStore::new())),
+        },
+    }
+}
+
+// Send event to parsers requiring this type of event.
+fn send_to_parsers(
+    event: &StorageEvent,
+    parsers: &[ParserInfo],
+    event_type: &EventType,
+) {
+    for parser in parsers {
+        let required = match parser.filter_event_type.contains(event_type) {
+            true => match parser.filter_event_subsys {
+                None => true,
+                Some(ref syss) => syss.contains(&event.sub_system),
+            },
+            false => false,
+        };
+        if required {
+            if let Err(e) = parser.sender.send(event.clone()) {
+                println!(
+                    "Failed to send event to parser {}: {}",
+                    parser.name, e
+                );
+            }
+        }
+    }
+}
+
+// Send to daemon output(stdout and journald), or to the replay result.
+fn notify_event(
+    event: &StorageEvent,
+    parsers: &[ParserInfo],
+    output: &mut dyn FnMut(&StorageEvent),
+) {
+    output(event);
+
+    // Send to parser if parser require it.
+    send_to_parsers(event, parsers, &EventType::Synthetic);
+}
+
+// Pass events through root cause analysis if enabled, then notify.
+fn root_cause_and_notify(
+    events: Vec<StorageEvent>,
+    root_cause: &mut Option<RootCause>,
+    parsers: &[ParserInfo],
+    output: &mut dyn FnMut(&StorageEvent),
+) {
+    for mut event in events {
+        if let Some(ref mut r) = *root_cause {
+            for summary in r.process(&mut event) {
+                notify_event(&summary, parsers, output);
+            }
+        }
+        notify_event(&event, parsers, output);
+    }
+}
+
+fn handle_events_from_parsers(
+    recver: &Receiver<StorageEvent>,
+    parsers: &[ParserInfo],
+    daemon_conf: Option<ConfMain>,
+    output: &mut dyn FnMut(&StorageEvent),
+    exit_on_idle: bool,
+) {
+    let mut root_cause_window = DEFAULT_ROOT_CAUSE_WINDOW;
+    let mut dedup_window = DEFAULT_DEDUP_WINDOW;
+    if let Some(c) = daemon_conf {
+        if let Some(w) = c.root_cause_window {
+            root_cause_window = w;
+        }
+        if let Some(w) = c.dedup_window {
+            dedup_window = w;
+        }
+    }
+    let mut root_cause = match root_cause_window {
+        0 => None,
+        w => Some(RootCause::new(w)),
+    };
+    let mut dedup = match dedup_window {
+        0 => None,
+        w => Some(Dedup::new(w)),
+    };
+    loop {
+        // Wake up periodically to send out held events and summary events
+        // of root causes.
+        let timeout = match exit_on_idle {
+            true => REPLAY_IDLE_TIMEOUT,
+            false => 1,
+        };
+        let event = match recver.recv_timeout(Duration::from_secs(timeout)) {
+            Ok(e) => e,
+            Err(RecvTimeoutError::Timeout) => {
+                if let Some(ref mut d) = dedup {
+                    root_cause_and_notify(
+                        d.expire_now(),
+                        &mut root_cause,
+                        parsers,
+                        output,
+                    );
+                }
+                if let Some(ref mut r) = root_cause {
+                    for summary in r.expire_now() {
+                        notify_event(&summary, parsers, output);
+                    }
+                }
+                if exit_on_idle {
+                    return;
+                }
+                continue;
+            }
+            Err(e) => {
+                println!("Failed to receive event from parsers: {}", e);
+                continue;
+            }
+        };
+
+        // Parsers should only generate registered event types. The raw
+        // kernel log not matching any regex has no event type.
+        if !event.event_type.is_empty() {
+            if let Err(e) = event.event_type_info() {
+                println!("BUG: Got event from parsers: {}", e);
+            }
+        }
+
+        let events = match dedup {
+            Some(ref mut d) => d.process(event),
+            None => vec![event],
+        };
+        root_cause_and_notify(events, &mut root_cause, parsers, output);
+    }
+}
+
+fn collector_to_parsers(
+    collector_recv: &Receiver<StorageEvent>,
+    parsers: &[ParserInfo],
+) {
+    loop {
+        match collector_recv.recv() {
+            Ok(event) => send_to_parsers(&event, parsers, &EventType::Raw),
+            Err(e) => {
+                println!("Failed to retrieve event from collector: {}", e);
+                return;
+            }
+        }
+    }
+}
+
+// Event store daemon saves events to as configured in /etc/peripetyd.conf,
+// for tools querying saved events like prpt. Journald store is used when
+// not configured or saving is disabled.
+pub fn daemon_store() -> Result<Box<dyn EventStore>, String> {
+    let daemon_conf = conf::read_conf()?.map(|c| c.main);
+    match new_store(&daemon_conf) {
+        Some(s) => Ok(s),
+        None => Ok(Box::new(JournaldStore::new())),
+    }
+}
+
+// Register event types of plugin parsers configured in
+// /etc/peripetyd.conf, for tools filtering or listing events like prpt.
+pub fn register_plugin_event_types() -> Result<(), String> {
+    match conf::read_conf()? {
+        Some(c) => c.register_event_types(),
+        None => Ok(()),
+    }
+}
+
+// Run as daemon against the live system.
+pub fn run() {
+    run_with_parsers(ParserRegistry::buildin());
+}
+
+// Run as daemon with extra parsers registered, like:
+//
+//  let mut registry = ParserRegistry::buildin();
+//  registry.register(|s| Box::new(MyParser::new(s)));
+//  peripetyd::run_with_parsers(registry);
+pub fn run_with_parsers(registry: ParserRegistry) {
+    let (collector_send, collector_recv) = mpsc::channel();
+    let (notifier_send, notifier_recv) = mpsc::channel();
+    let (conf_send, conf_recv) = mpsc::channel();
+
+    let mut daemon_conf = None;
+    let mut collector_conf = None;
+    let mut parsers_conf = None;
+    let mut plugin_confs = Vec::new();
+    let mut hook_confs = Vec::new();
+    if let Some(c) = conf::load_conf() {
+        daemon_conf = Some(c.main);
+        collector_conf = Some(c.collector);
+        parsers_conf = c.parsers;
+        plugin_confs = c.plugin_parsers.unwrap_or_default();
+        hook_confs = c.hooks.unwrap_or_default();
+    }
+
+    let conf_changed_signal = chan_signal::notify(&[Signal::HUP]);
+
+    let sys_root = SystemRoot::default();
+
+    // 1. Start parser threads
+    let mut registry = registry;
+    registry.register_plugins(&plugin_confs);
+    let parsers = registry.start(
+        &notifier_send,
+        &sys_root,
+        parsers_conf.as_ref(),
+    );
+
+    let parsers_clone = parsers.clone();
+    let skip_stdout = match daemon_conf {
+        Some(ref c) => c.notify_stdout != Some(true),
+        None => true,
+    };
+    let hooks = hook::new_hooks(&hook_confs);
+
+    // 2. Start thread for forwarding collector output to parsers.
+    Builder::new()
+        .name("collector_to_parser".into())
+        .spawn(move || {
+            collector_to_parsers(&collector_recv, &parsers);
+        })
+        .expect("Failed to start 'collector_to_parser' thread");
+
+    // 3. Start thread for forwarding parsers output to parsers and notifier.
+    Builder::new()
+        .name("handle_events_from_parsers".into())
+        .spawn(move || {
+            // Journal handle cannot be sent between threads.
+            let mut store = new_store(&daemon_conf);
+            handle_events_from_parsers(
+                &notifier_recv,
+                &parsers_clone,
+                daemon_conf,
+                &mut |event| {
+                    // Send to stdout
+                    if !skip_stdout {
+                        if let Ok(s) = event.to_json_string_pretty() {
+                            println!("{}", s);
+                        }
+                    }
+                    // Save to journald or file.
+                    // TODO(Gris Ge): Invoke a thread of this in case
+                    //                sdjournal slows us.
+                    if let Some(ref mut s) = store {
+                        if let Err(e) = s.save(event) {
+                            println!("Failed to save event: {}", e);
+                        }
+                    }
+                    // Run hooks in their own threads.
+                    for hook in &hooks {
+                        hook.notify(event);
+                    }
+                },
+                false,
+            );
+        })
+        .expect("Failed to start 'handle_events_from_parsers' thread");
+
+    // TODO(Gris Ge): Need better way for waiting threads to be ready.
+    sleep(Duration::from_secs(1));
+
+    // 4. Start collector thread
+    let source = match collector_conf.as_ref().and_then(|c| c.source.clone())
+    {
+        Some(ref s) if s == collector::SOURCE_KMSG => collector::SOURCE_KMSG,
+        Some(ref s) if s != collector::SOURCE_JOURNALD => {
+            println!("Invalid collector source '{}', using journald", s);
+            collector::SOURCE_JOURNALD
+        }
+        _ => collector::SOURCE_JOURNALD,
+    };
+    Builder::new()
+        .name("collector".into())
+        .spawn(move || {
+            collector::new(&collector_send, &conf_recv, source);
+        })
+        .expect("Failed to start 'collector' thread");
+
+    // 5. Start udev collector thread, its events need no parser.
+    let udev_sys_root = sys_root.clone();
+    Builder::new()
+        .name("udev_collector".into())
+        .spawn(move || {
+            udev::new(&notifier_send, &udev_sys_root);
+        })
+        .expect("Failed to start 'udev_collector' thread");
+
+    if let Some(c) = collector_conf {
+        conf_send
+            .send(c)
+            .expect("Failed to send config to collector");
+    }
+
+    println!("Peripetyd: Ready!");
+
+    loop {
+        if conf_changed_signal.recv().is_none() {
+            println!("Failed to recv() from signal channel");
+            continue;
+        }
+        if let Some(c) = conf::load_conf() {
+            if let Err(e) = conf_send.send(c.collector) {
+                println!("Failed to send config to collector: {}", e);
+                continue;
+            }
+        }
+    }
+}
@@ -1,15 +1,13 @@
-use data::{EventType, ParserInfo, Sysfs};
-use peripety::{BlkInfo, BlkType, StorageEvent, StorageSubSystem};
+use data::{EventType, Sysfs};
+use parser::Parser;
+use peripety::{BlkInfo, BlkType, StorageEvent, StorageSubSystem, SystemRoot};
 use regex::Regex;
 use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
-use std::sync::mpsc;
-use std::sync::mpsc::Sender;
-use std::thread::Builder;
 
-fn get_dm_name(dev_path: &str) -> Option<String> {
-    match fs::read_link(dev_path) {
+fn get_dm_name(dev_path: &str, sys_root: &SystemRoot) -> Option<String> {
+    match fs::read_link(sys_root.real_path(dev_path)) {
         // We don't do unicode check there as this are used internally
         // where no such non-utf8 concern.
         Ok(p) => {
@@ -32,8 +30,12 @@ fn get_dm_name(dev_path: &str) -> Option<String> {
 }
 
 //TODO(Gris Ge): Maybe we should be save iscsi/fc data into BlkInfo::new().
-fn iscsi_session_id_of_host(host_id: &str) -> Option<String> {
-    let path = format!("/sys/class/iscsi_host/host{}", host_id);
+fn iscsi_session_id_of_host(
+    host_id: &str,
+    sys_root: &SystemRoot,
+) -> Option<String> {
+    let path =
+        sys_root.sysfs_path(&format!("class/iscsi_host/host{}", host_id));
     let p = match fs::read_link(&path) {
         Ok(l) => match l.to_str() {
             Some(s) => s.to_string(),
@@ -56,7 +58,7 @@ fn iscsi_session_id_of_host(host_id: &str) -> Option<String> {
     {
         Some(c) => {
             match c.get(1) {
-                Some(d) => format!("/sys/{}", d.as_str()),
+                Some(d) => sys_root.sysfs_path(d.as_str()),
                 None => {
                     // should never happen, if does, silently return.
                     return None;
@@ -94,14 +96,18 @@ fn iscsi_session_id_of_host(host_id: &str) -> Option<String> {
     None
 }
 
-fn get_iscsi_info(host_id: &str) -> HashMap<String, String> {
+fn get_iscsi_info(
+    host_id: &str,
+    sys_root: &SystemRoot,
+) -> HashMap<String, String> {
     let mut ret = HashMap::new();
-    if let Some(sid) = iscsi_session_id_of_host(host_id) {
-        let session_dir = format!("/sys/class/iscsi_session/session{}", sid);
-        let conn_dir = format!(
-            "/sys/class/iscsi_connection/connection{}:0",
+    if let Some(sid) = iscsi_session_id_of_host(host_id, sys_root) {
+        let session_dir =
+            sys_root.sysfs_path(&format!("class/iscsi_session/session{}", sid));
+        let conn_dir = sys_root.sysfs_path(&format!(
+            "class/iscsi_connection/connection{}:0",
             sid
-        );
+        ));
         if !Path::new(&session_dir).exists() {
             return ret;
         }
@@ -133,13 +139,19 @@ fn get_iscsi_info(host_id: &str) -> HashMap<String, String> {
     ret
 }
 
-fn get_fc_info(host_id: &str, scsi_id: &str) -> HashMap<String, String> {
+fn get_fc_info(
+    host_id: &str,
+    scsi_id: &str,
+    sys_root: &SystemRoot,
+) -> HashMap<String, String> {
     let mut ret = HashMap::new();
     // fc-hosts are using the same host id with scsi host.
     if let Some(index) = scsi_id.rfind(":") {
         let target_id = &scsi_id[..index];
-        let target_dir = format!("/sys/class/fc_transport/target{}", target_id);
-        let host_dir = format!("/sys/class/fc_host/host{}", host_id);
+        let target_dir = sys_root
+            .sysfs_path(&format!("class/fc_transport/target{}", target_id));
+        let host_dir =
+            sys_root.sysfs_path(&format!("class/fc_host/host{}", host_id));
         if !Path::new(&host_dir).exists() {
             return ret;
         }
@@ -167,20 +179,24 @@ fn get_fc_info(host_id: &str, scsi_id: &str) -> HashMap<String, String> {
     ret
 }
 
-fn is_iscsi_host(host_id: &str) -> bool {
-    Path::new(&format!(
-        "/sys/class/iscsi_host/host{}",
+fn is_iscsi_host(host_id: &str, sys_root: &SystemRoot) -> bool {
+    Path::new(&sys_root.sysfs_path(&format!(
+        "class/iscsi_host/host{}",
         host_id
-    )).exists()
+    ))).exists()
 }
 
-fn is_fc_host(host_id: &str) -> bool {
-    Path::new(&format!("/sys/class/fc_host/host{}", host_id)).exists()
+fn is_fc_host(host_id: &str, sys_root: &SystemRoot) -> bool {
+    Path::new(&sys_root.sysfs_path(&format!("class/fc_host/host{}", host_id)))
+        .exists()
 }
 
-fn get_scsi_transport_info(sd_name: &str) -> HashMap<String, String> {
+fn get_scsi_transport_info(
+    sd_name: &str,
+    sys_root: &SystemRoot,
+) -> HashMap<String, String> {
     let mut ret = HashMap::new();
-    let scsi_id = match Sysfs::scsi_id_of_disk(sd_name) {
+    let scsi_id = match Sysfs::scsi_id_of_disk(sd_name, sys_root) {
         Some(s) => s,
         None => return ret,
     };
@@ -190,19 +206,19 @@ fn get_scsi_transport_info(sd_name: &str) -> HashMap<String, String> {
     };
     ret.insert(
         "driver_name".to_string(),
-        Sysfs::read(&format!(
-            "/sys/class/scsi_host/host{}/proc_name",
+        Sysfs::read(&sys_root.sysfs_path(&format!(
+            "class/scsi_host/host{}/proc_name",
             &host_id
-        )),
+        ))),
     );
-    if is_iscsi_host(&host_id) {
+    if is_iscsi_host(&host_id, sys_root) {
         ret.insert("transport".to_string(), "iSCSI".to_string());
-        for (key, value) in get_iscsi_info(&host_id) {
+        for (key, value) in get_iscsi_info(&host_id, sys_root) {
             ret.insert(key, value);
         }
-    } else if is_fc_host(&host_id) {
+    } else if is_fc_host(&host_id, sys_root) {
         ret.insert("transport".to_string(), "FC".to_string());
-        for (key, value) in get_fc_info(&host_id, &scsi_id) {
+        for (key, value) in get_fc_info(&host_id, &scsi_id, sys_root) {
             ret.insert(key, value);
         }
     }
@@ -210,10 +226,14 @@ fn get_scsi_transport_info(sd_name: &str) -> HashMap<String, String> {
     ret
 }
 
-fn get_mpath_info_from_blk(major_minor: &str) -> Option<(String, String)> {
+fn get_mpath_info_from_blk(
+    major_minor: &str,
+    sys_root: &SystemRoot,
+) -> Option<(String, String)> {
     // We use sysfs information to speed up things without cacheing.
     // TODO(Gris Ge): This function should return Result<>
-    let sysfs_holder_dir = format!("/sys/dev/block/{}/holders", major_minor);
+    let sysfs_holder_dir =
+        sys_root.sysfs_path(&format!("dev/block/{}/holders", major_minor));
     let mut holders = match fs::read_dir(&sysfs_holder_dir) {
         Ok(o) => o,
         Err(e) => {
@@ -239,9 +259,9 @@ fn get_mpath_info_from_blk(major_minor: &str) -> Option<(String, String)> {
             };
             let name_path = format!("{}/dm/name", dm);
             let uuid_path = format!("{}/dm/uuid", dm);
-            let mut uuid = Sysfs::read(&uuid_path);
+            // Keep the 'mpath-' prefix, same as the wwid of BlkInfo.
+            let uuid = Sysfs::read(&uuid_path);
             if uuid.starts_with("mpath-") {
-                uuid = uuid["mpath-".len()..].to_string();
                 return Some((Sysfs::read(&name_path), uuid));
             }
         }
@@ -254,48 +274,93 @@ fn get_mpath_info_from_blk(major_minor: &str) -> Option<(String, String)> {
     None
 }
 
-fn parse_event(event: &StorageEvent, sender: &Sender<StorageEvent>) {
+// The WWID multipath uses, the dm uuid without 'mpath-' prefix.
+fn mpath_wwid(dm_uuid: &str) -> &str {
+    dm_uuid.trim_start_matches("mpath-")
+}
+
+// The multipathd logs map name instead of path.
+fn parse_map_event(
+    event: &StorageEvent,
+    sys_root: &SystemRoot,
+) -> Option<StorageEvent> {
+    let blk_path = format!("/dev/mapper/{}", event.kdev);
+    let blk_info = match BlkInfo::new(&blk_path, sys_root) {
+        Ok(b) => b,
+        Err(e) => {
+            println!("mpath_parser: {}", e);
+            return None;
+        }
+    };
+    let mut event = event.to_synthetic("mpath");
+    event.dev_path = blk_info.blk_path.clone();
+    event.dev_wwid = blk_info.wwid.clone();
+    event.owners_wwids = blk_info.owners_wwids();
+    event.owners_paths = blk_info.owners_paths();
+    event.msg = format!(
+        "{} mpath_wwid: {}",
+        event.raw_msg,
+        mpath_wwid(&event.dev_wwid)
+    );
+    Some(event)
+}
+
+fn parse_event(
+    event: &StorageEvent,
+    sys_root: &SystemRoot,
+) -> Option<StorageEvent> {
     match event.event_type.as_ref() {
-        "DM_MPATH_PATH_FAILED" | "DM_MPATH_PATH_REINSTATED" => {
-            let (name, uuid) = match get_mpath_info_from_blk(&event.kdev) {
-                Some(t) => t,
-                None => return,
-            };
-            let mut event = event.clone();
+        "DM_MPATH_ALL_PATHS_DOWN" | "DM_MPATH_REMAINING_PATHS" => {
+            parse_map_event(event, sys_root)
+        }
+        "DM_MPATH_PATH_FAILED"
+        | "DM_MPATH_PATH_REINSTATED"
+        | "DM_MPATH_CHECKER_FAILED_PATH" => {
+            let (name, uuid) =
+                get_mpath_info_from_blk(&event.kdev, sys_root)?;
+            let mut event = event.to_synthetic("mpath");
             event.dev_path = format!("/dev/mapper/{}", name);
             event.dev_wwid = uuid;
-            let path_blk_name =  match BlkInfo::major_minor_to_blk_name(&event.kdev) {
+            let path_blk_name = match BlkInfo::major_minor_to_blk_name(
+                &event.kdev,
+                sys_root,
+            ) {
                 Ok(b) => b,
                 Err(e) => {
                     println!("mpath_parser: {}", e);
-                    return;
+                    return None;
                 },
             };
             event.msg = format!(
                 "{} mpath_wwid: {}, path_blk_name: {}",
-                event.raw_msg, event.dev_wwid, path_blk_name
+                event.raw_msg,
+                mpath_wwid(&event.dev_wwid),
+                path_blk_name
             );
-            let dm_name = match get_dm_name(&event.dev_path) {
+            let dm_name = match get_dm_name(&event.dev_path, sys_root) {
                 Some(d) => d,
                 None => {
                     println!(
                         "mpath_parser: Failed to find dm_name for {}",
                         &event.dev_path
                     );
-                    return;
+                    return None;
                 }
             };
 
-            match BlkInfo::new(&dm_name) {
+            match BlkInfo::new(&dm_name, sys_root) {
                 Ok(blk_info) => {
-                    event.owners_wwids = blk_info.owners_wwids;
-                    event.owners_paths = blk_info.owners_paths;
-                    if let Ok(pbi) = BlkInfo::new_skip_extra(&path_blk_name) {
+                    event.owners_wwids = blk_info.owners_wwids();
+                    event.owners_paths = blk_info.owners_paths();
+                    if let Ok(pbi) =
+                        BlkInfo::new_skip_extra(&path_blk_name, sys_root)
+                    {
                         if pbi.blk_type == BlkType::Scsi {
                             // Check for iSCSI/FC/FCoE informations.
-                            for (key, value) in
-                                get_scsi_transport_info(&path_blk_name)
-                            {
+                            for (key, value) in get_scsi_transport_info(
+                                &path_blk_name,
+                                sys_root,
+                            ) {
                                 event.msg.push_str(&format!(", {}={}", key, value));
                                 event.extension.insert(key, value);
                             }
@@ -304,51 +369,93 @@ fn parse_event(event: &StorageEvent, sender: &Sender<StorageEvent>) {
                 }
                 Err(e) => {
                     println!("mpath_parser: {}", e);
-                    return;
+                    return None;
                 }
             };
             event.extension.insert(
                 "blk_major_minor".to_string(),
                 event.kdev.clone(),
             );
-            if let Err(e) = sender.send(event) {
-                println!("mpath_parser: Failed to send event: {}", e);
-            }
+            Some(event)
         }
-        _ => println!(
-            "mpath: Got unknown event type: {}",
-            event.event_type
-        ),
-    };
+        _ => {
+            println!(
+                "mpath: Got unknown event type: {}",
+                event.event_type
+            );
+            None
+        }
+    }
 }
 
-pub fn parser_start(sender: Sender<StorageEvent>) -> ParserInfo {
-    let (event_in_sender, event_in_recver) = mpsc::channel();
-    let name = "mpath".to_string();
-    let filter_event_type = vec![EventType::Raw];
-    let filter_event_subsys = vec![StorageSubSystem::Multipath];
+pub struct MpathParser {
+    sys_root: SystemRoot,
+}
 
-    if let Err(e) = Builder::new()
-        .name("mpath_parser".into())
-        .spawn(move || loop {
-            match event_in_recver.recv() {
-                Ok(event) => parse_event(&event, &sender),
-                Err(e) => {
-                    println!("mpath_parser: Failed to retrieve event: {}", e)
-                }
-            };
-        }) {
-        panic!(
-            "mpath_parser: Failed to create parser thread: {}",
-            e
-        );
+impl MpathParser {
+    pub fn new(sys_root: &SystemRoot) -> MpathParser {
+        MpathParser {
+            sys_root: sys_root.clone(),
+        }
+    }
+}
+
+impl Parser for MpathParser {
+    fn name(&self) -> &str {
+        "mpath"
+    }
+
+    fn filter_event_type(&self) -> Vec<EventType> {
+        vec![EventType::Raw]
     }
 
-    println!("mpath_parser: Ready");
-    ParserInfo {
-        sender: event_in_sender,
-        name: name,
-        filter_event_type: filter_event_type,
-        filter_event_subsys: Some(filter_event_subsys),
+    fn filter_event_subsys(&self) -> Option<Vec<StorageSubSystem>> {
+        Some(vec![StorageSubSystem::Multipath])
+    }
+
+    fn parse(&mut self, event: &StorageEvent) -> Vec<StorageEvent> {
+        parse_event(event, &self.sys_root).into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use peripety::fixture::Fixture;
+
+    // Multipath map dm-0 on top of sda.
+    fn fixture() -> Fixture {
+        let f = Fixture::new("mpath");
+        f.add_blk("sda", "8:0");
+        f.write("/sys/block/sda/device/wwid", "naa.6001405\n");
+        f.add_blk("dm-0", "253:0");
+        f.write("/sys/block/dm-0/dm/name", "mpatha\n");
+        f.write("/sys/block/dm-0/dm/uuid", "mpath-36001405\n");
+        f.mkdir("/sys/block/dm-0/holders");
+        f.add_slave("dm-0", "sda");
+        f.symlink("../dm-0", "/dev/mapper/mpatha");
+        f
+    }
+
+    #[test]
+    fn test_dev_wwid() {
+        let f = fixture();
+        let sys_root = &f.sys_root;
+        let blk_info = BlkInfo::new("/dev/mapper/mpatha", sys_root).unwrap();
+        assert_eq!(blk_info.wwid, "mpath-36001405");
+
+        let event = StorageEvent {
+            event_type: "DM_MPATH_ALL_PATHS_DOWN".to_string(),
+            kdev: "mpatha".to_string(),
+            ..Default::default()
+        };
+        let event = parse_event(&event, sys_root).unwrap();
+        assert_eq!(event.dev_wwid, blk_info.wwid);
+        assert_eq!(event.owners_wwids, vec!["naa.6001405".to_string()]);
+
+        assert_eq!(
+            get_mpath_info_from_blk("8:0", sys_root),
+            Some(("mpatha".to_string(), blk_info.wwid.clone()))
+        );
     }
 }
@@ -0,0 +1,295 @@
+// Parsers turn raw events from collector or synthetic events from other
+// parsers into synthetic events.
+//
+// Each parser registered in `ParserRegistry` runs in its own thread named
+// `<name>_parser`. If a parser panics, it is recreated by its factory and
+// continues with the next event.
+
+use blk::BlkParser;
+use conf::ConfPluginParser;
+use daemon::DaemonParser;
+use data::{EventType, ParserInfo};
+use fs::FsParser;
+use mpath::MpathParser;
+use peripety::{StorageEvent, StorageSubSystem, SystemRoot};
+use plugin::PluginParser;
+use scsi::ScsiParser;
+use std::collections::HashSet;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::thread::{sleep, Builder};
+use std::time::Duration;
+use toml;
+
+// Wait this long before recreating a crashed parser, in case it crashes on
+// every event.
+static PARSER_RESTART_DELAY: u64 = 1;
+
+pub trait Parser: Send {
+    // Unique name, also used for thread name and config section.
+    fn name(&self) -> &str;
+
+    fn filter_event_type(&self) -> Vec<EventType>;
+
+    // None means events of all sub-systems.
+    fn filter_event_subsys(&self) -> Option<Vec<StorageSubSystem>>;
+
+    // Invoked before the first event with the `[parsers.<name>]` section of
+    // config file.
+    fn init(&mut self, _conf: Option<&toml::Value>) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn parse(&mut self, event: &StorageEvent) -> Vec<StorageEvent>;
+
+    // Parser holding events for follow up logs could ask tick() to be
+    // invoked after each event and at least once in this interval.
+    fn tick_interval(&self) -> Option<Duration> {
+        None
+    }
+
+    fn tick(&mut self) -> Vec<StorageEvent> {
+        Vec::new()
+    }
+}
+
+type ParserFactory = Box<dyn Fn(&SystemRoot) -> Box<dyn Parser> + Send>;
+
+pub struct ParserRegistry {
+    factories: Vec<ParserFactory>,
+}
+
+fn parser_loop(
+    parser: &mut dyn Parser,
+    recver: &Receiver<StorageEvent>,
+    sender: &Sender<StorageEvent>,
+) {
+    let interval = parser.tick_interval();
+    loop {
+        let event = match interval {
+            Some(i) => match recver.recv_timeout(i) {
+                Ok(event) => Some(event),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => return,
+            },
+            None => match recver.recv() {
+                Ok(event) => Some(event),
+                Err(_) => return,
+            },
+        };
+        let mut events = match event {
+            Some(ref e) => parser.parse(e),
+            None => Vec::new(),
+        };
+        if interval.is_some() {
+            events.extend(parser.tick());
+        }
+        for event in events {
+            if let Err(e) = sender.send(event) {
+                println!(
+                    "{}_parser: Failed to send event: {}",
+                    parser.name(),
+                    e
+                );
+            }
+        }
+    }
+}
+
+fn init_parser(
+    parser: &mut dyn Parser,
+    conf: Option<&toml::value::Table>,
+) -> Result<(), String> {
+    let conf = conf.and_then(|c| c.get(parser.name()));
+    parser.init(conf)
+}
+
+impl ParserRegistry {
+    pub fn new() -> ParserRegistry {
+        ParserRegistry {
+            factories: Vec::new(),
+        }
+    }
+
+    // Parsers shipped with peripetyd.
+    pub fn buildin() -> ParserRegistry {
+        let mut registry = ParserRegistry::new();
+        registry.register(|s| Box::new(MpathParser::new(s)));
+        registry.register(|s| Box::new(ScsiParser::new(s)));
+        registry.register(|s| Box::new(FsParser::new(s)));
+        registry.register(|s| Box::new(BlkParser::new(s)));
+        registry.register(|s| Box::new(DaemonParser::new(s)));
+        registry
+    }
+
+    // The factory is invoked once on start and again whenever the parser
+    // crashed.
+    pub fn register<F>(&mut self, factory: F)
+    where
+        F: Fn(&SystemRoot) -> Box<dyn Parser> + Send + 'static,
+    {
+        self.factories.push(Box::new(factory));
+    }
+
+    pub(crate) fn register_plugins(&mut self, confs: &[ConfPluginParser]) {
+        for conf in confs {
+            let conf = conf.clone();
+            self.register(move |_| Box::new(PluginParser::new(&conf)));
+        }
+    }
+
+    // Spawn all parsers, the `conf` is the `[parsers]` section of config
+    // file. Parser is skipped if failed to initialize or has
+    // `enabled = false` in its section.
+    pub(crate) fn start(
+        self,
+        sender: &Sender<StorageEvent>,
+        sys_root: &SystemRoot,
+        conf: Option<&toml::value::Table>,
+    ) -> Vec<ParserInfo> {
+        let mut ret = Vec::new();
+        let mut names = HashSet::new();
+        for factory in self.factories {
+            let mut parser = factory(sys_root);
+            let name = parser.name().to_string();
+            if !names.insert(name.clone()) {
+                println!("{}_parser: Duplicate parser name, skipped", name);
+                continue;
+            }
+            let enabled = conf
+                .and_then(|c| c.get(&name))
+                .and_then(|c| c.get("enabled"))
+                .and_then(|e| e.as_bool());
+            if enabled == Some(false) {
+                println!("{}_parser: Disabled", name);
+                continue;
+            }
+            if let Err(e) = init_parser(&mut *parser, conf) {
+                println!("{}_parser: Failed to initialize: {}", name, e);
+                continue;
+            }
+
+            let (event_in_sender, event_in_recver) = mpsc::channel();
+            let filter_event_type = parser.filter_event_type();
+            let filter_event_subsys = parser.filter_event_subsys();
+            let sender = sender.clone();
+            let sys_root = sys_root.clone();
+            let parser_conf = conf.cloned();
+            let thread_name = format!("{}_parser", name);
+            Builder::new()
+                .name(thread_name.clone())
+                .spawn(move || {
+                    let mut parser = parser;
+                    loop {
+                        let ret = panic::catch_unwind(AssertUnwindSafe(|| {
+                            parser_loop(&mut *parser, &event_in_recver, &sender)
+                        }));
+                        if ret.is_ok() {
+                            return;
+                        }
+                        println!("{}: Crashed, restarting", thread_name);
+                        sleep(Duration::from_secs(PARSER_RESTART_DELAY));
+                        parser = factory(&sys_root);
+                        if let Err(e) =
+                            init_parser(&mut *parser, parser_conf.as_ref())
+                        {
+                            println!(
+                                "{}: Failed to initialize: {}",
+                                thread_name, e
+                            );
+                            return;
+                        }
+                    }
+                })
+                .expect("Failed to start parser thread");
+
+            println!("{}_parser: Ready", name);
+            ret.push(ParserInfo {
+                sender: event_in_sender,
+                name,
+                filter_event_type,
+                filter_event_subsys,
+            });
+        }
+        ret
+    }
+}
+
+impl Default for ParserRegistry {
+    fn default() -> ParserRegistry {
+        ParserRegistry::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    // Panics on the first event it ever sees, echoes the others.
+    struct CrashParser {
+        name: String,
+        crashed: Arc<AtomicBool>,
+    }
+
+    impl Parser for CrashParser {
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        fn filter_event_type(&self) -> Vec<EventType> {
+            vec![EventType::Raw]
+        }
+
+        fn filter_event_subsys(&self) -> Option<Vec<StorageSubSystem>> {
+            None
+        }
+
+        fn parse(&mut self, event: &StorageEvent) -> Vec<StorageEvent> {
+            if !self.crashed.swap(true, Ordering::SeqCst) {
+                panic!("crash on purpose");
+            }
+            vec![event.clone()]
+        }
+    }
+
+    fn register_crash_parser(registry: &mut ParserRegistry, name: &str) {
+        let name = name.to_string();
+        let crashed = Arc::new(AtomicBool::new(false));
+        registry.register(move |_| {
+            Box::new(CrashParser {
+                name: name.clone(),
+                crashed: crashed.clone(),
+            })
+        });
+    }
+
+    #[test]
+    fn test_start() {
+        let mut registry = ParserRegistry::new();
+        register_crash_parser(&mut registry, "crash");
+        register_crash_parser(&mut registry, "crash");
+        register_crash_parser(&mut registry, "disabled");
+        let conf: toml::value::Table =
+            toml::from_str("[disabled]\nenabled = false\n").unwrap();
+        let (sender, recver) = mpsc::channel();
+        let parsers =
+            registry.start(&sender, &SystemRoot::default(), Some(&conf));
+        let names: Vec<&str> =
+            parsers.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["crash"]);
+
+        // Parser is recreated after crash and handles the next event.
+        let mut event: StorageEvent = Default::default();
+        event.raw_msg = "first".to_string();
+        parsers[0].sender.send(event.clone()).unwrap();
+        event.raw_msg = "second".to_string();
+        parsers[0].sender.send(event).unwrap();
+        let event = recver
+            .recv_timeout(Duration::from_secs(PARSER_RESTART_DELAY + 5))
+            .unwrap();
+        assert_eq!(event.raw_msg, "second");
+    }
+}
@@ -0,0 +1,438 @@
+// Parser running as external executable, for parsers which cannot be
+// upstreamed or are easier written in other languages.
+//
+// Protocol: each event matching the filters is written to stdin of the
+// executable as single line JSON:
+//
+//  {"request_id": <number>, "event": <event>}
+//
+// For each line, the executable should reply one line to stdout, echoing
+// the request ID with a JSON array of generated events, `[]` if nothing:
+//
+//  {"request_id": <number>, "events": [<event>, ...]}
+//
+// Reply lines of other request ID or not in this format are logged and
+// ignored. The stderr is shared with peripetyd.
+//
+// Generated events should use build-in event types or the ones declared in
+// `new_event_types` of config, others are dropped. Missing event ID is
+// derived from the event being parsed. Events generated by the executable
+// are not sent back to itself.
+//
+// The executable is killed if no reply in `timeout` seconds and is started
+// again on next event if not running.
+
+use conf::ConfPluginParser;
+use data::EventType;
+use parser::Parser;
+use peripety::{StorageEvent, StorageSubSystem};
+use serde_json::{self, Value};
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::thread::Builder;
+use std::time::{Duration, Instant};
+use toml;
+
+static DEFAULT_PLUGIN_TIMEOUT: u64 = 5;
+// Do not start the executable more often than this many seconds, events
+// came in between are dropped.
+static PLUGIN_RESTART_INTERVAL: u64 = 5;
+// Number of recently generated event IDs remembered for not sending them
+// back to the executable.
+static PLUGIN_EMITTED_CACHE_SIZE: usize = 1024;
+
+#[derive(Serialize)]
+struct PluginRequest<'a> {
+    request_id: u64,
+    event: &'a StorageEvent,
+}
+
+#[derive(Deserialize)]
+struct PluginReply {
+    request_id: u64,
+    events: Vec<Value>,
+}
+
+struct PluginProcess {
+    child: Child,
+    // Lines for stdin. Written by a thread, so a executable not reading
+    // stdin only blocks that thread till killed on timeout.
+    stdin: Sender<String>,
+    // Lines from stdout, disconnected once the executable exited.
+    lines: Receiver<String>,
+}
+
+impl Drop for PluginProcess {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+impl PluginProcess {
+    // Send the request and wait its reply.
+    fn request(
+        &self,
+        name: &str,
+        line: String,
+        request_id: u64,
+        timeout: Duration,
+    ) -> Result<Vec<Value>, &'static str> {
+        if self.stdin.send(line).is_err() {
+            return Err("Exited");
+        }
+        let deadline = Instant::now() + timeout;
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return Err("Timeout waiting reply");
+            }
+            let line = match self.lines.recv_timeout(deadline - now) {
+                Ok(l) => l,
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err("Timeout waiting reply")
+                }
+                Err(RecvTimeoutError::Disconnected) => return Err("Exited"),
+            };
+            match serde_json::from_str::<PluginReply>(&line) {
+                Ok(ref r) if r.request_id != request_id => println!(
+                    "{}_parser: Ignored reply of request {}, expecting {}",
+                    name, r.request_id, request_id
+                ),
+                Ok(r) => return Ok(r.events),
+                Err(e) => println!(
+                    "{}_parser: Ignored invalid reply '{}': {}",
+                    name, line, e
+                ),
+            }
+        }
+    }
+}
+
+pub struct PluginParser {
+    conf: ConfPluginParser,
+    filter_event_type: Vec<EventType>,
+    filter_event_subsys: Option<Vec<StorageSubSystem>>,
+    timeout: Duration,
+    process: Option<PluginProcess>,
+    last_start: Option<Instant>,
+    last_request_id: u64,
+    emitted: VecDeque<String>,
+}
+
+impl PluginParser {
+    pub fn new(conf: &ConfPluginParser) -> PluginParser {
+        PluginParser {
+            conf: conf.clone(),
+            filter_event_type: vec![EventType::Raw],
+            filter_event_subsys: None,
+            timeout: Duration::from_secs(
+                conf.timeout.unwrap_or(DEFAULT_PLUGIN_TIMEOUT),
+            ),
+            process: None,
+            last_start: None,
+            last_request_id: 0,
+            emitted: VecDeque::new(),
+        }
+    }
+
+    fn start(&mut self) -> Result<PluginProcess, String> {
+        self.last_start = Some(Instant::now());
+        let mut child = Command::new(&self.conf.exec)
+            .args(self.conf.args.clone().unwrap_or_default())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| {
+                format!("Failed to start {}: {}", self.conf.exec, e)
+            })?;
+        let (mut stdin, stdout) =
+            match (child.stdin.take(), child.stdout.take()) {
+                (Some(i), Some(o)) => (i, o),
+                _ => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err("Failed to open stdin and stdout".to_string());
+                }
+            };
+        let (stdin_sender, stdin_recver) = mpsc::channel::<String>();
+        let (line_sender, lines) = mpsc::channel();
+        let process = PluginProcess {
+            child,
+            stdin: stdin_sender,
+            lines,
+        };
+        Builder::new()
+            .name(format!("{}_plugin_writer", self.conf.name))
+            .spawn(move || {
+                for line in stdin_recver.iter() {
+                    if stdin.write_all(line.as_bytes()).is_err() {
+                        return;
+                    }
+                }
+            })
+            .map_err(|e| format!("Failed to start writer thread: {}", e))?;
+        Builder::new()
+            .name(format!("{}_plugin_reader", self.conf.name))
+            .spawn(move || {
+                for line in BufReader::new(stdout).lines() {
+                    match line {
+                        Ok(l) => {
+                            if line_sender.send(l).is_err() {
+                                return;
+                            }
+                        }
+                        Err(_) => return,
+                    }
+                }
+            })
+            .map_err(|e| format!("Failed to start reader thread: {}", e))?;
+        println!("{}_parser: Started {}", self.conf.name, self.conf.exec);
+        Ok(process)
+    }
+
+    fn parse_reply(
+        &mut self,
+        values: Vec<Value>,
+        event: &StorageEvent,
+    ) -> Vec<StorageEvent> {
+        let mut ret = Vec::new();
+        for (index, value) in values.into_iter().enumerate() {
+            let mut new_event =
+                match StorageEvent::from_json_string(&value.to_string()) {
+                    Ok(e) => e,
+                    Err(e) => {
+                        println!(
+                            "{}_parser: Invalid event in reply: {}",
+                            self.conf.name, e
+                        );
+                        continue;
+                    }
+                };
+            if let Err(e) = new_event.event_type_info() {
+                println!(
+                    "{}_parser: Dropped event in reply: {}",
+                    self.conf.name, e
+                );
+                continue;
+            }
+            if new_event.event_id.is_empty() {
+                new_event.event_id = StorageEvent::gen_event_id(&format!(
+                    "{}/{}/{}",
+                    event.event_id, self.conf.name, index
+                ));
+            }
+            if new_event.parent_event_id.is_empty() {
+                new_event.parent_event_id = event.event_id.clone();
+            }
+            if self.emitted.len() >= PLUGIN_EMITTED_CACHE_SIZE {
+                self.emitted.pop_front();
+            }
+            self.emitted.push_back(new_event.event_id.clone());
+            ret.push(new_event);
+        }
+        ret
+    }
+}
+
+impl Parser for PluginParser {
+    fn name(&self) -> &str {
+        &self.conf.name
+    }
+
+    fn filter_event_type(&self) -> Vec<EventType> {
+        self.filter_event_type.clone()
+    }
+
+    fn filter_event_subsys(&self) -> Option<Vec<StorageSubSystem>> {
+        self.filter_event_subsys.clone()
+    }
+
+    fn init(&mut self, _conf: Option<&toml::Value>) -> Result<(), String> {
+        if let Some(ref types) = self.conf.event_types {
+            let mut filter_event_type = Vec::new();
+            for t in types {
+                filter_event_type.push(match t.to_lowercase().as_str() {
+                    "raw" => EventType::Raw,
+                    "synthetic" => EventType::Synthetic,
+                    _ => return Err(format!("Invalid event type: {}", t)),
+                });
+            }
+            self.filter_event_type = filter_event_type;
+        }
+        if let Some(ref subs) = self.conf.sub_systems {
+            let mut filter_event_subsys = Vec::new();
+            for s in subs {
+                filter_event_subsys.push(
+                    s.parse::<StorageSubSystem>()
+                        .map_err(|e| format!("{}", e))?,
+                );
+            }
+            self.filter_event_subsys = Some(filter_event_subsys);
+        }
+        // Registered before hooks are created, so hooks could filter on
+        // them.
+        if let Some(ref types) = self.conf.new_event_types {
+            for t in types {
+                t.register()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn parse(&mut self, event: &StorageEvent) -> Vec<StorageEvent> {
+        if !event.event_id.is_empty() && self.emitted.contains(&event.event_id)
+        {
+            return Vec::new();
+        }
+        if self.process.is_none() {
+            let interval = Duration::from_secs(PLUGIN_RESTART_INTERVAL);
+            match self.last_start {
+                Some(t) if t.elapsed() < interval => {
+                    println!(
+                        "{}_parser: Dropped event {} as {} was started \
+                         less than {} seconds ago",
+                        self.conf.name,
+                        event.event_id,
+                        self.conf.exec,
+                        PLUGIN_RESTART_INTERVAL
+                    );
+                    return Vec::new();
+                }
+                _ => (),
+            }
+            match self.start() {
+                Ok(p) => self.process = Some(p),
+                Err(e) => {
+                    println!("{}_parser: {}", self.conf.name, e);
+                    return Vec::new();
+                }
+            }
+        }
+
+        self.last_request_id += 1;
+        let request = PluginRequest {
+            request_id: self.last_request_id,
+            event,
+        };
+        let mut line = match serde_json::to_string(&request) {
+            Ok(l) => l,
+            Err(e) => {
+                println!("{}_parser: {}", self.conf.name, e);
+                return Vec::new();
+            }
+        };
+        line.push('\n');
+        let reply = match self.process {
+            Some(ref p) => p.request(
+                &self.conf.name,
+                line,
+                self.last_request_id,
+                self.timeout,
+            ),
+            None => return Vec::new(),
+        };
+        match reply {
+            Ok(values) => self.parse_reply(values, event),
+            Err(reason) => {
+                println!(
+                    "{}_parser: {}, will restart {} on next event",
+                    self.conf.name, reason, self.conf.exec
+                );
+                // Dropping the process kills it.
+                self.process = None;
+                Vec::new()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use conf::ConfPluginEventType;
+    use peripety::EventTypeInfo;
+
+    fn plugin(script: &str, timeout: u64) -> PluginParser {
+        let mut parser = PluginParser::new(&ConfPluginParser {
+            name: "test".to_string(),
+            exec: "sh".to_string(),
+            args: Some(vec!["-c".to_string(), script.to_string()]),
+            event_types: Some(vec!["raw".to_string(), "synthetic".to_string()]),
+            sub_systems: None,
+            timeout: Some(timeout),
+            new_event_types: Some(vec![ConfPluginEventType {
+                name: "TEST_PLUGIN_VENDOR_EVENT".to_string(),
+                severity: "warning".to_string(),
+                kind: "failure".to_string(),
+                description: "Vendor event of test plugin".to_string(),
+                sub_systems: Some(vec!["scsi".to_string()]),
+            }]),
+        });
+        parser.init(None).unwrap();
+        parser
+    }
+
+    fn event(id: &str, msg: &str) -> StorageEvent {
+        StorageEvent {
+            event_id: id.to_string(),
+            msg: msg.to_string(),
+            ..Default::default()
+        }
+    }
+
+    // Reply the first word of event message as event type, after a stray
+    // line.
+    static ECHO_SCRIPT: &str = r#"
+        while read -r line; do
+            id=$(echo "$line" | sed -e 's/^{"request_id":\([0-9]*\),.*/\1/')
+            et=$(echo "$line" | sed -e 's/.*"msg":"\([A-Z_]*\).*/\1/')
+            echo "stray output"
+            echo "{\"request_id\":0,\"events\":[]}"
+            echo "{\"request_id\":$id,\"events\":[{\"event_type\":\"$et\"}]}"
+        done
+    "#;
+
+    #[test]
+    fn test_plugin_reply() {
+        let mut parser = plugin(ECHO_SCRIPT, 5);
+        assert!(EventTypeInfo::get("TEST_PLUGIN_VENDOR_EVENT").is_ok());
+
+        let events = parser.parse(&event("a", "TEST_PLUGIN_VENDOR_EVENT"));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event_type, "TEST_PLUGIN_VENDOR_EVENT");
+        assert_eq!(events[0].parent_event_id, "a");
+        assert_eq!(
+            events[0].event_id,
+            StorageEvent::gen_event_id("a/test/0")
+        );
+
+        // Own events are not sent back.
+        assert!(parser.parse(&events[0]).is_empty());
+
+        // Unknown event types are dropped.
+        assert!(parser.parse(&event("b", "NO_SUCH_EVENT_TYPE")).is_empty());
+
+        let events = parser.parse(&event("c", "SCSI_CDB"));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].parent_event_id, "c");
+    }
+
+    #[test]
+    fn test_plugin_timeout() {
+        // Never read stdin, so writing large event blocks.
+        let mut parser = plugin("exec sleep 30", 1);
+        let start = Instant::now();
+        let msg = "a".repeat(1 << 20);
+        assert!(parser.parse(&event("a", &msg)).is_empty());
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert!(parser.process.is_none());
+
+        // Dropped till restart interval passed.
+        assert!(parser.parse(&event("b", "SCSI_CDB")).is_empty());
+        assert!(parser.process.is_none());
+    }
+}
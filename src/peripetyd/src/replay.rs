@@ -0,0 +1,281 @@
+// Run saved logs through collector and parsers instead of live system, for
+// checking what peripetyd would have reported on customer log bundles.
+//
+// Supported formats:
+//  * kmsg dump, like `cat /dev/kmsg`.
+//  * `journalctl -o export`.
+//  * `journalctl -o json`.
+
+use collector;
+use conf;
+use kmsg;
+use peripety::{StorageEvent, SystemRoot};
+use serde_json::{self, Value};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::sync::mpsc;
+
+use super::{handle_events_from_parsers, send_to_parsers};
+use data::EventType;
+use parser::ParserRegistry;
+
+// Binary field of journal export format: name, then 64 bits little endian
+// size, then data.
+fn read_export_binary(
+    data: &[u8],
+    pos: &mut usize,
+) -> Result<String, String> {
+    if *pos + 8 > data.len() {
+        return Err("Truncated binary field in journal export".to_string());
+    }
+    let mut size_bytes = [0u8; 8];
+    size_bytes.copy_from_slice(&data[*pos..*pos + 8]);
+    let size = u64::from_le_bytes(size_bytes) as usize;
+    *pos += 8;
+    if *pos + size > data.len() {
+        return Err("Truncated binary field in journal export".to_string());
+    }
+    let value = String::from_utf8_lossy(&data[*pos..*pos + size]).to_string();
+    // Skip the trailing new line.
+    *pos += size + 1;
+    Ok(value)
+}
+
+fn parse_export(data: &[u8]) -> Result<Vec<HashMap<String, String>>, String> {
+    let mut entries = Vec::new();
+    let mut entry = HashMap::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let end = match data[pos..].iter().position(|b| *b == b'\n') {
+            Some(i) => pos + i,
+            None => data.len(),
+        };
+        let line = String::from_utf8_lossy(&data[pos..end]).to_string();
+        pos = end + 1;
+        if line.is_empty() {
+            if !entry.is_empty() {
+                entries.push(entry);
+                entry = HashMap::new();
+            }
+            continue;
+        }
+        match line.find('=') {
+            Some(i) => {
+                entry.insert(line[..i].to_string(), line[i + 1..].to_string());
+            }
+            None => {
+                let value = read_export_binary(data, &mut pos)?;
+                entry.insert(line, value);
+            }
+        }
+    }
+    if !entry.is_empty() {
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+// Field holding multiple values is an array, binary data is array of bytes.
+fn json_value_to_string(value: &Value) -> Option<String> {
+    match *value {
+        Value::String(ref s) => Some(s.clone()),
+        Value::Number(ref n) => Some(format!("{}", n)),
+        Value::Array(ref a) => {
+            if a.iter().all(|v| v.is_u64()) {
+                let bytes: Vec<u8> = a
+                    .iter()
+                    .filter_map(|v| v.as_u64())
+                    .map(|b| b as u8)
+                    .collect();
+                Some(String::from_utf8_lossy(&bytes).to_string())
+            } else {
+                a.iter().filter_map(json_value_to_string).next()
+            }
+        }
+        _ => None,
+    }
+}
+
+fn parse_json(data: &str) -> Result<Vec<HashMap<String, String>>, String> {
+    let mut entries = Vec::new();
+    for (i, line) in data.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fields: HashMap<String, Value> = serde_json::from_str(line)
+            .map_err(|e| format!("Invalid JSON at line {}: {}", i + 1, e))?;
+        let mut entry = HashMap::new();
+        for (key, value) in &fields {
+            if let Some(v) = json_value_to_string(value) {
+                entry.insert(key.clone(), v);
+            }
+        }
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+// The boot time is unknown, hence timestamps are relative to 1970-01-01.
+fn parse_kmsg(data: &str) -> Vec<HashMap<String, String>> {
+    let mut entries = Vec::new();
+    let mut record = String::new();
+    for line in data.lines() {
+        // Continuation lines of dictionary start with space.
+        if !line.starts_with(' ') && !record.is_empty() {
+            entries.extend(kmsg::parse_record(&record, 0));
+            record.clear();
+        }
+        record.push_str(line);
+        record.push('\n');
+    }
+    if !record.is_empty() {
+        entries.extend(kmsg::parse_record(&record, 0));
+    }
+    entries
+}
+
+fn is_kmsg(line: &str) -> bool {
+    match line.find(';') {
+        Some(i) => {
+            let fields: Vec<&str> = line[..i].split(',').collect();
+            fields.len() >= 3
+                && fields[..3].iter().all(|f| f.parse::<u64>().is_ok())
+        }
+        None => false,
+    }
+}
+
+fn load_entries(path: &str) -> Result<Vec<HashMap<String, String>>, String> {
+    let mut data = Vec::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_end(&mut data))
+        .map_err(|e| format!("Failed to read file {}: {}", path, e))?;
+    let text = String::from_utf8_lossy(&data);
+    let first_line = text.lines().find(|l| !l.trim().is_empty()).unwrap_or("");
+    if first_line.trim_start().starts_with('{') {
+        parse_json(&text)
+    } else if is_kmsg(first_line) {
+        Ok(parse_kmsg(&text))
+    } else {
+        parse_export(&data)
+    }
+}
+
+// Run log entries of file through collector and parsers. Return the events
+// daemon would have generated. The `sys_root` could point to sysfs, devfs and
+// procfs captured from the system where logs are generated.
+pub fn replay(
+    path: &str,
+    sys_root: &SystemRoot,
+) -> Result<Vec<StorageEvent>, String> {
+    let entries = load_entries(path)?;
+
+    let mut daemon_conf = None;
+    let mut collector_conf = None;
+    let mut parsers_conf = None;
+    let mut plugin_confs = Vec::new();
+    if let Some(c) = conf::load_conf() {
+        daemon_conf = Some(c.main);
+        collector_conf = Some(c.collector);
+        parsers_conf = c.parsers;
+        plugin_confs = c.plugin_parsers.unwrap_or_default();
+    }
+
+    let (collector_send, collector_recv) = mpsc::channel();
+    let (notifier_send, notifier_recv) = mpsc::channel();
+    let mut registry = ParserRegistry::buildin();
+    registry.register_plugins(&plugin_confs);
+    let parsers = registry.start(
+        &notifier_send,
+        sys_root,
+        parsers_conf.as_ref(),
+    );
+
+    collector::replay(&entries, &collector_send, collector_conf);
+    for event in collector_recv.try_iter() {
+        send_to_parsers(&event, &parsers, &EventType::Raw);
+    }
+
+    let mut events = Vec::new();
+    handle_events_from_parsers(
+        &notifier_recv,
+        &parsers,
+        daemon_conf,
+        &mut |event| events.push(event.clone()),
+        true,
+    );
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use peripety::fixture::Fixture;
+
+    #[test]
+    fn test_parse_export() {
+        let mut data = b"_HOSTNAME=a\nMESSAGE\n".to_vec();
+        data.extend_from_slice(&7u64.to_le_bytes());
+        data.extend_from_slice(b"a\nb=c d\n");
+        data.extend_from_slice(b"PRIORITY=3\n\nMESSAGE=next\n");
+        let entries = parse_export(&data).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["_HOSTNAME"], "a");
+        assert_eq!(entries[0]["MESSAGE"], "a\nb=c d");
+        assert_eq!(entries[0]["PRIORITY"], "3");
+        assert_eq!(entries[1]["MESSAGE"], "next");
+
+        let mut data = b"MESSAGE\n".to_vec();
+        data.extend_from_slice(&100u64.to_le_bytes());
+        data.extend_from_slice(b"short\n");
+        assert!(parse_export(&data).is_err());
+    }
+
+    #[test]
+    fn test_parse_json() {
+        let data = "\
+{\"MESSAGE\":[97,10,98],\"PRIORITY\":\"3\",\"_PID\":[\"1\",\"2\"]}
+
+{\"MESSAGE\":\"next\",\"_BOOT\":null}
+";
+        let entries = parse_json(data).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["MESSAGE"], "a\nb");
+        assert_eq!(entries[0]["PRIORITY"], "3");
+        assert_eq!(entries[0]["_PID"], "1");
+        assert_eq!(entries[1]["MESSAGE"], "next");
+        assert!(!entries[1].contains_key("_BOOT"));
+        assert!(parse_json("{\"MESSAGE\":").is_err());
+    }
+
+    #[test]
+    fn test_is_kmsg() {
+        assert!(is_kmsg("3,1024,5000000,-;sd 0:0:0:0: [sda] error"));
+        assert!(is_kmsg("6,1,0,c;msg"));
+        assert!(!is_kmsg("__CURSOR=s=abc;i=1"));
+        assert!(!is_kmsg("3,x,5000000,-;msg"));
+        assert!(!is_kmsg("3,1024;msg"));
+        assert!(!is_kmsg("{\"MESSAGE\":\"a;b\"}"));
+    }
+
+    #[test]
+    fn test_load_entries() {
+        let f = Fixture::new("replay");
+        f.write("kmsg.txt", "3,1,5000000,-;a\n SUBSYSTEM=scsi\n3,2,6,-;b\n");
+        f.write("json.txt", "\n{\"MESSAGE\":\"a\"}\n");
+        f.write("export.txt", "MESSAGE=a\n\nMESSAGE=b\n");
+
+        let entries = load_entries(&f.path("kmsg.txt")).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["MESSAGE"], "a");
+        assert_eq!(entries[0]["_KERNEL_SUBSYSTEM"], "scsi");
+        let entries = load_entries(&f.path("json.txt")).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0]["MESSAGE"], "a");
+        let entries = load_entries(&f.path("export.txt")).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1]["MESSAGE"], "b");
+        assert!(load_entries(&f.path("none.txt")).is_err());
+    }
+}
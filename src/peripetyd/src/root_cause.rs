@@ -0,0 +1,380 @@
+// Link the collateral damage events(like file system I/O error) to the event
+// of lower layer causing them(like SCSI disk I/O error).
+//
+// Failure event A is considered as root cause of failure event B if they
+// happened within the time window and B is on the same device of A or on
+// top of the device of A(`dev_wwid` of A is in `owners_wwids` of B).
+// Once the time window of root cause expired, a summary event is generated
+// listing all its collateral damage events.
+
+use chrono::{DateTime, Duration, FixedOffset, Local, SecondsFormat};
+use peripety::{EventTypeKind, StorageEvent};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static SUMMARY_EVENT_TYPE: &str = "ROOT_CAUSE_SUMMARY";
+static EVENT_ID_SEQ: AtomicUsize = AtomicUsize::new(0);
+
+struct TrackedEvent {
+    event_id: String,
+    root_cause_event_id: String,
+    dev_wwid: String,
+    owners_wwids: Vec<String>,
+    time: DateTime<FixedOffset>,
+}
+
+struct Collateral {
+    event_id: String,
+    event_type: String,
+    dev_path: String,
+}
+
+struct RootCauseGroup {
+    root: StorageEvent,
+    collaterals: Vec<Collateral>,
+    last_time: DateTime<FixedOffset>,
+}
+
+pub struct RootCause {
+    window: Duration,
+    events: Vec<TrackedEvent>,
+    groups: Vec<RootCauseGroup>,
+}
+
+fn now() -> DateTime<FixedOffset> {
+    let now = Local::now();
+    now.with_timezone(now.offset())
+}
+
+fn event_time(event: &StorageEvent) -> DateTime<FixedOffset> {
+    DateTime::parse_from_rfc3339(&event.timestamp).unwrap_or_else(|_| now())
+}
+
+// Event without ID cannot be referred by `root_cause_event_id`. Events from
+// journal always have ID, this is for other sources.
+fn ensure_event_id(event: &mut StorageEvent) {
+    if event.event_id.is_empty() {
+        let now = now();
+        let seq = EVENT_ID_SEQ.fetch_add(1, Ordering::SeqCst);
+        event.event_id = format!(
+            "{:x}.{:05x}-{:x}",
+            now.timestamp(),
+            now.timestamp_subsec_micros(),
+            seq
+        );
+    }
+}
+
+fn is_failure(event: &StorageEvent) -> bool {
+    match event.event_type_info() {
+        Ok(t) => t.kind == EventTypeKind::Failure,
+        Err(_) => false,
+    }
+}
+
+impl RootCauseGroup {
+    fn to_summary(&self) -> StorageEvent {
+        let root = &self.root;
+        let mut dev_paths: Vec<&str> = Vec::new();
+        let mut event_types: Vec<&str> = Vec::new();
+        for c in &self.collaterals {
+            if !dev_paths.contains(&c.dev_path.as_str()) {
+                dev_paths.push(&c.dev_path);
+            }
+            if !event_types.contains(&c.event_type.as_str()) {
+                event_types.push(&c.event_type);
+            }
+        }
+        let event_ids: Vec<&str> =
+            self.collaterals.iter().map(|c| c.event_id.as_str()).collect();
+
+        let mut extension = HashMap::new();
+        extension
+            .insert("root_event_type".to_string(), root.event_type.clone());
+        extension.insert(
+            "collateral_count".to_string(),
+            format!("{}", self.collaterals.len()),
+        );
+        extension
+            .insert("collateral_event_ids".to_string(), event_ids.join(","));
+        extension.insert(
+            "collateral_event_types".to_string(),
+            event_types.join(","),
+        );
+        extension
+            .insert("collateral_dev_paths".to_string(), dev_paths.join(","));
+
+        StorageEvent {
+            hostname: root.hostname.clone(),
+            severity: root.severity,
+            sub_system: root.sub_system,
+            timestamp: self.last_time
+                .to_rfc3339_opts(SecondsFormat::Micros, false),
+            event_id: StorageEvent::gen_event_id(&format!(
+                "{}/{}",
+                root.event_id, SUMMARY_EVENT_TYPE
+            )),
+            root_cause_event_id: root.event_id.clone(),
+            parent_event_id: root.event_id.clone(),
+            event_type: SUMMARY_EVENT_TYPE.to_string(),
+            dev_wwid: root.dev_wwid.clone(),
+            dev_path: root.dev_path.clone(),
+            owners_wwids: root.owners_wwids.clone(),
+            owners_paths: root.owners_paths.clone(),
+            msg: format!(
+                "{} on {} caused {} event(s) on {}",
+                root.event_type,
+                root.dev_path,
+                self.collaterals.len(),
+                dev_paths.join(", ")
+            ),
+            extension,
+            ..Default::default()
+        }
+    }
+}
+
+impl RootCause {
+    pub fn new(window_secs: u64) -> RootCause {
+        RootCause {
+            window: Duration::seconds(window_secs as i64),
+            events: Vec::new(),
+            groups: Vec::new(),
+        }
+    }
+
+    // Set `root_cause_event_id` of provided failure event, other events get
+    // it cleared. Return summary events of expired root causes.
+    pub fn process(&mut self, event: &mut StorageEvent) -> Vec<StorageEvent> {
+        ensure_event_id(event);
+        let time = event_time(event);
+        let summaries = self.expire(time);
+        if !is_failure(event) {
+            event.root_cause_event_id = String::new();
+            return summaries;
+        }
+        event.root_cause_event_id = event.event_id.clone();
+        if event.dev_wwid.is_empty() {
+            return summaries;
+        }
+
+        // Newest first, so repeated failures are linked to the latest
+        // root cause still in the time window.
+        let root_id = self.events
+            .iter()
+            .rev()
+            .find(|t| {
+                t.dev_wwid == event.dev_wwid
+                    || event.owners_wwids.contains(&t.dev_wwid)
+            })
+            .map(|t| t.root_cause_event_id.clone());
+
+        match root_id {
+            Some(root_id) => {
+                if let Some(g) = self.groups
+                    .iter_mut()
+                    .find(|g| g.root.event_id == root_id)
+                {
+                    g.collaterals.push(Collateral {
+                        event_id: event.event_id.clone(),
+                        event_type: event.event_type.clone(),
+                        dev_path: event.dev_path.clone(),
+                    });
+                    g.last_time = time;
+                }
+                event.root_cause_event_id = root_id;
+            }
+            None => self.new_group(event, time),
+        }
+
+        self.events.push(TrackedEvent {
+            event_id: event.event_id.clone(),
+            root_cause_event_id: event.root_cause_event_id.clone(),
+            dev_wwid: event.dev_wwid.clone(),
+            owners_wwids: event.owners_wwids.clone(),
+            time,
+        });
+        summaries
+    }
+
+    // Logs of upper layer might be processed before the lower layer one,
+    // in that case, move the groups of upper layer into the new one.
+    // The upper layer events have been sent out with themselves as root
+    // cause, only the summary event could correct that.
+    fn new_group(&mut self, event: &StorageEvent, time: DateTime<FixedOffset>) {
+        let mut group = RootCauseGroup {
+            root: event.clone(),
+            collaterals: Vec::new(),
+            last_time: time,
+        };
+        let upper_root_ids: Vec<String> = self.events
+            .iter()
+            .filter(|t| {
+                t.event_id == t.root_cause_event_id
+                    && t.owners_wwids.contains(&event.dev_wwid)
+            })
+            .map(|t| t.event_id.clone())
+            .collect();
+        for root_id in &upper_root_ids {
+            let i = match self.groups
+                .iter()
+                .position(|g| &g.root.event_id == root_id)
+            {
+                Some(i) => i,
+                None => continue,
+            };
+            let upper = self.groups.remove(i);
+            group.collaterals.push(Collateral {
+                event_id: upper.root.event_id.clone(),
+                event_type: upper.root.event_type.clone(),
+                dev_path: upper.root.dev_path.clone(),
+            });
+            group.collaterals.extend(upper.collaterals);
+        }
+        for t in &mut self.events {
+            if upper_root_ids.contains(&t.root_cause_event_id) {
+                t.root_cause_event_id = event.event_id.clone();
+            }
+        }
+        self.groups.push(group);
+    }
+
+    // Forget events out of time window and generate summary events for
+    // root causes with collateral damage.
+    pub fn expire(
+        &mut self,
+        time: DateTime<FixedOffset>,
+    ) -> Vec<StorageEvent> {
+        let window = self.window;
+        self.events.retain(|t| t.time + window >= time);
+        let mut summaries = Vec::new();
+        let mut i = 0;
+        while i < self.groups.len() {
+            if self.groups[i].last_time + window >= time {
+                i += 1;
+                continue;
+            }
+            let group = self.groups.remove(i);
+            if !group.collaterals.is_empty() {
+                summaries.push(group.to_summary());
+            }
+        }
+        summaries
+    }
+
+    // Expire with current time, used when no event is received for a while.
+    pub fn expire_now(&mut self) -> Vec<StorageEvent> {
+        self.expire(now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static SCSI_WWID: &str = "naa.600a098038304437415d4b6a59684a52";
+    static MPATH_WWID: &str = "mpath-3600a098038304437415d4b6a59684a52";
+    static LV_WWID: &str = "LVM-Ft9Qd5E3RnHXmhHfDf4ZMFgLI0GfEbxl";
+    static FS_UUID: &str = "47841ee5-047d-4f73-8951-5a3d7a4c770e";
+
+    fn event(
+        event_id: &str,
+        event_type: &str,
+        secs: u32,
+        dev_wwid: &str,
+        owners_wwids: &[&str],
+    ) -> StorageEvent {
+        StorageEvent {
+            event_id: event_id.to_string(),
+            event_type: event_type.to_string(),
+            timestamp: format!("2018-03-02T14:13:{:02}.000000+00:00", secs),
+            dev_wwid: dev_wwid.to_string(),
+            owners_wwids: owners_wwids.iter().map(|w| w.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    // The SCSI, mpath, LV and fs events use the wwid forms of BlkInfo.
+    fn chain() -> Vec<StorageEvent> {
+        vec![
+            event("scsi", "SCSI_DRIVE_IO_ERROR", 0, SCSI_WWID, &[]),
+            event(
+                "mpath",
+                "DM_MPATH_PATH_FAILED",
+                1,
+                MPATH_WWID,
+                &[SCSI_WWID],
+            ),
+            event("lv", "BLK_IO_ERROR", 2, LV_WWID, &[MPATH_WWID, SCSI_WWID]),
+            event(
+                "fs",
+                "DM_FS_IO_ERROR",
+                3,
+                FS_UUID,
+                &[LV_WWID, MPATH_WWID, SCSI_WWID],
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_chain() {
+        let mut root_cause = RootCause::new(10);
+        for mut e in chain() {
+            assert!(root_cause.process(&mut e).is_empty());
+            assert_eq!(e.root_cause_event_id, "scsi");
+        }
+
+        let time = DateTime::parse_from_rfc3339("2018-03-02T14:14:00+00:00")
+            .unwrap();
+        let summaries = root_cause.expire(time);
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].root_cause_event_id, "scsi");
+        assert_eq!(
+            summaries[0].extension.get("collateral_event_ids"),
+            Some(&"mpath,lv,fs".to_string())
+        );
+    }
+
+    #[test]
+    fn test_chain_upper_layer_first() {
+        let mut root_cause = RootCause::new(10);
+        let mut events = chain();
+        events.reverse();
+        for mut e in events {
+            assert!(root_cause.process(&mut e).is_empty());
+            assert_eq!(e.root_cause_event_id, e.event_id);
+        }
+        let time = DateTime::parse_from_rfc3339("2018-03-02T14:14:00+00:00")
+            .unwrap();
+        let summaries = root_cause.expire(time);
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].root_cause_event_id, "scsi");
+        assert_eq!(
+            summaries[0].extension.get("collateral_count"),
+            Some(&"3".to_string())
+        );
+    }
+
+    #[test]
+    fn test_non_failure() {
+        let mut root_cause = RootCause::new(10);
+        let mut e = event("scsi", "SCSI_DRIVE_IO_ERROR", 0, SCSI_WWID, &[]);
+        root_cause.process(&mut e);
+        let mut e = event("mounted", "DM_FS_MOUNTED", 1, FS_UUID, &[SCSI_WWID]);
+        e.root_cause_event_id = "scsi".to_string();
+        root_cause.process(&mut e);
+        assert_eq!(e.root_cause_event_id, "");
+    }
+
+    #[test]
+    fn test_window_expired() {
+        let mut root_cause = RootCause::new(10);
+        let mut events = chain();
+        events[3].timestamp = "2018-03-02T14:13:30.000000+00:00".to_string();
+        for e in &mut events {
+            root_cause.process(e);
+        }
+        assert_eq!(events[2].root_cause_event_id, "scsi");
+        assert_eq!(events[3].root_cause_event_id, "fs");
+    }
+}
@@ -1,10 +1,168 @@
-use data::{EventType, ParserInfo};
-use peripety::{BlkInfo, StorageEvent, StorageSubSystem};
-use std::sync::mpsc;
-use std::sync::mpsc::Sender;
-use std::thread::spawn;
+use data::EventType;
+use parser::Parser;
+use peripety::scsi_sense::{self, Cdb};
+use peripety::{BlkInfo, EventTypeInfo, StorageEvent, StorageSubSystem,
+               SystemRoot};
+use std::time::{Duration, Instant};
 
-fn parse_event(event: &StorageEvent, sender: &Sender<StorageEvent>) {
+// Kernel logs all lines of a failed command at once, wait this long for
+// the missing lines before sending out what we got.
+const CMD_LOG_TIMEOUT: u64 = 1;
+
+// Logs of single failed SCSI command sharing the same `tag#`.
+struct ScsiCmd {
+    event: StorageEvent,
+    tag: String,
+    raw_msgs: Vec<String>,
+    time: Instant,
+}
+
+// Convert sense key, additional sense code and CDB printed by kernel into
+// codes, LBA and number of blocks.
+fn decode_sense(event: &mut StorageEvent) {
+    let mut ext = Vec::new();
+    match event.event_type.as_ref() {
+        "SCSI_SENSE_KEY" => {
+            let sense_key = event
+                .extension
+                .get("sense_key_msg")
+                .and_then(|m| scsi_sense::sense_key_from_name(m));
+            if let Some(sense_key) = sense_key {
+                ext.push(("sense_key", format!("0x{:02x}", sense_key)));
+                match sense_key {
+                    // Find a way to use follow up CBD event to extract
+                    // sector number of medium error.
+                    0x03 => {
+                        event.event_type = "SCSI_MEDIUM_ERROR".to_string()
+                    }
+                    0x04 => {
+                        event.event_type = "SCSI_HARDWARE_ERROR".to_string()
+                    }
+                    _ => {}
+                }
+            }
+        }
+        "SCSI_ADDITIONAL_SENSE_CODE" => {
+            if let Some((asc, ascq)) = event
+                .extension
+                .get("sense_msg")
+                .and_then(|m| scsi_sense::asc_ascq_from_name(m))
+            {
+                ext.push(("asc", format!("0x{:02x}", asc)));
+                ext.push(("ascq", format!("0x{:02x}", ascq)));
+            }
+        }
+        "SCSI_CDB" => {
+            let cdb = match event.extension.get("cdb") {
+                Some(c) => Cdb::from_hex_str(c),
+                None => return,
+            };
+            match cdb {
+                Ok(cdb) => {
+                    ext.push(("cdb_opcode", cdb.name));
+                    if let Some(lba) = cdb.lba {
+                        ext.push(("block_range_start", lba.to_string()));
+                        ext.push(("block_range_unit", "lba".to_string()));
+                    }
+                    if let Some(len) = cdb.transfer_length {
+                        ext.push(("block_range_size", len.to_string()));
+                    }
+                }
+                Err(e) => println!("scsi_parser: {}", e),
+            }
+        }
+        _ => {}
+    }
+    for (key, value) in ext {
+        event.extension.insert(key.to_string(), value);
+    }
+}
+
+impl ScsiCmd {
+    fn new(event: StorageEvent, tag: &str) -> ScsiCmd {
+        ScsiCmd {
+            raw_msgs: vec![event.raw_msg.clone()],
+            event,
+            tag: tag.to_string(),
+            time: Instant::now(),
+        }
+    }
+
+    fn merge(&mut self, event: StorageEvent) {
+        self.raw_msgs.push(event.raw_msg);
+        for (key, value) in event.extension {
+            self.event.extension.insert(key, value);
+        }
+    }
+
+    // Generate SCSI_DRIVE_IO_ERROR event like:
+    //  SCSI disk /dev/sdf(wwid) I/O failure on Write(10) block 9984+64:
+    //  NOT READY, Logical unit communication failure
+    fn into_event(self) -> StorageEvent {
+        let mut event = self.event;
+        event.event_type = "SCSI_DRIVE_IO_ERROR".to_string();
+        event.severity = EventTypeInfo::get(&event.event_type)
+            .expect("BUG: SCSI_DRIVE_IO_ERROR is not registered")
+            .severity;
+        event.raw_msg = self.raw_msgs.join("\n");
+
+        let ext = &event.extension;
+        let mut msg = format!(
+            "SCSI disk {}({}) I/O failure",
+            event.dev_path, event.dev_wwid
+        );
+        if let Some(op) = ext.get("cdb_opcode") {
+            msg = format!("{} on {}", msg, op);
+        }
+        if let Some(start) = ext.get("block_range_start") {
+            msg = format!("{} block {}", msg, start);
+            if let Some(size) = ext.get("block_range_size") {
+                msg = format!("{}+{}", msg, size);
+            }
+        }
+        let mut reasons: Vec<&str> = Vec::new();
+        for key in &["sense_key_msg", "sense_msg"] {
+            if let Some(r) = ext.get(*key) {
+                reasons.push(r);
+            }
+        }
+        if reasons.is_empty() {
+            for key in &["hostbyte", "driverbyte"] {
+                if let Some(r) = ext.get(*key) {
+                    reasons.push(r);
+                }
+            }
+        }
+        if !reasons.is_empty() {
+            msg = format!("{}: {}", msg, reasons.join(", "));
+        }
+        event.msg = msg;
+        event
+    }
+}
+
+// Send out commands waited too long for remaining logs.
+fn flush_cmds(
+    cmds: &mut Vec<ScsiCmd>,
+    events: &mut Vec<StorageEvent>,
+) {
+    let timeout = Duration::from_secs(CMD_LOG_TIMEOUT);
+    let mut i = 0;
+    while i < cmds.len() {
+        if cmds[i].time.elapsed() < timeout {
+            i += 1;
+            continue;
+        }
+        events.push(cmds.remove(i).into_event());
+    }
+}
+
+fn parse_event(
+    event: &StorageEvent,
+    events: &mut Vec<StorageEvent>,
+    sys_root: &SystemRoot,
+    cmds: &mut Vec<ScsiCmd>,
+) {
     let mut kdev: &str = &event.kdev;
     if event.kdev.starts_with("+scsi:host") {
         return;
@@ -12,53 +170,104 @@ fn parse_event(event: &StorageEvent, sender: &Sender<StorageEvent>) {
     if event.kdev.starts_with("+scsi:") {
         kdev = &event.kdev["+scsi:".len()..];
     }
-    match BlkInfo::new_skip_extra(kdev) {
+    let mut event = match BlkInfo::new_skip_extra(kdev, sys_root) {
         Ok(b) => {
-            let mut event = event.clone();
+            let mut event = event.to_synthetic("scsi");
             event.dev_path = b.blk_path;
             event.dev_wwid = b.wwid;
-            if event.event_type == "SCSI_SENSE_KEY" {
-                if let Some(sense_key) = event.extension.get("sense_key") {
-                    match sense_key.as_ref() {
-                        // Find a way to use follow up CBD event to extract
-                        // sector number of medium error.
-                        "Medium Error" => {
-                            event.event_type = "SCSI_MEDIUM_ERROR".to_string()
-                        }
-                        "Hardware Error" => {
-                            event.event_type = "SCSI_HARDWARE_ERROR".to_string()
-                        }
-                        _ => {}
-                    }
-                }
-            }
+            decode_sense(&mut event);
+            event
+        }
+        Err(e) => {
+            println!("scsi_parser: {}", e);
+            return;
+        }
+    };
+
+    // Old kernel does not log tag, so we cannot tell which lines belong to
+    // the same command.
+    let tag = match event.extension.get("tag") {
+        Some(t) => t.clone(),
+        None => {
             event.msg =
                 format!("{}, wwid: '{}'", event.raw_msg, event.dev_wwid);
-            if let Err(e) = sender.send(event) {
-                println!("scsi_parser: Failed to send event: {}", e);
+            events.push(event);
+            return;
+        }
+    };
+
+    let pos = cmds
+        .iter()
+        .position(|c| c.tag == tag && c.event.dev_wwid == event.dev_wwid);
+    // 'FAILED Result' is the first line of failed command, the previous one
+    // with the same tag is done.
+    let pos = match pos {
+        Some(i) if event.event_type == "SCSI_FAILED_RESULT" => {
+            events.push(cmds.remove(i).into_event());
+            None
+        }
+        _ => pos,
+    };
+    // CDB is the last line.
+    let is_last = event.event_type == "SCSI_CDB";
+    match pos {
+        Some(i) => {
+            cmds[i].merge(event);
+            if is_last {
+                events.push(cmds.remove(i).into_event());
+            }
+        }
+        None => {
+            let cmd = ScsiCmd::new(event, &tag);
+            if is_last {
+                events.push(cmd.into_event());
+            } else {
+                cmds.push(cmd);
             }
         }
-        Err(e) => println!("scsi_parser: {}", e),
     }
 }
 
-pub fn parser_start(sender: Sender<StorageEvent>) -> ParserInfo {
-    let (event_in_sender, event_in_recver) = mpsc::channel();
-    let name = "scsi".to_string();
-    let filter_event_type = vec![EventType::Raw];
-    let filter_event_subsys = vec![StorageSubSystem::Scsi];
+pub struct ScsiParser {
+    sys_root: SystemRoot,
+    cmds: Vec<ScsiCmd>,
+}
 
-    spawn(move || loop {
-        match event_in_recver.recv() {
-            Ok(event) => parse_event(&event, &sender),
-            Err(e) => println!("scsi_parser: Failed to receive event: {}", e),
+impl ScsiParser {
+    pub fn new(sys_root: &SystemRoot) -> ScsiParser {
+        ScsiParser {
+            sys_root: sys_root.clone(),
+            cmds: Vec::new(),
         }
-    });
+    }
+}
+
+impl Parser for ScsiParser {
+    fn name(&self) -> &str {
+        "scsi"
+    }
+
+    fn filter_event_type(&self) -> Vec<EventType> {
+        vec![EventType::Raw]
+    }
+
+    fn filter_event_subsys(&self) -> Option<Vec<StorageSubSystem>> {
+        Some(vec![StorageSubSystem::Scsi])
+    }
+
+    fn parse(&mut self, event: &StorageEvent) -> Vec<StorageEvent> {
+        let mut events = Vec::new();
+        parse_event(event, &mut events, &self.sys_root, &mut self.cmds);
+        events
+    }
+
+    fn tick_interval(&self) -> Option<Duration> {
+        Some(Duration::from_secs(CMD_LOG_TIMEOUT))
+    }
 
-    ParserInfo {
-        sender: event_in_sender,
-        name: name,
-        filter_event_type: filter_event_type,
-        filter_event_subsys: Some(filter_event_subsys),
+    fn tick(&mut self) -> Vec<StorageEvent> {
+        let mut events = Vec::new();
+        flush_cmds(&mut self.cmds, &mut events);
+        events
     }
 }
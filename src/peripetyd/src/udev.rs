@@ -0,0 +1,366 @@
+// Collector of block device add, remove and change events from the udev
+// netlink monitor.
+//
+// Block information is captured when device is added(or when daemon started),
+// hence the removal event still holds WWID and path of the gone device.
+
+use chrono::{Local, SecondsFormat};
+use data::{get_boot_id, get_hostname};
+use nix;
+use nix::errno::Errno;
+use nix::libc;
+use nix::sys::socket::{bind, SockAddr};
+use nix::unistd::close;
+use peripety::{BlkInfo, LogSeverity, StorageEvent, StorageSubSystem,
+               SystemRoot};
+use std::collections::HashMap;
+use std::fs;
+use std::mem;
+use std::os::unix::io::RawFd;
+use std::ptr;
+use std::sync::mpsc::Sender;
+
+// Multicast group of events sent by udevd once rules are applied, the
+// group 1 is for raw kernel uevents.
+static UDEV_MONITOR_GROUP: u32 = 2;
+static UDEV_MONITOR_MAGIC: u32 = 0xfeed_cafe;
+// The "libudev\0" prefix, magic, header_size, properties_off and
+// properties_len.
+static UDEV_MONITOR_HEADER_SIZE: usize = 24;
+static RECV_BUFFER_SIZE: usize = 8192;
+
+fn u32_at(buff: &[u8], offset: usize, big_endian: bool) -> u32 {
+    let mut bytes = [0u8; 4];
+    bytes.copy_from_slice(&buff[offset..offset + 4]);
+    match big_endian {
+        true => u32::from_be_bytes(bytes),
+        false => u32::from_ne_bytes(bytes),
+    }
+}
+
+// Return the 'KEY=VALUE' properties of udev monitor message.
+fn parse_udev_msg(buff: &[u8]) -> Option<HashMap<String, String>> {
+    if buff.len() < UDEV_MONITOR_HEADER_SIZE
+        || !buff.starts_with(b"libudev\0")
+        || u32_at(buff, 8, true) != UDEV_MONITOR_MAGIC
+    {
+        return None;
+    }
+    let off = u32_at(buff, 16, false) as usize;
+    let len = u32_at(buff, 20, false) as usize;
+    if off < UDEV_MONITOR_HEADER_SIZE || off + len > buff.len() {
+        return None;
+    }
+    let mut ret = HashMap::new();
+    for prop in buff[off..off + len].split(|b| *b == 0) {
+        let prop = String::from_utf8_lossy(prop);
+        if let Some(i) = prop.find('=') {
+            ret.insert(prop[..i].to_string(), prop[i + 1..].to_string());
+        }
+    }
+    Some(ret)
+}
+
+fn open_udev_monitor() -> Result<RawFd, String> {
+    let fd = unsafe {
+        libc::socket(
+            libc::AF_NETLINK,
+            libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+            libc::NETLINK_KOBJECT_UEVENT,
+        )
+    };
+    let fd = Errno::result(fd)
+        .map_err(|e| format!("Failed to create netlink socket: {}", e))?;
+    if let Err(e) = bind(fd, &SockAddr::new_netlink(0, UDEV_MONITOR_GROUP)) {
+        let _ = close(fd);
+        return Err(format!("Failed to bind netlink socket: {}", e));
+    }
+    if let Err(e) = enable_pass_cred(fd) {
+        let _ = close(fd);
+        return Err(format!("Failed to set SO_PASSCRED: {}", e));
+    }
+    Ok(fd)
+}
+
+// Ask for credentials of sender on each message.
+fn enable_pass_cred(fd: RawFd) -> nix::Result<()> {
+    let on: libc::c_int = 1;
+    let rc = unsafe {
+        libc::setsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_PASSCRED,
+            &on as *const libc::c_int as *const libc::c_void,
+            mem::size_of_val(&on) as libc::socklen_t,
+        )
+    };
+    Errno::result(rc).map(|_| ())
+}
+
+// Any process could send to the multicast group, only trust messages sent
+// by root like libudev does. Return None for other messages.
+fn recv_from_root(
+    fd: RawFd,
+    buff: &mut [u8],
+) -> nix::Result<Option<usize>> {
+    let mut iov = libc::iovec {
+        iov_base: buff.as_mut_ptr() as *mut libc::c_void,
+        iov_len: buff.len(),
+    };
+    // Space of a ucred control message, u64 is for alignment of cmsghdr.
+    let mut cmsg_buff = [0u64; 8];
+    let mut mhdr: libc::msghdr = unsafe { mem::zeroed() };
+    mhdr.msg_iov = &mut iov;
+    mhdr.msg_iovlen = 1;
+    mhdr.msg_control = cmsg_buff.as_mut_ptr() as *mut libc::c_void;
+    mhdr.msg_controllen = mem::size_of_val(&cmsg_buff) as _;
+    let len = Errno::result(unsafe { libc::recvmsg(fd, &mut mhdr, 0) })?;
+
+    let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&mhdr) };
+    while !cmsg.is_null() {
+        let hdr = unsafe { &*cmsg };
+        if hdr.cmsg_level == libc::SOL_SOCKET
+            && hdr.cmsg_type == libc::SCM_CREDENTIALS
+        {
+            let cred: libc::ucred = unsafe {
+                ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const _)
+            };
+            if cred.uid == 0 {
+                return Ok(Some(len as usize));
+            }
+            return Ok(None);
+        }
+        cmsg = unsafe { libc::CMSG_NXTHDR(&mhdr, cmsg) };
+    }
+    Ok(None)
+}
+
+// Capture all existing blocks so we could tell what was removed even if it
+// was added before daemon started.
+fn load_blk_infos(sys_root: &SystemRoot) -> HashMap<String, BlkInfo> {
+    let mut ret = HashMap::new();
+    let entries = match fs::read_dir(sys_root.sysfs_path("class/block")) {
+        Ok(e) => e,
+        Err(e) => {
+            println!("udev_collector: Failed to list blocks: {}", e);
+            return ret;
+        }
+    };
+    for entry in entries.flatten() {
+        if let Some(name) = entry.file_name().to_str() {
+            // Use new_skip_extra() as new() will return the holder of disk.
+            if let Ok(blk_info) = BlkInfo::new_skip_extra(name, sys_root) {
+                ret.insert(name.to_string(), blk_info);
+            }
+        }
+    }
+    ret
+}
+
+fn process_uevent(
+    props: &HashMap<String, String>,
+    blk_infos: &mut HashMap<String, BlkInfo>,
+    sys_root: &SystemRoot,
+    hostname: &str,
+    boot_id: &str,
+    sender: &Sender<StorageEvent>,
+) {
+    if props.get("SUBSYSTEM").map(|s| s.as_str()) != Some("block") {
+        return;
+    }
+    let action = match props.get("ACTION") {
+        Some(a) => a.as_str(),
+        None => return,
+    };
+    let devpath = match props.get("DEVPATH") {
+        Some(d) => d,
+        None => return,
+    };
+    let kdev = match devpath.rsplit('/').next() {
+        Some(k) if !k.is_empty() => k,
+        _ => return,
+    };
+
+    let (event_type, verb, blk_info) = match action {
+        "add" | "change" => {
+            match BlkInfo::new_skip_extra(kdev, sys_root) {
+                Ok(b) => {
+                    blk_infos.insert(kdev.to_string(), b);
+                }
+                Err(e) => println!("udev_collector: {}", e),
+            };
+            let blk_info = blk_infos.get(kdev).cloned();
+            match action {
+                "add" => ("BLK_ADDED", "added", blk_info),
+                _ => ("BLK_CHANGED", "changed", blk_info),
+            }
+        }
+        "remove" => ("BLK_REMOVED", "removed", blk_infos.remove(kdev)),
+        _ => return,
+    };
+
+    let mut event = StorageEvent {
+        hostname: hostname.to_string(),
+        severity: LogSeverity::Info,
+        sub_system: StorageSubSystem::Block,
+        timestamp: Local::now()
+            .to_rfc3339_opts(SecondsFormat::Micros, false),
+        event_type: event_type.to_string(),
+        kdev: kdev.to_string(),
+        // Same format as kernel uevent header.
+        raw_msg: format!("{}@{}", action, devpath),
+        ..Default::default()
+    };
+    if let Ok(t) = event.event_type_info() {
+        event.severity = t.severity;
+    }
+    // Sequence number is unique within the boot.
+    event.event_id = StorageEvent::gen_event_id(&format!(
+        "{}/udev/{}",
+        boot_id,
+        props.get("SEQNUM").unwrap_or(&event.timestamp)
+    ));
+    event
+        .extension
+        .insert("devpath".to_string(), devpath.to_string());
+
+    match blk_info {
+        Some(b) => {
+            event.dev_path = b.blk_path.clone();
+            event.dev_wwid = b.wwid.clone();
+            event.owners_wwids = b.owners_wwids();
+            event.owners_paths = b.owners_paths();
+            event
+                .extension
+                .insert("blk_type".to_string(), format!("{}", b.blk_type));
+        }
+        None => {
+            event.dev_path = props
+                .get("DEVNAME")
+                .cloned()
+                .unwrap_or_else(|| format!("/dev/{}", kdev));
+        }
+    }
+    event.msg = format!(
+        "Block {} {}, blk_wwid: '{}'",
+        event.dev_path, verb, event.dev_wwid
+    );
+
+    if let Err(e) = sender.send(event) {
+        println!("udev_collector: Failed to send event: {}", e);
+    }
+}
+
+pub fn new(sender: &Sender<StorageEvent>, sys_root: &SystemRoot) {
+    let fd = match open_udev_monitor() {
+        Ok(fd) => fd,
+        Err(e) => {
+            println!("udev_collector: {}", e);
+            return;
+        }
+    };
+    let hostname = get_hostname();
+    let boot_id = get_boot_id(sys_root);
+    let mut blk_infos = load_blk_infos(sys_root);
+    let mut buff = vec![0u8; RECV_BUFFER_SIZE];
+
+    loop {
+        let len = match recv_from_root(fd, &mut buff) {
+            Ok(Some(l)) => l,
+            Ok(None) => {
+                println!("udev_collector: Ignored uevent not sent by root");
+                continue;
+            }
+            Err(e) => {
+                // ENOBUFS means we are too slow and lost some uevents.
+                println!("udev_collector: Failed to receive uevent: {}", e);
+                continue;
+            }
+        };
+        if let Some(props) = parse_udev_msg(&buff[..len]) {
+            process_uevent(
+                &props,
+                &mut blk_infos,
+                sys_root,
+                &hostname,
+                &boot_id,
+                sender,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn udev_msg(props: &[u8]) -> Vec<u8> {
+        let mut buff = b"libudev\0".to_vec();
+        buff.extend_from_slice(&UDEV_MONITOR_MAGIC.to_be_bytes());
+        buff.extend_from_slice(&40u32.to_ne_bytes());
+        buff.extend_from_slice(&40u32.to_ne_bytes());
+        buff.extend_from_slice(&(props.len() as u32).to_ne_bytes());
+        // Filter hashes and tag bloom, not used.
+        buff.extend_from_slice(&[0u8; 16]);
+        buff.extend_from_slice(props);
+        buff
+    }
+
+    #[test]
+    fn test_parse_udev_msg() {
+        let buff = udev_msg(
+            b"ACTION=add\0DEVPATH=/devices/virtual/block/loop0\0\
+              SUBSYSTEM=block\0",
+        );
+        let props = parse_udev_msg(&buff).unwrap();
+        assert_eq!(props["ACTION"], "add");
+        assert_eq!(props["DEVPATH"], "/devices/virtual/block/loop0");
+        assert_eq!(props["SUBSYSTEM"], "block");
+        assert_eq!(props.len(), 3);
+    }
+
+    #[test]
+    fn test_parse_truncated_udev_msg() {
+        let buff = udev_msg(b"ACTION=add\0SUBSYSTEM=block\0");
+        // Truncated header.
+        assert_eq!(parse_udev_msg(&buff[..UDEV_MONITOR_HEADER_SIZE - 1]), None);
+        assert_eq!(parse_udev_msg(&buff[..8]), None);
+        // Truncated properties.
+        assert_eq!(parse_udev_msg(&buff[..buff.len() - 1]), None);
+        // Raw kernel uevent.
+        assert_eq!(parse_udev_msg(b"add@/devices/virtual/block/loop0\0"), None);
+        assert_eq!(parse_udev_msg(&[]), None);
+    }
+
+    #[test]
+    fn test_recv_from_root() {
+        let mut fds = [0 as libc::c_int; 2];
+        let rc = unsafe {
+            libc::socketpair(
+                libc::AF_UNIX,
+                libc::SOCK_DGRAM,
+                0,
+                fds.as_mut_ptr(),
+            )
+        };
+        assert_eq!(rc, 0);
+        let mut buff = [0u8; 16];
+        let send = |fd: RawFd| unsafe {
+            libc::send(fd, b"uevent".as_ptr() as *const libc::c_void, 6, 0)
+        };
+
+        // No credentials without SO_PASSCRED.
+        assert_eq!(send(fds[0]), 6);
+        assert_eq!(recv_from_root(fds[1], &mut buff).unwrap(), None);
+
+        enable_pass_cred(fds[1]).unwrap();
+        assert_eq!(send(fds[0]), 6);
+        let expected = match unsafe { libc::getuid() } {
+            0 => Some(6),
+            _ => None,
+        };
+        assert_eq!(recv_from_root(fds[1], &mut buff).unwrap(), expected);
+        let _ = close(fds[0]);
+        let _ = close(fds[1]);
+    }
+}
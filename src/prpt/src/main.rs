@@ -1,15 +1,14 @@
 extern crate chrono;
 #[macro_use]
 extern crate clap;
-extern crate nix;
 extern crate peripety;
-extern crate sdjournal;
+extern crate peripetyd;
 
 use chrono::{DateTime, Local, TimeZone};
 use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
-use nix::sys::select::FdSet;
-use peripety::{BlkInfo, LogSeverity, StorageEvent, StorageSubSystem};
-use std::os::unix::io::AsRawFd;
+use peripety::{BlkInfo, BlkNode, EventStore, EventTypeInfo, JournaldStore,
+               JsonFileStore, LogSeverity, StorageEvent, StorageSubSystem,
+               SystemRoot};
 use std::process::exit;
 
 #[derive(Debug, Clone)]
@@ -20,6 +19,7 @@ struct CliOpt {
     since: Option<u64>,
     blk_info: Option<BlkInfo>,
     is_json: bool,
+    store_file: Option<String>,
 }
 
 arg_enum!{
@@ -49,6 +49,7 @@ fn arg_match_to_cliopt(matches: &ArgMatches) -> CliOpt {
         since: None,
         blk_info: None,
         is_json: false,
+        store_file: None,
     };
     if matches.is_present("severity") {
         match matches.value_of("severity") {
@@ -69,6 +70,12 @@ fn arg_match_to_cliopt(matches: &ArgMatches) -> CliOpt {
             Some(ets) => {
                 let mut event_types = Vec::new();
                 for et in ets {
+                    if let Err(e) = EventTypeInfo::get(et) {
+                        quit_with_msg(&format!(
+                            "{}, use 'prpt event-types' to list all",
+                            e
+                        ));
+                    }
                     event_types.push(et.to_string());
                 }
                 ret.event_types = Some(event_types);
@@ -110,11 +117,15 @@ fn arg_match_to_cliopt(matches: &ArgMatches) -> CliOpt {
     }
 
     ret.is_json = matches.is_present("J");
+    ret.store_file = matches.value_of("file").map(|f| f.to_string());
 
     if matches.is_present("blk") {
         match matches.value_of("blk") {
             Some(s) => {
-                ret.blk_info = match BlkInfo::new_skip_extra(&s) {
+                ret.blk_info = match BlkInfo::new_skip_extra(
+                    s,
+                    &SystemRoot::default(),
+                ) {
                     Ok(b) => Some(b),
                     Err(e) => {
                         quit_with_msg(&format!("Invalid blk option: {}", e));
@@ -183,89 +194,104 @@ fn handle_event(event: &StorageEvent, cli_opt: &CliOpt) {
     }
 }
 
+// The `--file` overrides the store configured for peripetyd.
+fn new_store(cli_opt: &CliOpt) -> Box<dyn EventStore> {
+    if let Some(ref f) = cli_opt.store_file {
+        return Box::new(JsonFileStore::new(f));
+    }
+    match peripetyd::daemon_store() {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("Warning: {}, using journald", e);
+            Box::new(JournaldStore::new())
+        }
+    }
+}
+
 fn handle_monitor(cli_opt: &CliOpt) {
     if let Some(_) = cli_opt.since {
         quit_with_msg("`monitor` sub-command does not allow `--since` option");
     }
 
-    let mut journal =
-        sdjournal::Journal::new().expect("Failed to open systemd journal");
-    // We never want to block, so set the timeout to 0
-    journal.timeout_us = 0;
-    // Jump to the end as we cannot annotate old journal entries.
-    journal
-        .seek_tail()
-        .expect("Unable to seek to end of journal!");
-    journal
-        .add_match("IS_PERIPETY=TRUE")
-        .expect("Unable to search peripety journal");
-
+    let mut store = new_store(cli_opt);
     loop {
-        let mut fds = FdSet::new();
-        fds.insert(journal.as_raw_fd());
-        if let Err(e) =
-            nix::sys::select::select(None, Some(&mut fds), None, None, None)
-        {
-            println!(
-                "collector: Failed select against journal fd: {}",
-                e
-            );
-            continue;
-        }
-        if !fds.contains(journal.as_raw_fd()) {
-            continue;
-        }
-        for entry in &mut journal {
-            match entry {
-                Ok(entry) => {
-                    if let Some(j) = entry.get("JSON") {
-                        if let Ok(event) = StorageEvent::from_json_string(j) {
-                            handle_event(&event, &cli_opt);
-                        }
-                    }
-                }
-                Err(e) => {
-                    println!("Error retrieving the journal entry: {:?}", e)
-                }
+        match store.monitor() {
+            Ok(event) => handle_event(&event, cli_opt),
+            Err(e) => {
+                quit_with_msg(&format!("{}", e));
             }
         }
     }
 }
 
 fn handle_query(cli_opt: &CliOpt) {
-    let mut journal =
-        sdjournal::Journal::new().expect("Failed to open systemd journal");
-    // We never want to block, so set the timeout to 0
-    journal.timeout_us = 0;
-    journal
-        .add_match("IS_PERIPETY=TRUE")
-        .expect("Unable to search peripety journal");
-
-    if let Some(since) = cli_opt.since {
-        journal
-            .seek_realtime_usec(since)
-            .expect(&format!(
-                "Unable to seek journal after {}",
-                since
-            ));
-    }
-    for entry in &mut journal {
-        match entry {
-            Ok(entry) => {
-                if let Some(j) = entry.get("JSON") {
-                    match StorageEvent::from_json_string(j) {
-                        Ok(event) => handle_event(&event, &cli_opt),
-                        Err(e) => println!("Error: {}", e),
-                    };
-                }
+    match new_store(cli_opt).query(cli_opt.since) {
+        Ok(events) => {
+            for event in &events {
+                handle_event(event, cli_opt);
             }
-            Err(e) => println!("Error retrieving the journal entry: {:?}", e),
         }
+        Err(e) => quit_with_msg(&format!("{}", e)),
+    }
+}
+
+fn print_blk_node(node: &BlkNode, depth: usize) {
+    println!(
+        "{:indent$}{} ({}) {}",
+        "",
+        node.blk_path,
+        node.blk_type,
+        node.wwid,
+        indent = 15 + depth * 4
+    );
+    for owner in &node.owners {
+        print_blk_node(owner, depth + 1);
+    }
+}
+
+fn print_blk_info(i: &BlkInfo) {
+    println!("blk_path     : {}", i.blk_path);
+    println!("blk_type     : {}", i.blk_type);
+    println!("wwid         : {}", i.wwid);
+    println!("owners       :");
+    for owner in &i.owners {
+        print_blk_node(owner, 0);
+    }
+    println!(
+        "uuid         : {}",
+        i.uuid.clone().unwrap_or_default()
+    );
+    println!(
+        "mount_point  : {}",
+        i.mount_point.clone().unwrap_or_default()
+    );
+    for alias in &i.aliases {
+        println!("alias        : {}", alias);
+    }
+    for m in &i.mounts {
+        println!(
+            "mount        : {} {} {} ({})",
+            m.mount_point,
+            m.fs_type,
+            m.mount_options,
+            if m.read_only { "ro" } else { "rw" }
+        );
+    }
+    let mut keys: Vec<&String> = i.extension.keys().collect();
+    keys.sort();
+    for key in keys {
+        println!("{:<13}: {}", key, i.extension[key]);
+    }
+    for m in &i.md_members {
+        println!(
+            "md_member    : {} {} {}",
+            m.blk_path, m.wwid, m.role
+        );
     }
 }
 
-fn handle_info(blk: &str, is_json: bool) {
-    match BlkInfo::new(blk) {
+fn handle_info(blk: &str, sys_root: &SystemRoot, is_json: bool) {
+    match BlkInfo::new(blk, sys_root) {
         Ok(i) => {
             if is_json {
                 println!(
@@ -274,31 +300,79 @@ fn handle_info(blk: &str, is_json: bool) {
                         .expect("BUG: handle_info()")
                 );
             } else {
-                println!("blk_path     : {}", i.blk_path);
-                println!("blk_type     : {}", i.blk_type);
-                println!("wwid         : {}", i.wwid);
-                println!("owners_wwids : {:?}", i.owners_wwids);
-                println!("owners_paths : {:?}", i.owners_paths);
-                let mut types = Vec::new();
-                for t in i.owners_types {
-                    types.push(format!("{}", t));
-                }
-                println!("owners_types : {:?}", types);
-                println!(
-                    "uuid         : {}",
-                    i.uuid.unwrap_or("".to_string())
-                );
-                println!(
-                    "mount_point  : {}",
-                    i.mount_point.unwrap_or("".to_string())
-                );
+                print_blk_info(&i);
             }
         }
         Err(e) => quit_with_msg(&format!("{}", e)),
     };
 }
 
+fn handle_holders(blk: &str, sys_root: &SystemRoot, is_json: bool) {
+    // Use new_skip_extra() as new() will return the holder of disk.
+    let holders = match BlkInfo::new_skip_extra(blk, sys_root)
+        .and_then(|i| i.holders(sys_root))
+    {
+        Ok(h) => h,
+        Err(e) => {
+            quit_with_msg(&format!("{}", e));
+            return;
+        }
+    };
+    if is_json {
+        let mut jsons = Vec::new();
+        for h in &holders {
+            jsons.push(
+                h.to_json_string_pretty()
+                    .expect("BUG: handle_holders()"),
+            );
+        }
+        println!("[{}]", jsons.join(",\n"));
+    } else {
+        for (index, h) in holders.iter().enumerate() {
+            if index != 0 {
+                println!();
+            }
+            print_blk_info(h);
+        }
+    }
+}
+
+fn handle_event_types(is_json: bool) {
+    // Build-in ones, then the ones of plugin parsers.
+    let mut types: Vec<&EventTypeInfo> = EventTypeInfo::all().iter().collect();
+    types.extend(EventTypeInfo::registered());
+    if is_json {
+        let mut jsons = Vec::new();
+        for t in &types {
+            jsons.push(
+                t.to_json_string_pretty()
+                    .expect("BUG: handle_event_types()"),
+            );
+        }
+        println!("[{}]", jsons.join(",\n"));
+        return;
+    }
+    for (index, t) in types.iter().enumerate() {
+        if index != 0 {
+            println!();
+        }
+        let sub_systems: Vec<String> =
+            t.sub_systems.iter().map(|s| s.to_string()).collect();
+        println!("event_type   : {}", t.name);
+        println!("severity     : {:?}", t.severity);
+        println!("sub_systems  : {}", sub_systems.join(", "));
+        println!("kind         : {}", t.kind);
+        println!("description  : {}", t.description);
+    }
+}
+
 fn main() {
+    // Allow event types of plugin parsers in `--event-type` and
+    // `event-types`.
+    if let Err(e) = peripetyd::register_plugin_event_types() {
+        eprintln!("Warning: {}", e);
+    }
+
     let sev_arg = Arg::from_usage(
         "--severity=[SEVERITY] 'Only show event with equal or higher severity'",
     ).possible_values(&Severity::variants())
@@ -315,10 +389,16 @@ fn main() {
          'Only show event with specific sub-system, argument could be \
          repeated'",
     );
-    let blk_arg =
-        Arg::from_usage("--blk=[BLOCK] 'Only show event with specific block'");
+    let blk_arg = Arg::from_usage(
+        "--blk=[BLOCK] 'Only show event with specific block, could also be \
+         a file or folder path to show event of its backing block'",
+    );
 
     let json_arg = Arg::from_usage("-J 'Use json format'");
+    let file_arg = Arg::from_usage(
+        "--file=[FILE] 'Read events from JSON lines file saved by peripetyd \
+         instead of the store configured in /etc/peripetyd.conf'",
+    );
 
     let matches = App::new("Peripety CLI")
         .version("0.1")
@@ -332,7 +412,8 @@ fn main() {
                 .arg(&sev_arg)
                 .arg(&evt_arg)
                 .arg(&sub_arg)
-                .arg(&blk_arg),
+                .arg(&blk_arg)
+                .arg(&file_arg),
         )
         .subcommand(
             SubCommand::with_name("query")
@@ -342,6 +423,7 @@ fn main() {
                 .arg(&evt_arg)
                 .arg(&sub_arg)
                 .arg(&blk_arg)
+                .arg(&file_arg)
                 .arg(Arg::from_usage(
                     "--since [SINCE] \
                      'Only show event on or newer than the specified \
@@ -354,8 +436,25 @@ fn main() {
                 .arg(Arg::from_usage(
                     "<blk> 'Block to query, could be \'major:minor\', \
                      block name, block path, symbolic link to block, \
-                     uuid, wwid, or fs mount point'",
+                     uuid, wwid, NVMe controller and namespace ID like \
+                     \'nvme0:1\', link name in /dev/disk/by-id or \
+                     /dev/disk/by-path, LVM \'vg/lv\', \'LABEL=\', \
+                     \'UUID=\', \'PARTUUID=\', \'PARTLABEL=\', fs mount \
+                     point or any file or folder path'",
                 ))
+                .arg(Arg::from_usage(
+                    "--sys-root=[DIR] 'Query against sysfs, devfs and procfs \
+                     captured in DIR/sys, DIR/dev and DIR/proc'",
+                ))
+                .arg(Arg::from_usage(
+                    "--holders 'Show all blocks and file systems stacked on \
+                     top of specified block'",
+                ))
+                .arg(&json_arg),
+        )
+        .subcommand(
+            SubCommand::with_name("event-types")
+                .about("List all event types")
                 .arg(&json_arg),
         )
         .get_matches();
@@ -372,10 +471,25 @@ fn main() {
         exit(0);
     }
 
+    if let Some(matches) = matches.subcommand_matches("event-types") {
+        handle_event_types(matches.is_present("J"));
+        exit(0);
+    }
+
     if let Some(matches) = matches.subcommand_matches("info") {
         let is_json = matches.is_present("J");
+        let sys_root = match matches.value_of("sys-root") {
+            Some(d) => SystemRoot::new(d),
+            None => SystemRoot::default(),
+        };
         match matches.value_of("blk") {
-            Some(s) => handle_info(s, is_json),
+            Some(s) => {
+                if matches.is_present("holders") {
+                    handle_holders(s, &sys_root, is_json)
+                } else {
+                    handle_info(s, &sys_root, is_json)
+                }
+            }
             None => quit_with_msg("Invalid 'blk' argument"),
         }
         exit(0);
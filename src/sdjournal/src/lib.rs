@@ -139,6 +139,8 @@ extern "C" {
     ) -> c_int;
     fn sd_journal_get_realtime_usec(j: *mut SdJournal, usec: *mut u64)
         -> c_int;
+    fn sd_journal_get_cursor(j: *mut SdJournal, cursor: *mut *mut c_char)
+        -> c_int;
 
     fn sd_journal_get_fd(j: *mut SdJournal) -> c_int;
 
@@ -310,6 +312,20 @@ impl Journal {
             )));
         }
 
+        // The cursor uniquely identifies the entry, even after reboot.
+        let mut cursor: *mut c_char = std::ptr::null_mut();
+        let rc = unsafe { sd_journal_get_cursor(self.handle, &mut cursor) };
+        if rc == 0 {
+            let bytes = unsafe { CStr::from_ptr(cursor) }.to_bytes().to_vec();
+            unsafe { libc::free(cursor as *mut c_void) };
+            result.insert("__CURSOR".to_string(), String::from_utf8(bytes)?);
+        } else {
+            return Err(SdJournalError::CError(ClibraryError::new(
+                String::from("Error on sd_journal_get_cursor"),
+                rc,
+            )));
+        }
+
         Ok(result)
     }
 